@@ -0,0 +1,79 @@
+use std::fs;
+
+use indicatif::HumanBytes;
+
+use crate::local::{self, TempKind};
+use crate::options::Options;
+use crate::{CliError, Outcome};
+
+/// run 删除下载留下的分片, 布局记录, bitmap 和 .partial 文件, 下载运行时不要清理
+/// 分片上传的断点记录只在 uploads 为 true 时删除, 删除后上传从头开始
+pub fn run(options: &Options, dry_run: bool, uploads: bool) -> Result<Outcome, CliError> {
+    let mut removed_count = 0;
+    let mut removed_bytes = 0;
+    let mut failed_count = 0;
+    for (kind, path) in local::temp_files(options) {
+        if kind == TempKind::Upload && !uploads {
+            continue;
+        }
+        let size = fs::metadata(&path).map_or(0, |metadata| metadata.len());
+        if dry_run {
+            println!("clean, {}, {}", path.display(), HumanBytes(size));
+        } else if let Err(err) = fs::remove_file(&path) {
+            failed_count += 1;
+            println!("clean, {}, {}", path.display(), err);
+            continue;
+        }
+        removed_count += 1;
+        removed_bytes += size;
+    }
+
+    let action = if dry_run { "to remove" } else { "removed" };
+    println!(
+        "clean, {}: {} ({}), failed: {}",
+        action,
+        removed_count,
+        HumanBytes(removed_bytes),
+        failed_count
+    );
+    if failed_count > 0 {
+        Ok(Outcome::Failed)
+    } else {
+        Ok(Outcome::Success)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::tests::{options, use_loc};
+
+    #[test]
+    fn test_clean() {
+        let use_loc = use_loc("clean");
+        let options = options(&use_loc);
+        let temp_path = options.temp_path();
+        let data_path = options.data_path();
+        fs::create_dir_all(&temp_path).unwrap();
+        fs::create_dir_all(&data_path).unwrap();
+        let range_path = temp_path.join("0__5242880__0cc175b9__a.bin");
+        let upload_path = temp_path.join("0cc175b9__b.bin.upload.json");
+        let user_path = data_path.join("user.partial");
+        for path in [&range_path, &upload_path, &user_path] {
+            fs::write(path, "data").unwrap();
+        }
+
+        assert_eq!(run(&options, true, false).unwrap(), Outcome::Success);
+        assert!(range_path.exists());
+
+        // 上传记录只在 uploads 时删除, 没有 bitmap 的 .partial 不删除
+        assert_eq!(run(&options, false, false).unwrap(), Outcome::Success);
+        assert!(!range_path.exists());
+        assert!(upload_path.exists());
+        assert_eq!(run(&options, false, true).unwrap(), Outcome::Success);
+        assert!(!upload_path.exists());
+        assert!(user_path.exists());
+
+        fs::remove_dir_all(&use_loc).unwrap();
+    }
+}
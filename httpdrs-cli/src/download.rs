@@ -0,0 +1,29 @@
+use httpdrs::logger;
+use httpdrs::read::session::{Session, SessionConfig};
+
+use crate::options::Options;
+use crate::{CliError, Outcome};
+
+/// run 下载清单中的文件, 已经完成的文件跳过, 有文件失败时返回 Failed
+/// 失败的文件记录在 {use_loc}/report 中, 再次运行时只下载未完成的部分
+pub fn run(options: &Options, metas: &[String]) -> Result<Outcome, CliError> {
+    let manifests = options.manifests(metas)?;
+    let config = SessionConfig::from_config(&options.config, None).map_err(CliError::Config)?;
+
+    logger::try_logger_init(format!("{}/logs", options.config.use_loc).as_str());
+    let session = Session::start(config);
+    for meta in manifests {
+        if session.push_meta(meta).is_err() {
+            break;
+        }
+    }
+    session.close();
+    session.wait()?;
+
+    let stats = session.stats();
+    if stats.uncompleted_count + stats.rejected_count > 0 {
+        Ok(Outcome::Failed)
+    } else {
+        Ok(Outcome::Success)
+    }
+}
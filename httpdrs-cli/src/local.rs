@@ -0,0 +1,225 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use httpdrs::core::httpd::local_hash_name;
+use httpdrs::core::read::reader::MetaRow;
+use httpdrs::read::manifest;
+
+use crate::CliError;
+use crate::options::Options;
+
+/// LocalState 清单中的文件在 data 目录中的状态
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LocalState {
+    Complete,         // 大小和清单相同
+    Mismatch(u64),    // 存在但大小不同, 值为本地大小
+    Missing,          // 不存在
+    Rejected(String), // 签名无效或路径不安全, 下载时会记为失败
+}
+
+/// LocalFile 清单中的一个文件
+#[derive(Debug)]
+pub struct LocalFile {
+    pub meta: String,
+    pub line: Option<u64>,
+    pub row: MetaRow,
+    pub path: Option<PathBuf>, // data 目录中的绝对路径
+    pub state: LocalState,
+}
+
+impl LocalFile {
+    /// name 用于输出的名字, 路径无效时使用清单和行号
+    pub fn name(&self) -> String {
+        match &self.path {
+            Some(path) => path.display().to_string(),
+            None => format!("{}:{}", self.meta, self.line.unwrap_or(0)),
+        }
+    }
+}
+
+/// scan 读取清单并检查本地文件, 不访问网络
+pub fn scan(options: &Options, metas: &[String]) -> Result<Vec<LocalFile>, CliError> {
+    let signer = options.signer()?;
+    let meta_path = options.meta_path();
+    let data_path = options.data_path();
+
+    let mut files = Vec::new();
+    for meta in options.manifests(metas)? {
+        let manifest_path = meta_path.join(&meta);
+        let rows = manifest::read_manifest(
+            &manifest_path.to_string_lossy(),
+            &signer,
+            options.config.path_policy,
+        )?;
+        for file in rows {
+            let (path, state) = match file.path {
+                Ok(relative_path) => {
+                    let path = data_path.join(relative_path);
+                    let state = match fs::metadata(&path) {
+                        Ok(metadata) if metadata.len() == file.row.size => LocalState::Complete,
+                        Ok(metadata) => LocalState::Mismatch(metadata.len()),
+                        Err(_) => LocalState::Missing,
+                    };
+                    (Some(path), state)
+                }
+                Err(err) => (None, LocalState::Rejected(err.to_string())),
+            };
+            files.push(LocalFile {
+                meta: meta.clone(),
+                line: file.line,
+                row: file.row,
+                path,
+                state,
+            });
+        }
+    }
+    Ok(files)
+}
+
+/// walk 目录下的所有文件, 目录不存在时为空
+pub fn walk(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            match entry.file_type() {
+                Ok(file_type) if file_type.is_dir() => dirs.push(entry.path()),
+                Ok(file_type) if file_type.is_file() => files.push(entry.path()),
+                _ => {}
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/// TempKind 下载和上传留下的临时文件
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TempKind {
+    Range,   // temp 下的分片: {idx}__{chunk_size}__{hash}__{name}.bin
+    Layout,  // temp 下分片下载的布局记录: {hash}__{name}.layout.json
+    Bitmap,  // temp 下预分配下载的分片记录: {hash}__{name}.bitmap
+    Upload,  // temp 下分片上传的断点记录: {hash}__{name}.upload.json
+    Partial, // data 下预分配下载未完成的文件: {name}.partial, temp 下有对应的 bitmap
+}
+
+/// temp_files 按类型列出临时文件, 不属于以上类型的文件忽略
+/// data 下的 .partial 可能是用户自己的文件, 只有 temp 下有对应的 bitmap 时才列出
+pub fn temp_files(options: &Options) -> Vec<(TempKind, PathBuf)> {
+    let temp_path = options.temp_path();
+    let mut files = Vec::new();
+    for path in walk(&temp_path) {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let kind = if name.ends_with(".upload.json") {
+            TempKind::Upload
+        } else if name.ends_with(".layout.json") {
+            TempKind::Layout
+        } else if name.ends_with(".bitmap") {
+            TempKind::Bitmap
+        } else if name.ends_with(".bin") && name.split("__").count() >= 3 {
+            TempKind::Range
+        } else {
+            continue;
+        };
+        files.push((kind, path));
+    }
+    for path in walk(&options.data_path()) {
+        if path.extension().is_some_and(|extn| extn == "partial")
+            && partial_bitmap(&path, &temp_path).exists()
+        {
+            files.push((TempKind::Partial, path));
+        }
+    }
+    files
+}
+
+/// partial_bitmap 预分配下载的 .partial 对应的 bitmap, 和下载时的 local_bitmap_path 相同
+fn partial_bitmap(partial_path: &Path, temp_path: &Path) -> PathBuf {
+    let (file_hash, file_name) = local_hash_name(&partial_path.with_extension(""));
+    temp_path.join(format!("{}__{}.bitmap", file_hash, file_name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::tests::{options, use_loc};
+
+    #[test]
+    fn test_temp_files() {
+        let use_loc = use_loc("temp");
+        let options = options(&use_loc);
+        let temp_path = options.temp_path();
+        let data_path = options.data_path().join("host/ds");
+        fs::create_dir_all(&temp_path).unwrap();
+        fs::create_dir_all(&data_path).unwrap();
+
+        // 下载中的 .partial 在 temp 下有 bitmap, 用户自己的 .partial 没有
+        let partial_path = data_path.join("a.bin.partial");
+        let bitmap_path = partial_bitmap(&partial_path, &temp_path);
+        for path in [
+            temp_path.join("0__5242880__0cc175b9__a.bin"),
+            temp_path.join("0cc175b9__a.bin.layout.json"),
+            temp_path.join("0cc175b9__b.bin.upload.json"),
+            temp_path.join("notes.txt"),
+            bitmap_path.clone(),
+            partial_path.clone(),
+            data_path.join("user.partial"),
+        ] {
+            fs::write(path, "").unwrap();
+        }
+
+        let mut files = temp_files(&options);
+        files.sort_by(|a, b| a.1.cmp(&b.1));
+        let mut expected = vec![
+            (
+                TempKind::Range,
+                temp_path.join("0__5242880__0cc175b9__a.bin"),
+            ),
+            (
+                TempKind::Layout,
+                temp_path.join("0cc175b9__a.bin.layout.json"),
+            ),
+            (TempKind::Bitmap, bitmap_path),
+            (
+                TempKind::Upload,
+                temp_path.join("0cc175b9__b.bin.upload.json"),
+            ),
+            (TempKind::Partial, partial_path),
+        ];
+        expected.sort_by(|a, b| a.1.cmp(&b.1));
+        assert_eq!(files, expected);
+
+        fs::remove_dir_all(&use_loc).unwrap();
+    }
+
+    #[test]
+    fn test_scan() {
+        let use_loc = use_loc("scan");
+        let options = options(&use_loc);
+        let data_path = options.data_path().join("host/ds");
+        fs::create_dir_all(options.meta_path()).unwrap();
+        fs::create_dir_all(&data_path).unwrap();
+        fs::write(data_path.join("a.bin"), "abc").unwrap();
+        fs::write(data_path.join("b.bin"), "ab").unwrap();
+        fs::write(
+            options.meta_path().join("m0.bin"),
+            "sign,size\nhttp://host/ds/a.bin,3\nhttp://host/ds/b.bin,5\nhttp://host/ds/c.bin,1\nnot-a-url,1\n",
+        )
+        .unwrap();
+
+        let files = scan(&options, &[]).unwrap();
+        let states: Vec<_> = files.iter().map(|file| file.state.clone()).collect();
+        assert_eq!(states.len(), 4);
+        assert_eq!(states[0], LocalState::Complete);
+        assert_eq!(states[1], LocalState::Mismatch(2));
+        assert_eq!(states[2], LocalState::Missing);
+        assert!(matches!(states[3], LocalState::Rejected(_)));
+        assert_eq!(files[0].path, Some(data_path.join("a.bin")));
+        assert!(files[3].name().starts_with("m0.bin:"));
+
+        fs::remove_dir_all(&use_loc).unwrap();
+    }
+}
@@ -0,0 +1,169 @@
+// Copyright 2025 BAAI, Inc.
+
+//! # httpdrs
+//!
+//! 不依赖 Python 的命令行工具, 参数和 multi_read/multi_write 相同
+//!
+//! 参数从高到低: 命令行, IHTTPD_* 环境变量, --config 或 IHTTPD_CONFIG 指定的文件,
+//! {use_loc}/ihttpd.toml, ~/.config/ihttpd/config.toml, 默认值
+//!
+//! 退出码: 0 成功, 1 有文件失败, 2 参数或配置错误, 3 运行错误(签名, 网络, 本地文件), 130 被中断
+
+mod clean;
+mod download;
+mod local;
+mod options;
+mod plan;
+mod status;
+mod upload;
+mod verify;
+
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand};
+
+use httpdrs::core::httpd::HttpdError;
+
+use crate::options::{CommonArgs, Options};
+
+#[derive(Parser, Debug)]
+#[command(name = "httpdrs", version, about = "ihttpd 命令行工具")]
+struct Cli {
+    #[arg(
+        long,
+        global = true,
+        help = "toml config file, overrides ihttpd.toml and ~/.config/ihttpd/config.toml"
+    )]
+    config: Option<PathBuf>,
+
+    #[command(flatten)]
+    options: CommonArgs,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// download files in the manifests under {use_loc}/meta
+    Download {
+        #[arg(help = "manifest names under {use_loc}/meta, default all")]
+        metas: Vec<String>,
+    },
+    /// upload files in the manifests under {use_loc}/upload
+    Upload,
+    /// verify size and digest of downloaded files
+    Verify {
+        #[arg(help = "manifest names under {use_loc}/meta, default all")]
+        metas: Vec<String>,
+        #[arg(long, help = "check size only, skip md5/sha256/crc32c")]
+        no_digest: bool,
+    },
+    /// show local files, temp files and the last report
+    Status {
+        #[arg(help = "manifest names under {use_loc}/meta, default all")]
+        metas: Vec<String>,
+    },
+    /// remove ranges, layouts, bitmaps and .partial files left by downloads
+    Clean {
+        #[arg(long, help = "list files without removing them")]
+        dry_run: bool,
+        #[arg(long, help = "also remove multipart upload records")]
+        uploads: bool,
+    },
+    /// list files to download without network access
+    Plan {
+        #[arg(help = "manifest names under {use_loc}/meta, default all")]
+        metas: Vec<String>,
+    },
+}
+
+/// Outcome 命令完成后的结果, 有文件失败时退出码为 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Success,
+    Failed,
+}
+
+/// CliError 命令没有完成的原因
+#[derive(Debug)]
+pub enum CliError {
+    Config(String),    // 参数或配置文件错误
+    Httpd(HttpdError), // 签名, 网络, 本地文件等运行错误
+}
+
+impl CliError {
+    fn exit_code(&self) -> u8 {
+        match self {
+            CliError::Config(_) => 2,
+            CliError::Httpd(HttpdError::Cancelled) => 130,
+            CliError::Httpd(_) => 3,
+        }
+    }
+}
+
+impl Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CliError::Config(message) => write!(f, "{}", message),
+            CliError::Httpd(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<HttpdError> for CliError {
+    fn from(err: HttpdError) -> Self {
+        CliError::Httpd(err)
+    }
+}
+
+impl From<std::io::Error> for CliError {
+    fn from(err: std::io::Error) -> Self {
+        CliError::Httpd(err.into())
+    }
+}
+
+fn run(cli: Cli) -> Result<Outcome, CliError> {
+    let options = Options::load(cli.options, cli.config.as_deref())?;
+
+    match cli.command {
+        Command::Download { metas } => download::run(&options, &metas),
+        Command::Upload => upload::run(&options),
+        Command::Verify { metas, no_digest } => verify::run(&options, &metas, !no_digest),
+        Command::Status { metas } => status::run(&options, &metas),
+        Command::Clean { dry_run, uploads } => clean::run(&options, dry_run, uploads),
+        Command::Plan { metas } => plan::run(&options, &metas),
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli) {
+        Ok(Outcome::Success) => ExitCode::SUCCESS,
+        Ok(Outcome::Failed) => ExitCode::from(1),
+        Err(err) => {
+            eprintln!("httpdrs: {}", err);
+            ExitCode::from(err.exit_code())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_code() {
+        assert_eq!(
+            CliError::Config("unknown signer".to_string()).exit_code(),
+            2
+        );
+        assert_eq!(CliError::Httpd(HttpdError::Cancelled).exit_code(), 130);
+        let err = HttpdError::Network("timeout".to_string());
+        assert_eq!(CliError::Httpd(err).exit_code(), 3);
+        let err = std::io::Error::from(std::io::ErrorKind::NotFound);
+        assert_eq!(CliError::from(err).exit_code(), 3);
+    }
+}
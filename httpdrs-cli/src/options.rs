@@ -0,0 +1,221 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use clap::Args;
+
+use httpdrs::config::{Config, ConfigLayer};
+use httpdrs::core::httpd::{Signer, SignerConfig, TokenVerifier};
+
+use crate::CliError;
+
+/// CommonArgs 所有子命令共用的参数, 优先于环境变量和配置文件
+/// 配置文件中的名字相同, 使用下划线, 例如 max_parallel = 64
+#[derive(Args, Debug, Clone, Default)]
+pub struct CommonArgs {
+    #[arg(
+        long,
+        global = true,
+        help = "working directory with meta, data and temp, default ."
+    )]
+    pub use_loc: Option<String>,
+    #[arg(long, global = true, help = "presign api, signer http")]
+    pub presign_api: Option<String>,
+    #[arg(long, global = true, help = "network, default private")]
+    pub network: Option<String>,
+    #[arg(
+        long,
+        global = true,
+        visible_alias = "bandwidth",
+        help = "bandwidth MB/s, default 100"
+    )]
+    pub max_bandwidth: Option<u64>,
+    #[arg(
+        long,
+        global = true,
+        visible_alias = "parallel",
+        help = "max parallel, default 200"
+    )]
+    pub max_parallel: Option<u64>,
+    #[arg(
+        long,
+        global = true,
+        help = "min parallel, downloads adjust between min and max, default 8"
+    )]
+    pub min_parallel: Option<u64>,
+    #[arg(long, global = true, num_args = 0..=1, default_missing_value = "true", help = "preallocate, write ranges into data directly")]
+    pub preallocate: Option<bool>,
+    #[arg(
+        long,
+        global = true,
+        help = "signer: http, s3 or passthrough, default http"
+    )]
+    pub signer: Option<String>,
+    #[arg(long, global = true, help = "s3 access key, signer s3")]
+    pub s3_access_key: Option<String>,
+    #[arg(long, global = true, help = "s3 secret key, signer s3")]
+    pub s3_secret_key: Option<String>,
+    #[arg(long, global = true, help = "s3 region, signer s3, default us-east-1")]
+    pub s3_region: Option<String>,
+    #[arg(long, global = true, help = "s3 endpoint, signer s3")]
+    pub s3_endpoint: Option<String>,
+    #[arg(long, global = true, num_args = 0..=1, default_missing_value = "true", help = "s3 virtual-hosted style, signer s3")]
+    pub s3_virtual_host: Option<bool>,
+    #[arg(long, global = true, help = "verify sign with hmac secret")]
+    pub jwt_secret: Option<String>,
+    #[arg(long, global = true, help = "verify sign with jwks file")]
+    pub jwks_path: Option<String>,
+    #[arg(
+        long,
+        global = true,
+        help = "unsafe local path policy: reject or sanitize, default reject"
+    )]
+    pub path_policy: Option<String>,
+    #[arg(
+        long,
+        global = true,
+        help = "max attempts per range and presign request"
+    )]
+    pub range_attempts: Option<u32>,
+    #[arg(
+        long,
+        global = true,
+        help = "max attempts per file, failed ranges are requeued"
+    )]
+    pub file_attempts: Option<u32>,
+    #[arg(long, global = true, help = "retryable http status, e.g. 5xx,408,429")]
+    pub retry_status: Option<String>,
+}
+
+impl CommonArgs {
+    fn into_layer(self) -> ConfigLayer {
+        ConfigLayer {
+            use_loc: self.use_loc,
+            presign_api: self.presign_api,
+            network: self.network,
+            max_bandwidth: self.max_bandwidth,
+            max_parallel: self.max_parallel,
+            min_parallel: self.min_parallel,
+            preallocate: self.preallocate,
+            signer: self.signer,
+            s3_access_key: self.s3_access_key,
+            s3_secret_key: self.s3_secret_key,
+            s3_region: self.s3_region,
+            s3_endpoint: self.s3_endpoint,
+            s3_virtual_host: self.s3_virtual_host,
+            jwt_secret: self.jwt_secret,
+            jwks_path: self.jwks_path,
+            path_policy: self.path_policy,
+            range_attempts: self.range_attempts,
+            file_attempts: self.file_attempts,
+            retry_status: self.retry_status,
+            ..Default::default()
+        }
+    }
+}
+
+/// Options 合并命令行参数, 环境变量和配置文件后的参数
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub config: Config,
+}
+
+impl Options {
+    /// load config_file 是 --config 指定的配置文件, 优先于默认位置的配置文件
+    pub fn load(args: CommonArgs, config_file: Option<&Path>) -> Result<Options, CliError> {
+        let config = Config::load(args.into_layer(), config_file).map_err(CliError::Config)?;
+        Ok(Options { config })
+    }
+
+    /// signer_config 下载和上传运行时使用的签名配置
+    pub fn signer_config(&self) -> Result<(SignerConfig, TokenVerifier), CliError> {
+        self.config.signer_config().map_err(CliError::Config)
+    }
+
+    /// signer 只用来解析清单, 不访问签名服务
+    pub fn signer(&self) -> Result<Arc<dyn Signer>, CliError> {
+        let (signer, verifier) = self.signer_config()?;
+        Ok(signer.build(verifier, self.config.retry.clone()))
+    }
+
+    pub fn meta_path(&self) -> PathBuf {
+        Path::new(&self.config.use_loc).join("meta")
+    }
+
+    pub fn data_path(&self) -> PathBuf {
+        Path::new(&self.config.use_loc).join("data")
+    }
+
+    pub fn temp_path(&self) -> PathBuf {
+        Path::new(&self.config.use_loc).join("temp")
+    }
+
+    pub fn report_path(&self) -> PathBuf {
+        Path::new(&self.config.use_loc).join("report")
+    }
+
+    /// manifests {use_loc}/meta 下的清单文件名, 未指定时返回所有 *.bin
+    pub fn manifests(&self, metas: &[String]) -> Result<Vec<String>, CliError> {
+        if !metas.is_empty() {
+            return Ok(metas.to_vec());
+        }
+        let meta_path = self.meta_path();
+        let mut names = fs::read_dir(&meta_path)
+            .map_err(|err| CliError::Config(format!("read {}: {}", meta_path.display(), err)))?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_file())
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .filter(|name| name.ends_with(".bin"))
+            .collect::<Vec<_>>();
+        names.sort();
+        Ok(names)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// options 使用 use_loc 和 passthrough 签名, 不读取环境变量和配置文件
+    pub(crate) fn options(use_loc: &Path) -> Options {
+        let config = Config::resolve(ConfigLayer {
+            use_loc: Some(use_loc.to_string_lossy().into_owned()),
+            signer: Some("passthrough".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        Options { config }
+    }
+
+    /// use_loc 测试使用的空目录
+    pub(crate) fn use_loc(name: &str) -> PathBuf {
+        let use_loc =
+            std::env::temp_dir().join(format!("httpdrs-cli-{}-{}", name, std::process::id()));
+        fs::remove_dir_all(&use_loc).unwrap_or(());
+        fs::create_dir_all(&use_loc).unwrap();
+        use_loc
+    }
+
+    #[test]
+    fn test_manifests() {
+        let use_loc = use_loc("manifests");
+        let options = options(&use_loc);
+
+        // meta 目录不存在时是配置错误
+        assert!(matches!(options.manifests(&[]), Err(CliError::Config(_))));
+
+        // 只列出 meta 下的 *.bin 文件, 按名字排序
+        let meta_path = options.meta_path();
+        fs::create_dir_all(meta_path.join("d.bin")).unwrap();
+        for name in ["b.bin", "a.bin", "c.txt"] {
+            fs::write(meta_path.join(name), "sign,size\n").unwrap();
+        }
+        assert_eq!(options.manifests(&[]).unwrap(), ["a.bin", "b.bin"]);
+
+        // 指定的清单原样返回
+        let metas = vec!["c.txt".to_string()];
+        assert_eq!(options.manifests(&metas).unwrap(), metas);
+
+        fs::remove_dir_all(&use_loc).unwrap();
+    }
+}
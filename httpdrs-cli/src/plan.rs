@@ -0,0 +1,85 @@
+use indicatif::HumanBytes;
+
+use crate::local::{self, LocalState};
+use crate::options::Options;
+use crate::{CliError, Outcome};
+
+/// run 列出下载时需要处理的文件, 不访问网络也不修改本地文件
+/// 本地大小和清单相同的文件下载时只校验, 不会重新下载
+pub fn run(options: &Options, metas: &[String]) -> Result<Outcome, CliError> {
+    let files = local::scan(options, metas)?;
+
+    let (mut download_count, mut download_bytes) = (0, 0);
+    let (mut skip_count, mut skip_bytes) = (0, 0);
+    let mut rejected_count = 0;
+    for file in &files {
+        match &file.state {
+            LocalState::Complete => {
+                skip_count += 1;
+                skip_bytes += file.row.size;
+            }
+            LocalState::Missing | LocalState::Mismatch(_) => {
+                download_count += 1;
+                download_bytes += file.row.size;
+                println!("download, {}, {}", file.name(), HumanBytes(file.row.size));
+            }
+            LocalState::Rejected(err) => {
+                rejected_count += 1;
+                println!("reject, {}, {}", file.name(), err);
+            }
+        }
+    }
+    println!(
+        "plan, files: {}, download: {} ({}), exists: {} ({}), rejected: {}",
+        files.len(),
+        download_count,
+        HumanBytes(download_bytes),
+        skip_count,
+        HumanBytes(skip_bytes),
+        rejected_count
+    );
+    Ok(Outcome::Success)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::options::tests::{options, use_loc};
+    use crate::status;
+
+    #[test]
+    fn test_plan_status() {
+        let use_loc = use_loc("plan");
+        let options = options(&use_loc);
+
+        // 没有清单时是配置错误
+        assert!(matches!(run(&options, &[]), Err(CliError::Config(_))));
+        assert!(matches!(
+            status::run(&options, &[]),
+            Err(CliError::Config(_))
+        ));
+
+        fs::create_dir_all(options.meta_path()).unwrap();
+        fs::create_dir_all(options.report_path()).unwrap();
+        fs::write(
+            options.meta_path().join("m0.bin"),
+            "sign,size\nhttp://host/ds/a.bin,3\nnot-a-url,1\n",
+        )
+        .unwrap();
+        fs::write(
+            options.report_path().join("report.jsonl"),
+            "{\"status\":\"failed\"}\nnot json\n",
+        )
+        .unwrap();
+        assert_eq!(run(&options, &[]).unwrap(), Outcome::Success);
+        assert_eq!(status::run(&options, &[]).unwrap(), Outcome::Success);
+
+        // 指定的清单不存在时返回错误
+        let metas = vec!["m1.bin".to_string()];
+        assert!(run(&options, &metas).is_err());
+
+        fs::remove_dir_all(&use_loc).unwrap();
+    }
+}
@@ -0,0 +1,70 @@
+use std::collections::BTreeMap;
+use std::fs;
+
+use indicatif::HumanBytes;
+
+use crate::local::{self, LocalState, TempKind};
+use crate::options::Options;
+use crate::{CliError, Outcome};
+
+/// run 输出清单, 本地文件, 临时文件和上次下载报告的统计, 不访问网络
+pub fn run(options: &Options, metas: &[String]) -> Result<Outcome, CliError> {
+    let files = local::scan(options, metas)?;
+
+    let mut states: BTreeMap<&str, (u64, u64)> = BTreeMap::new();
+    let mut total_bytes = 0;
+    for file in &files {
+        let state = match file.state {
+            LocalState::Complete => "complete",
+            LocalState::Mismatch(_) => "partial",
+            LocalState::Missing => "missing",
+            LocalState::Rejected(_) => "rejected",
+        };
+        let entry = states.entry(state).or_default();
+        entry.0 += 1;
+        entry.1 += file.row.size;
+        total_bytes += file.row.size;
+    }
+    println!(
+        "manifest, files: {} ({})",
+        files.len(),
+        HumanBytes(total_bytes)
+    );
+    for (state, (count, bytes)) in &states {
+        println!("local, {}: {} ({})", state, count, HumanBytes(*bytes));
+    }
+
+    let mut temps: BTreeMap<&str, (u64, u64)> = BTreeMap::new();
+    for (kind, path) in local::temp_files(options) {
+        let kind = match kind {
+            TempKind::Range => "ranges",
+            TempKind::Layout => "layouts",
+            TempKind::Bitmap => "bitmaps",
+            TempKind::Upload => "uploads",
+            TempKind::Partial => "partial",
+        };
+        let entry = temps.entry(kind).or_default();
+        entry.0 += 1;
+        entry.1 += fs::metadata(&path).map_or(0, |metadata| metadata.len());
+    }
+    for (kind, (count, bytes)) in &temps {
+        println!("temp, {}: {} ({})", kind, count, HumanBytes(*bytes));
+    }
+
+    // 上次下载的结果, 每行一个文件
+    let report_path = options.report_path().join("report.jsonl");
+    if let Ok(content) = fs::read_to_string(&report_path) {
+        let mut reports: BTreeMap<String, u64> = BTreeMap::new();
+        for line in content.lines() {
+            let status = serde_json::from_str::<serde_json::Value>(line)
+                .ok()
+                .and_then(|value| value["status"].as_str().map(str::to_string))
+                .unwrap_or("unknown".to_string());
+            *reports.entry(status).or_default() += 1;
+        }
+        for (status, count) in &reports {
+            println!("report, {}: {}", status, count);
+        }
+    }
+    Ok(Outcome::Success)
+}
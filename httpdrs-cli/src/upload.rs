@@ -0,0 +1,30 @@
+use httpdrs::logger;
+use httpdrs::write::runtime;
+
+use crate::options::Options;
+use crate::{CliError, Outcome};
+
+/// run 上传 {use_loc}/upload 下清单中的文件, 有文件失败时返回 Failed
+pub fn run(options: &Options) -> Result<Outcome, CliError> {
+    let (signer, verifier) = options.signer_config()?;
+
+    let config = &options.config;
+
+    logger::try_logger_init(format!("{}/logs", config.use_loc).as_str());
+    let stats = runtime::start_multi_thread(
+        config.max_bandwidth,
+        config.max_parallel,
+        config.use_loc.clone(),
+        signer,
+        verifier,
+        config.path_policy,
+        config.retry.clone(),
+        config.upload.clone(),
+    )?;
+
+    if stats.uncompleted_count + stats.rejected_count > 0 {
+        Ok(Outcome::Failed)
+    } else {
+        Ok(Outcome::Success)
+    }
+}
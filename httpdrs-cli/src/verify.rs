@@ -0,0 +1,72 @@
+use std::thread;
+
+use futures::StreamExt;
+use indicatif::HumanBytes;
+
+use httpdrs::core::verify;
+
+use crate::local::{self, LocalState};
+use crate::options::Options;
+use crate::{CliError, Outcome};
+
+/// run 检查 data 目录中的文件和清单是否一致, 不访问网络
+/// digest 为 true 时同时校验清单中的 md5/sha256/crc32c
+pub fn run(options: &Options, metas: &[String], digest: bool) -> Result<Outcome, CliError> {
+    let files = local::scan(options, metas)?;
+
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+    let parallel = thread::available_parallelism().map_or(4, |n| n.get());
+
+    let results = rt.block_on(
+        futures::stream::iter(files.iter().map(|file| async move {
+            let problem = match &file.state {
+                LocalState::Complete if digest => match &file.path {
+                    Some(path) => verify::verify_file(path.clone(), file.row.digest.clone())
+                        .await
+                        .err(),
+                    None => None,
+                },
+                LocalState::Complete => None,
+                LocalState::Mismatch(size) => Some(format!(
+                    "size mismatch, local: {}, expect: {}",
+                    size, file.row.size
+                )),
+                LocalState::Missing => Some("missing".to_string()),
+                LocalState::Rejected(err) => Some(format!("rejected, {}", err)),
+            };
+            (file, problem)
+        }))
+        .buffer_unordered(parallel)
+        .collect::<Vec<_>>(),
+    );
+
+    let mut verified_count = 0;
+    let mut verified_bytes = 0;
+    let mut failed_count = 0;
+    for (file, problem) in results {
+        match problem {
+            Some(problem) => {
+                failed_count += 1;
+                println!("verify, {}, {}", file.name(), problem);
+            }
+            None => {
+                verified_count += 1;
+                verified_bytes += file.row.size;
+            }
+        }
+    }
+    println!(
+        "verify, verified: {} ({}), failed: {}",
+        verified_count,
+        HumanBytes(verified_bytes),
+        failed_count
+    );
+
+    if failed_count > 0 {
+        Ok(Outcome::Failed)
+    } else {
+        Ok(Outcome::Success)
+    }
+}
@@ -0,0 +1,34 @@
+use std::path::Path;
+
+use tokio::fs;
+
+/// write_replace 先写同目录的临时文件再改名, 中断时不会留下不完整的文件
+/// 用于 temp 下的布局记录, bitmap 和上传记录, 上级目录不存在时创建
+pub async fn write_replace(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+    fs::write(&temp_path, data).await?;
+    fs::rename(&temp_path, path).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_write_replace() {
+        let temp_dir = std::env::temp_dir().join(format!("httpdrs-file-{}", std::process::id()));
+        let path = temp_dir.join("temp/a.layout.json");
+
+        write_replace(&path, b"1").await.unwrap();
+        write_replace(&path, b"2").await.unwrap();
+        assert_eq!(fs::read(&path).await.unwrap(), b"2");
+        assert!(!temp_dir.join("temp/a.layout.json.tmp").exists());
+
+        fs::remove_dir_all(&temp_dir).await.unwrap();
+    }
+}
@@ -1,17 +1,23 @@
 mod client;
+mod file;
 pub mod read;
 pub mod request;
-mod write;
+pub mod verify;
+pub mod write;
 
 pub mod httpd {
     pub use crate::client::*;
     pub use httpdrs_bandwidth::*;
+    pub use httpdrs_sign::error::*;
     pub use httpdrs_sign::jwtsign::*;
+    pub use httpdrs_sign::retry::*;
+    pub use httpdrs_sign::signer::*;
     pub use httpdrs_sign::*;
 }
 
 /// 本地文件相关
 pub mod io {
+    pub use crate::file::*;
     pub use crate::read::reader::*;
 }
 
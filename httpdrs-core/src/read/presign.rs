@@ -1,33 +1,353 @@
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 
-use tokio::time;
+use tokio::sync::{OnceCell, mpsc, oneshot};
+use tokio::time::{self, Duration, Instant};
 
-use crate::httpd::SignatureClient;
+use crate::httpd::{HttpdError, Presigned, Signer};
+
+/// 链接过期前提前刷新的时间, 有效期很短时最多提前有效期的一半
+const EXPIRE_MARGIN: Duration = Duration::from_secs(30);
 
 /// read 获取下载链接
-pub async fn read(sign: String, with_client: Arc<SignatureClient>) -> Option<String> {
+pub async fn read(sign: String, signer: Arc<dyn Signer>) -> Result<String, HttpdError> {
+    read_data(sign, signer).await.map(|data| data.endpoint)
+}
+
+async fn read_data(sign: String, signer: Arc<dyn Signer>) -> Result<Presigned, HttpdError> {
     let start = time::Instant::now();
 
-    let reader = match with_client.reader_get(sign).await {
-        Ok(reader) => reader,
+    let presigned = match signer.presign_read(&sign).await {
+        Ok(presigned) => presigned,
         Err(err) => {
-            tracing::error!("download_err, reader_presign err: {}", err);
-            return None;
+            tracing::error!("download_err, {}_presign err: {}", signer.name(), err);
+            return Err(err);
         }
     };
 
-    if reader.code != 0 {
-        tracing::error!(
-            "download_presign, resp_code != 0, message: {}",
-            reader.message
-        );
-        return None;
-    }
-    if reader.data.endpoint.is_empty() {
+    if presigned.endpoint.is_empty() {
         tracing::error!("download_presign, endpoint is empty");
-        return None;
+        return Err(HttpdError::Presign("endpoint is empty".to_string()));
     }
 
     tracing::info!("download_presign, use {:?}", start.elapsed());
-    Some(reader.data.endpoint)
+    Ok(presigned)
+}
+
+#[derive(Debug, Clone)]
+struct PresignEntry {
+    presigned: Presigned,
+    refresh_at: Option<Instant>, // 过期前 EXPIRE_MARGIN, 之后 get 重新签名
+    direct: bool,                // url 清单中的链接, 不需要签名也不会刷新
+}
+
+impl PresignEntry {
+    fn new(presigned: Presigned) -> Self {
+        // 优先使用签名返回的有效期, 否则从 S3 链接的 X-Amz-Expires 中获取
+        let expires_in = presigned.expires_in.or_else(|| {
+            query_value(&presigned.endpoint, "X-Amz-Expires")?
+                .parse()
+                .ok()
+        });
+        let refresh_at = expires_in.map(|secs| {
+            let expires_in = Duration::from_secs(secs);
+            Instant::now() + expires_in - EXPIRE_MARGIN.min(expires_in / 2)
+        });
+        PresignEntry {
+            refresh_at,
+            presigned,
+            direct: false,
+        }
+    }
+
+    fn expired(&self) -> bool {
+        match self.refresh_at {
+            Some(refresh_at) => Instant::now() >= refresh_at,
+            None => false,
+        }
+    }
+}
+
+type PresignCell = Arc<OnceCell<Result<PresignEntry, HttpdError>>>;
+
+/// PresignCache 按 sign 缓存下载链接
+/// 同一个文件的所有分片共享一次签名请求, 链接过期或存储返回 403 时重新签名
+pub struct PresignCache {
+    signer: Arc<dyn Signer>,
+    batch: Option<PresignBatchSender>,
+    entries: Mutex<HashMap<String, PresignCell>>,
+}
+
+impl PresignCache {
+    /// batch 为 None 时每个 sign 单独请求签名服务
+    pub fn new(signer: Arc<dyn Signer>, batch: Option<PresignBatchSender>) -> Arc<Self> {
+        Arc::new(PresignCache {
+            signer,
+            batch,
+            entries: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn signer(&self) -> &Arc<dyn Signer> {
+        &self.signer
+    }
+
+    /// get 获取下载链接, 同一时间只有一个请求发往签名服务
+    /// 这次调用中签名得到的链接直接返回, 即使已经在刷新时间之后, 避免有效期很短时一直重新签名
+    pub async fn get(&self, sign: &str) -> Result<Presigned, HttpdError> {
+        loop {
+            let cell = {
+                let mut entries = self.entries.lock().unwrap();
+                Arc::clone(entries.entry(sign.to_string()).or_default())
+            };
+
+            let mut fetched = false;
+            let entry = cell
+                .get_or_init(|| async {
+                    fetched = true;
+                    self.fetch(sign).await.map(PresignEntry::new)
+                })
+                .await;
+
+            match entry {
+                Ok(entry) if fetched || !entry.expired() => return Ok(entry.presigned.clone()),
+                Ok(_) => {
+                    tracing::info!("download_presign, expired: {}", sign);
+                    self.remove_cell(sign, &cell);
+                }
+                Err(err) => {
+                    // 签名失败不缓存, 下次重新请求
+                    self.remove_cell(sign, &cell);
+                    return Err(err.clone());
+                }
+            }
+        }
+    }
+
+    /// direct 登记 url 清单中的链接, get 直接返回, 不经过签名
+    pub fn direct(&self, url: &str) {
+        let entry = PresignEntry {
+            presigned: Presigned::new(url.to_string()),
+            refresh_at: None,
+            direct: true,
+        };
+        self.entries.lock().unwrap().insert(
+            url.to_string(),
+            Arc::new(OnceCell::new_with(Some(Ok(entry)))),
+        );
+    }
+
+    /// invalidate 存储拒绝了链接(403/过期), 下次 get 重新签名
+    /// 只有缓存的还是这个链接时才删除, 避免并发的分片重复刷新
+    pub fn invalidate(&self, sign: &str, endpoint: &str) {
+        let mut entries = self.entries.lock().unwrap();
+        let matched = entries
+            .get(sign)
+            .and_then(|cell| cell.get())
+            .is_some_and(|entry| {
+                entry
+                    .as_ref()
+                    .is_ok_and(|e| !e.direct && e.presigned.endpoint == endpoint)
+            });
+        if matched {
+            entries.remove(sign);
+        }
+    }
+
+    /// remove 文件下载结束后释放缓存
+    pub fn remove(&self, sign: &str) {
+        self.entries.lock().unwrap().remove(sign);
+    }
+
+    async fn fetch(&self, sign: &str) -> Result<Presigned, HttpdError> {
+        if let Some(batch) = &self.batch {
+            let (tx_data, rx_data) = oneshot::channel();
+            if batch.send((sign.to_string(), tx_data)).await.is_ok() {
+                return rx_data.await.unwrap_or(Err(HttpdError::Cancelled));
+            }
+        }
+        read_data(sign.to_string(), Arc::clone(&self.signer)).await
+    }
+
+    fn remove_cell(&self, sign: &str, cell: &PresignCell) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries
+            .get(sign)
+            .is_some_and(|current| Arc::ptr_eq(current, cell))
+        {
+            entries.remove(sign);
+        }
+    }
+}
+
+/// 批量签名的请求: sign 和返回结果的通道
+pub type PresignRequest = (String, oneshot::Sender<Result<Presigned, HttpdError>>);
+
+pub type PresignBatchSender = mpsc::Sender<PresignRequest>;
+
+pub type PresignBatchReceiver = mpsc::Receiver<PresignRequest>;
+
+/// batch 收集签名请求, 数量达到 max_batch 或等待超过 window 后一起发送
+/// 不支持批量签名时回退到单个请求
+pub async fn batch(
+    signer: Arc<dyn Signer>,
+    mut batch_receiver: PresignBatchReceiver,
+    max_batch: usize,
+    window: Duration,
+) {
+    let supported = Arc::new(AtomicBool::new(true));
+
+    while let Some(first) = batch_receiver.recv().await {
+        let mut requests = vec![first];
+
+        let deadline = time::sleep(window);
+        tokio::pin!(deadline);
+        while requests.len() < max_batch {
+            tokio::select! {
+                request = batch_receiver.recv() => match request {
+                    Some(request) => requests.push(request),
+                    None => break,
+                },
+                _ = &mut deadline => break,
+            }
+        }
+
+        tokio::spawn(batch_flush(
+            Arc::clone(&signer),
+            requests,
+            Arc::clone(&supported),
+        ));
+    }
+}
+
+async fn batch_flush(
+    signer: Arc<dyn Signer>,
+    requests: Vec<PresignRequest>,
+    supported: Arc<AtomicBool>,
+) {
+    let start = time::Instant::now();
+    let batch_size = requests.len();
+
+    let mut fallback = Vec::new();
+    if batch_size > 1 && supported.load(Ordering::Relaxed) {
+        let signs: Vec<String> = requests.iter().map(|(sign, _)| sign.clone()).collect();
+        match signer.presign_read_batch(&signs).await {
+            Ok(Some(data)) if data.len() == batch_size => {
+                for ((sign, tx_data), data) in requests.into_iter().zip(data) {
+                    if data.endpoint.is_empty() {
+                        fallback.push((sign, tx_data));
+                    } else {
+                        let _ = tx_data.send(Ok(data));
+                    }
+                }
+                tracing::info!(
+                    "download_presign, batch: {}, use {:?}",
+                    batch_size,
+                    start.elapsed()
+                );
+            }
+            Ok(None) => {
+                tracing::warn!("download_presign, batch unsupported, fallback to single");
+                supported.store(false, Ordering::Relaxed);
+                fallback = requests;
+            }
+            Ok(Some(data)) => {
+                tracing::error!(
+                    "download_presign, batch size: {}/{}",
+                    data.len(),
+                    batch_size
+                );
+                fallback = requests;
+            }
+            Err(err) => {
+                tracing::error!("download_presign, batch err: {}", err);
+                fallback = requests;
+            }
+        }
+    } else {
+        fallback = requests;
+    }
+
+    for (sign, tx_data) in fallback {
+        let signer = Arc::clone(&signer);
+        tokio::spawn(async move {
+            let _ = tx_data.send(read_data(sign, signer).await);
+        });
+    }
+}
+
+fn query_value<'a>(url: &'a str, key: &str) -> Option<&'a str> {
+    let (_, query) = url.split_once('?')?;
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(k, _)| *k == key)
+        .map(|(_, v)| v)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use futures::future::BoxFuture;
+
+    use super::*;
+
+    /// ShortSigner 返回有效期很短的链接, 记录签名次数
+    struct ShortSigner {
+        expires_in: u64,
+        count: AtomicUsize,
+    }
+
+    impl Signer for ShortSigner {
+        fn name(&self) -> &'static str {
+            "short"
+        }
+
+        fn presign_read<'a>(
+            &'a self,
+            sign: &'a str,
+        ) -> BoxFuture<'a, Result<Presigned, HttpdError>> {
+            let n = self.count.fetch_add(1, Ordering::Relaxed);
+            Box::pin(async move {
+                Ok(Presigned {
+                    endpoint: format!("http://127.0.0.1/{}?n={}", sign, n),
+                    expires_in: Some(self.expires_in),
+                    ..Default::default()
+                })
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_short_expires() {
+        for expires_in in [0, 10] {
+            let signer = Arc::new(ShortSigner {
+                expires_in,
+                count: AtomicUsize::new(0),
+            });
+            let cache = PresignCache::new(signer.clone(), None);
+
+            // 有效期小于 EXPIRE_MARGIN 时也返回, 不重复签名
+            let start = Instant::now();
+            let first = cache.get("a").await.unwrap();
+            assert_eq!(signer.count.load(Ordering::Relaxed), 1);
+
+            // 有效期过半后刷新
+            let refresh_at = cache.entries.lock().unwrap()["a"]
+                .get()
+                .and_then(|entry| entry.as_ref().ok())
+                .and_then(|entry| entry.refresh_at)
+                .unwrap();
+            let refresh_in = refresh_at - start;
+            let half = Duration::from_secs(expires_in) / 2;
+            assert!(refresh_in >= half && refresh_in < half + Duration::from_secs(1));
+
+            if expires_in > 0 {
+                assert_eq!(cache.get("a").await.unwrap().endpoint, first.endpoint);
+                assert_eq!(signer.count.load(Ordering::Relaxed), 1);
+            }
+        }
+    }
 }
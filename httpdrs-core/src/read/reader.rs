@@ -1,6 +1,8 @@
-use csv::Reader;
+use csv::{Reader, StringRecord};
 use std::fmt::Display;
 
+use crate::verify::{DigestColumns, FileDigest};
+
 pub struct CSVMetaReader {
     pub meta_path: String,
 }
@@ -18,7 +20,7 @@ impl CSVMetaReader {
         let paths = std::fs::read_dir(self.meta_path.as_str()).unwrap();
         for path in paths {
             let meta_path = path.unwrap().path().to_string_lossy().to_string();
-            let (lines, bytes) = read_meta_bin(meta_path.as_str(), &mut |_, _, _| {}).await?;
+            let (lines, bytes) = read_meta_bin(meta_path.as_str(), &mut |_, _, _, _| {}).await?;
             file_lines += lines;
             file_bytes += bytes;
         }
@@ -52,29 +54,140 @@ pub async fn read_meta_bin<F>(
     processor: &mut F,
 ) -> Result<(i64, i64), Box<dyn std::error::Error>>
 where
-    F: FnMut(String, i64, String),
+    F: FnMut(String, i64, String, FileDigest),
 {
     let mut csv_reader = Reader::from_path(file_path)?;
+    let meta_columns = MetaColumns::new(csv_reader.headers()?);
 
     let mut lines: i64 = 0;
     let mut bytes: i64 = 0;
     for raw_result in csv_reader.records() {
         let raw_line = raw_result?;
-        let sign = raw_line.get(0).unwrap();
-        let size = raw_line.get(1).unwrap().parse::<i64>().unwrap();
-        let extn = raw_line.get(2).unwrap();
+        let meta_row = meta_columns.row(&raw_line)?;
         lines += 1;
-        bytes += size;
+        bytes += meta_row.size as i64;
 
-        processor(sign.to_string(), size, extn.to_string());
+        processor(
+            meta_row.sign,
+            meta_row.size as i64,
+            meta_row.extn,
+            meta_row.digest,
+        );
     }
 
     Ok((lines, bytes))
 }
 
+/// MetaRow 清单中的一行
+#[derive(Debug, Clone)]
+pub struct MetaRow {
+    pub sign: String,         // sign 清单中是签名, url 清单中是下载链接
+    pub path: Option<String>, // url 清单中文件的相对路径
+    pub size: u64,
+    pub extn: String,
+    pub digest: FileDigest,
+}
+
+/// MetaColumns 根据表头识别清单格式
+/// sign 清单: sign,size,extn[,md5,sha256,crc32c], 按位置读取
+/// url 清单: 表头包含 url,path,size, 直接下载, 不需要签名
+#[derive(Debug, Clone)]
+pub struct MetaColumns {
+    sign: usize,
+    path: Option<usize>,
+    size: usize,
+    extn: Option<usize>,
+    digest: DigestColumns,
+}
+
+impl MetaColumns {
+    pub fn new(headers: &StringRecord) -> Self {
+        let position = |name: &str| {
+            headers
+                .iter()
+                .position(|header| header.trim().eq_ignore_ascii_case(name))
+        };
+        let digest = DigestColumns::new(headers);
+
+        match (position("url"), position("path"), position("size")) {
+            (Some(url), Some(path), Some(size)) => MetaColumns {
+                sign: url,
+                path: Some(path),
+                size,
+                extn: None,
+                digest,
+            },
+            _ => MetaColumns {
+                sign: 0,
+                path: None,
+                size: 1,
+                extn: Some(2),
+                digest,
+            },
+        }
+    }
+
+    pub fn is_url(&self) -> bool {
+        self.path.is_some()
+    }
+
+    pub fn row(&self, record: &StringRecord) -> Result<MetaRow, String> {
+        let value = |column: usize| record.get(column).map(|value| value.trim());
+
+        let sign = value(self.sign)
+            .filter(|sign| !sign.is_empty())
+            .ok_or_else(|| format!("missing sign, line: {:?}", record.position()))?;
+        let size = value(self.size)
+            .and_then(|size| size.parse::<u64>().ok())
+            .ok_or_else(|| format!("invalid size, line: {:?}", record.position()))?;
+        let path = match self.path {
+            Some(column) => Some(
+                value(column)
+                    .filter(|path| !path.is_empty())
+                    .ok_or_else(|| format!("missing path, line: {:?}", record.position()))?
+                    .to_string(),
+            ),
+            None => None,
+        };
+
+        Ok(MetaRow {
+            sign: sign.to_string(),
+            path,
+            size,
+            extn: self.extn.and_then(value).unwrap_or_default().to_string(),
+            digest: self.digest.digest(record),
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[test]
+    fn test_meta_columns() {
+        let headers = StringRecord::from(vec!["url", "path", "size", "md5"]);
+        let meta_columns = MetaColumns::new(&headers);
+        assert!(meta_columns.is_url());
+
+        let record = StringRecord::from(vec![
+            "https://mirror.example.com/a/b.bin",
+            "a/b.bin",
+            "12",
+            "",
+        ]);
+        let meta_row = meta_columns.row(&record).unwrap();
+        assert_eq!(meta_row.sign, "https://mirror.example.com/a/b.bin");
+        assert_eq!(meta_row.path.as_deref(), Some("a/b.bin"));
+        assert_eq!(meta_row.size, 12);
+        assert!(meta_row.digest.is_empty());
+
+        let headers = StringRecord::from(vec!["sign", "size", "extn"]);
+        let meta_columns = MetaColumns::new(&headers);
+        assert!(!meta_columns.is_url());
+        let record = StringRecord::from(vec!["token", "x", ""]);
+        assert!(meta_columns.row(&record).is_err());
+    }
+
     #[tokio::test]
     async fn test_read_rows() {
         let mut reader =
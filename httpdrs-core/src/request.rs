@@ -1,18 +1,75 @@
 use std::sync::Arc;
 
+use crate::read::reader::MetaRow;
+use crate::verify::FileDigest;
+
+/// 默认分片大小
+pub const DEFAULT_CHUNK_SIZE: u64 = 1024 * 1024 * 5;
+
 #[derive(Debug)]
 pub struct FSReader {
     pub request_sign: String,
+    pub request_path: Option<String>, // url 清单中的相对路径, 有值时 request_sign 是下载链接
     pub require_size: u64,
     pub chunk_size: u64,
+    pub extn: String,       // 清单中的 extn 列, 写入失败清单时原样保留
+    pub digest: FileDigest, // 清单中的摘要, 下载完成后校验
 }
 
 impl FSReader {
-    pub fn new(sign: String, size: u64) -> Arc<Self> {
+    pub fn new(sign: String, size: u64, digest: FileDigest, chunk_size: u64) -> Arc<Self> {
         Arc::new(FSReader {
+            request_sign: sign,
+            request_path: None,
+            require_size: size,
+            chunk_size,
+            extn: String::new(),
+            digest,
+        })
+    }
+
+    pub fn from_row(meta_row: MetaRow, chunk_size: u64) -> Arc<Self> {
+        Arc::new(FSReader {
+            request_sign: meta_row.sign,
+            request_path: meta_row.path,
+            require_size: meta_row.size,
+            chunk_size,
+            extn: meta_row.extn,
+            digest: meta_row.digest,
+        })
+    }
+
+    /// with_chunk_size 使用新的分片大小, 其他字段不变
+    pub fn with_chunk_size(&self, chunk_size: u64) -> Arc<Self> {
+        Arc::new(FSReader {
+            request_sign: self.request_sign.clone(),
+            request_path: self.request_path.clone(),
+            require_size: self.require_size,
+            chunk_size,
+            extn: self.extn.clone(),
+            digest: self.digest.clone(),
+        })
+    }
+
+    pub fn total_parts(&self) -> u64 {
+        self.require_size.div_ceil(self.chunk_size)
+    }
+}
+
+#[derive(Debug)]
+pub struct FSWriter {
+    pub request_sign: String,
+    pub require_size: u64,
+    pub chunk_size: u64,
+}
+
+impl FSWriter {
+    /// chunk_size: S3 分片最小 5MiB (最后一片除外)
+    pub fn new(sign: String, size: u64, chunk_size: u64) -> Arc<Self> {
+        Arc::new(FSWriter {
             request_sign: sign,
             require_size: size,
-            chunk_size: 1024 * 1024 * 5,
+            chunk_size,
         })
     }
 
@@ -0,0 +1,207 @@
+use std::fmt::Display;
+use std::io::Read;
+use std::path::PathBuf;
+
+use base64::prelude::*;
+use csv::StringRecord;
+use sha2::{Digest, Sha256};
+
+/// 每次读取文件计算摘要的大小
+const VERIFY_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// FileDigest 清单中提供的文件摘要, 支持 hex 或 base64 编码
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileDigest {
+    pub md5: Option<String>,
+    pub sha256: Option<String>,
+    pub crc32c: Option<String>,
+}
+
+impl FileDigest {
+    pub fn is_empty(&self) -> bool {
+        self.md5.is_none() && self.sha256.is_none() && self.crc32c.is_none()
+    }
+}
+
+impl Display for FileDigest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "md5: {:?}, sha256: {:?}, crc32c: {:?}",
+            self.md5, self.sha256, self.crc32c
+        )
+    }
+}
+
+/// DigestColumns 根据清单的表头找到摘要所在的列, 没有对应的列时不校验
+#[derive(Debug, Clone, Default)]
+pub struct DigestColumns {
+    md5: Option<usize>,
+    sha256: Option<usize>,
+    crc32c: Option<usize>,
+}
+
+impl DigestColumns {
+    pub fn new(headers: &StringRecord) -> Self {
+        let position = |name: &str| {
+            headers
+                .iter()
+                .position(|header| header.trim().eq_ignore_ascii_case(name))
+        };
+        DigestColumns {
+            md5: position("md5"),
+            sha256: position("sha256"),
+            crc32c: position("crc32c"),
+        }
+    }
+
+    pub fn digest(&self, record: &StringRecord) -> FileDigest {
+        let value = |column: Option<usize>| {
+            column
+                .and_then(|column| record.get(column))
+                .map(|value| value.trim())
+                .filter(|value| !value.is_empty())
+                .map(|value| value.to_string())
+        };
+        FileDigest {
+            md5: value(self.md5),
+            sha256: value(self.sha256),
+            crc32c: value(self.crc32c),
+        }
+    }
+}
+
+/// verify_file 一次读取文件计算需要的摘要并和清单比较
+/// Err 中是不一致的摘要说明
+pub async fn verify_file(file_path: PathBuf, digest: FileDigest) -> Result<(), String> {
+    if digest.is_empty() {
+        return Ok(());
+    }
+
+    tokio::task::spawn_blocking(move || verify_file_blocking(&file_path, &digest))
+        .await
+        .map_err(|err| format!("verify task err: {}", err))?
+}
+
+fn verify_file_blocking(file_path: &PathBuf, digest: &FileDigest) -> Result<(), String> {
+    let mut file = std::fs::File::open(file_path)
+        .map_err(|err| format!("open {:?} err: {}", file_path, err))?;
+
+    let mut state = DigestState::new(digest);
+    let mut buffer = vec![0u8; VERIFY_BUFFER_SIZE];
+    loop {
+        let n = file
+            .read(&mut buffer)
+            .map_err(|err| format!("read {:?} err: {}", file_path, err))?;
+        if n == 0 {
+            break;
+        }
+        state.update(&buffer[..n]);
+    }
+    state.check(digest)
+}
+
+/// verify_bytes 校验已经读入内存的数据
+pub fn verify_bytes(data: &[u8], digest: &FileDigest) -> Result<(), String> {
+    if digest.is_empty() {
+        return Ok(());
+    }
+    let mut state = DigestState::new(digest);
+    state.update(data);
+    state.check(digest)
+}
+
+/// DigestState 只计算清单中提供的摘要
+struct DigestState {
+    md5: Option<md5::Context>,
+    sha256: Option<Sha256>,
+    crc32c: Option<u32>,
+}
+
+impl DigestState {
+    fn new(digest: &FileDigest) -> Self {
+        DigestState {
+            md5: digest.md5.as_ref().map(|_| md5::Context::new()),
+            sha256: digest.sha256.as_ref().map(|_| Sha256::new()),
+            crc32c: digest.crc32c.as_ref().map(|_| 0),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        if let Some(context) = self.md5.as_mut() {
+            context.consume(data);
+        }
+        if let Some(context) = self.sha256.as_mut() {
+            context.update(data);
+        }
+        if let Some(value) = self.crc32c.as_mut() {
+            *value = crc32c::crc32c_append(*value, data);
+        }
+    }
+
+    fn check(self, digest: &FileDigest) -> Result<(), String> {
+        if let (Some(expected), Some(context)) = (&digest.md5, self.md5) {
+            check_digest("md5", expected, &context.finalize().0)?;
+        }
+        if let (Some(expected), Some(context)) = (&digest.sha256, self.sha256) {
+            check_digest("sha256", expected, &context.finalize())?;
+        }
+        if let (Some(expected), Some(value)) = (&digest.crc32c, self.crc32c) {
+            check_digest("crc32c", expected, &value.to_be_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+fn check_digest(name: &str, expected: &str, actual: &[u8]) -> Result<(), String> {
+    let actual_hex: String = actual.iter().map(|b| format!("{:02x}", b)).collect();
+    if expected.eq_ignore_ascii_case(&actual_hex) || expected == BASE64_STANDARD.encode(actual) {
+        return Ok(());
+    }
+    Err(format!(
+        "{} mismatch, expected: {}, actual: {}",
+        name, expected, actual_hex
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_verify_file() {
+        let file_path = std::env::temp_dir().join(format!("httpdrs-verify-{}", std::process::id()));
+        std::fs::write(&file_path, b"123456789").unwrap();
+
+        let digest = FileDigest {
+            md5: Some("25f9e794323b453885f5181f1b624d0b".to_string()),
+            sha256: Some(
+                "15e2b0d3c33891ebb0f1ef609ec419420c20e320ce94c65fbc8c3312448eb225".to_string(),
+            ),
+            crc32c: Some("e3069283".to_string()),
+        };
+        assert_eq!(verify_file(file_path.clone(), digest.clone()).await, Ok(()));
+
+        // base64 编码的 crc32c
+        let digest = FileDigest {
+            crc32c: Some("4waSgw==".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(verify_file(file_path.clone(), digest).await, Ok(()));
+
+        let digest = FileDigest {
+            md5: Some("00000000000000000000000000000000".to_string()),
+            ..Default::default()
+        };
+        assert!(verify_file(file_path.clone(), digest).await.is_err());
+
+        std::fs::remove_file(file_path).unwrap();
+
+        let digest = FileDigest {
+            md5: Some("25f9e794323b453885f5181f1b624d0b".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(verify_bytes(b"123456789", &digest), Ok(()));
+        assert!(verify_bytes(b"12345678", &digest).is_err());
+    }
+}
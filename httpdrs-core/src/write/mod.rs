@@ -1 +1 @@
-mod presign;
+pub mod presign;
@@ -1 +1,67 @@
+use std::sync::Arc;
 
+use tokio::time;
+
+use crate::httpd::writer::MultipartAction;
+use crate::httpd::{HttpdError, Signer};
+
+/// write 获取上传链接
+pub async fn write(sign: String, signer: Arc<dyn Signer>) -> Result<String, HttpdError> {
+    let start = time::Instant::now();
+
+    let presigned = match signer.presign_write(&sign).await {
+        Ok(presigned) => presigned,
+        Err(err) => {
+            tracing::error!("upload_err, {}_presign err: {}", signer.name(), err);
+            return Err(err);
+        }
+    };
+
+    let endpoint = endpoint(presigned.endpoint)?;
+    tracing::info!("upload_presign, use {:?}", start.elapsed());
+    Ok(endpoint)
+}
+
+/// multipart 获取分片上传各阶段的链接, part_number 从 1 开始
+pub async fn multipart(
+    sign: String,
+    signer: Arc<dyn Signer>,
+    action: MultipartAction,
+    upload_id: String,
+    part_number: Option<u64>,
+) -> Result<String, HttpdError> {
+    let start = time::Instant::now();
+
+    let presigned = match signer
+        .presign_multipart(&sign, action, &upload_id, part_number)
+        .await
+    {
+        Ok(presigned) => presigned,
+        Err(err) => {
+            tracing::error!(
+                "upload_err, {}_presign err: {}, action: {:?}",
+                signer.name(),
+                err,
+                action
+            );
+            return Err(err);
+        }
+    };
+
+    let endpoint = endpoint(presigned.endpoint)?;
+    tracing::info!(
+        "upload_presign, action: {:?}, part: {:?}, use {:?}",
+        action,
+        part_number,
+        start.elapsed()
+    );
+    Ok(endpoint)
+}
+
+fn endpoint(endpoint: String) -> Result<String, HttpdError> {
+    if endpoint.is_empty() {
+        tracing::error!("upload_presign, endpoint is empty");
+        return Err(HttpdError::Presign("endpoint is empty".to_string()));
+    }
+    Ok(endpoint)
+}
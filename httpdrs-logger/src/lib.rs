@@ -15,6 +15,9 @@ pub fn try_logger_init(log_path: &str) {
         )
         .finish();
 
-    tracing::subscriber::set_global_default(subscriber).expect("Failed to set subscriber");
+    // 同一进程中多次下载时只初始化一次, 日志写入第一次的目录
+    if tracing::subscriber::set_global_default(subscriber).is_err() {
+        return;
+    }
     tracing::info!("Logger initialized: baai-flagdatset-rs");
 }
@@ -0,0 +1,108 @@
+use std::fmt::Display;
+use std::sync::Arc;
+
+use reqwest::StatusCode;
+
+use crate::jwtsign::{PathError, TokenError};
+
+/// HttpdError 上传下载各层返回的错误
+/// 实现 Clone, 同一个签名结果可以分发给多个等待的分片
+#[derive(Debug, Clone)]
+pub enum HttpdError {
+    Presign(String),            // 签名配置错误, 签名被拒绝(code != 0)或响应无法解析
+    PresignUnavailable(String), // 签名服务暂时不可用: 连接失败或返回可以重试的状态码
+    Token(TokenError),          // sign 解析或校验失败
+    Path(PathError),            // 本地路径不安全
+    Network(String),            // 连接失败, 超时, 响应中断
+    Status { status: StatusCode, message: String }, // 存储或签名服务返回错误状态
+    Io(Arc<std::io::Error>),    // 本地文件读写
+    Verify(String),             // 摘要或大小校验失败
+    Cancelled,
+}
+
+impl HttpdError {
+    pub fn status(status: StatusCode, message: impl Into<String>) -> Self {
+        HttpdError::Status {
+            status,
+            message: message.into(),
+        }
+    }
+
+    /// kind 错误分类, 用于结果记录和 Python 异常
+    pub fn kind(&self) -> &'static str {
+        match self {
+            HttpdError::Presign(_) | HttpdError::PresignUnavailable(_) => "presign",
+            HttpdError::Token(_) => "token",
+            HttpdError::Path(_) => "path",
+            HttpdError::Network(_) => "network",
+            HttpdError::Status { .. } => "status",
+            HttpdError::Io(_) => "io",
+            HttpdError::Verify(_) => "verify",
+            HttpdError::Cancelled => "cancelled",
+        }
+    }
+
+    pub fn is_status(&self, code: StatusCode) -> bool {
+        matches!(self, HttpdError::Status { status, .. } if *status == code)
+    }
+}
+
+impl Display for HttpdError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HttpdError::Presign(reason) => write!(f, "presign err: {}", reason),
+            HttpdError::PresignUnavailable(reason) => write!(f, "presign unavailable: {}", reason),
+            HttpdError::Token(err) => write!(f, "{}", err),
+            HttpdError::Path(err) => write!(f, "{}", err),
+            HttpdError::Network(reason) => write!(f, "network err: {}", reason),
+            HttpdError::Status { status, message } if message.is_empty() => {
+                write!(f, "http status: {}", status)
+            }
+            HttpdError::Status { status, message } => {
+                write!(f, "http status: {}, {}", status, message)
+            }
+            HttpdError::Io(err) => write!(f, "io err: {}", err),
+            HttpdError::Verify(reason) => write!(f, "verify err: {}", reason),
+            HttpdError::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for HttpdError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            HttpdError::Token(err) => Some(err),
+            HttpdError::Path(err) => Some(err),
+            HttpdError::Io(err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<TokenError> for HttpdError {
+    fn from(err: TokenError) -> Self {
+        HttpdError::Token(err)
+    }
+}
+
+impl From<PathError> for HttpdError {
+    fn from(err: PathError) -> Self {
+        HttpdError::Path(err)
+    }
+}
+
+impl From<std::io::Error> for HttpdError {
+    fn from(err: std::io::Error) -> Self {
+        HttpdError::Io(Arc::new(err))
+    }
+}
+
+/// reqwest 的错误按是否带有状态码区分
+impl From<reqwest::Error> for HttpdError {
+    fn from(err: reqwest::Error) -> Self {
+        match err.status() {
+            Some(status) => HttpdError::status(status, ""),
+            None => HttpdError::Network(err.without_url().to_string()),
+        }
+    }
+}
@@ -1,10 +1,15 @@
+use std::collections::HashMap;
 use std::fmt::Display;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 use base64::prelude::*;
-use jwt::{Claims, Header, Token};
+use jsonwebtoken::errors::ErrorKind;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{DecodingKey, Validation, decode, decode_header, get_current_timestamp};
 use rmp_serde::from_slice;
 use serde::Deserialize;
+use serde_json::Value;
 use tokio::fs;
 
 #[derive(Debug, Deserialize)]
@@ -15,6 +20,33 @@ pub struct HttpdMetaReader {
 }
 
 impl HttpdMetaReader {
+    /// from_path url 清单中的文件, 本地路径就是清单中的相对路径
+    pub fn from_path(path: &str) -> Self {
+        HttpdMetaReader {
+            proto: "url".to_string(),
+            path: path.to_string(),
+            prefix: "".to_string(),
+        }
+    }
+
+    /// safe_path 规范化 prefix/path, 保证本地路径在 data 目录内
+    /// 绝对路径, .. , NUL 字节, Windows 盘符按 policy 拒绝或去掉
+    pub fn safe_path(self, policy: PathPolicy) -> Result<Self, PathError> {
+        let prefix = safe_relative(&self.prefix, policy)?;
+        let path = safe_relative(&self.path, policy)?;
+        if path.is_empty() {
+            return Err(PathError {
+                path: self.path,
+                reason: "empty path",
+            });
+        }
+        Ok(HttpdMetaReader {
+            proto: self.proto,
+            path,
+            prefix,
+        })
+    }
+
     pub fn local_relative_path(&self) -> PathBuf {
         PathBuf::from(&self.prefix).join(&self.path)
     }
@@ -31,28 +63,71 @@ impl HttpdMetaReader {
         check_file_meta(path).await
     }
 
-    pub fn local_part_path(&self, base_dir: &str, part_index: u64, temp_dir: &str) -> PathBuf {
-        // 获取本地绝对路径
-        let local_path = self.local_absolute_path_str(base_dir);
+    /// local_part_path 分片文件名带上分片大小, 分片大小变化后不会误用之前的分片
+    pub fn local_part_path(
+        &self,
+        base_dir: &str,
+        part_index: u64,
+        chunk_size: u64,
+        temp_dir: &str,
+    ) -> PathBuf {
+        let (file_hash, file_name) = self.local_hash_name(base_dir);
 
-        // 计算文件路径的MD5哈希
-        let absolute_path_str = local_path
-            .canonicalize()
-            .unwrap_or(local_path.clone())
-            .to_string_lossy()
-            .to_string();
-        let file_hash = format!("{:x}", md5::compute(absolute_path_str.as_bytes()));
+        // 生成分片文件名: {part_index}__{chunk_size}__{file_hash}__{file_name}.bin
+        let part_filename = format!(
+            "{}__{}__{}__{}.bin",
+            part_index, chunk_size, file_hash, file_name
+        );
+        PathBuf::from(temp_dir).join(part_filename)
+    }
 
-        // 获取文件名
-        let file_name = local_path
-            .file_name()
-            .and_then(|name| name.to_str())
-            .unwrap_or("unknown");
+    /// local_layout_path 分片下载的布局记录: {file_hash}__{file_name}.layout.json
+    pub fn local_layout_path(&self, base_dir: &str, temp_dir: &str) -> PathBuf {
+        let (file_hash, file_name) = self.local_hash_name(base_dir);
 
-        // 生成分片文件名: {part_index}__{file_hash}__{file_name}.bin
-        let part_filename = format!("{}__{}__{}.bin", part_index, file_hash, file_name);
-        PathBuf::from(temp_dir).join(part_filename)
+        let layout_filename = format!("{}__{}.layout.json", file_hash, file_name);
+        PathBuf::from(temp_dir).join(layout_filename)
+    }
+
+    /// local_upload_path 分片上传的断点记录: {file_hash}__{file_name}.upload.json
+    pub fn local_upload_path(&self, base_dir: &str, temp_dir: &str) -> PathBuf {
+        let (file_hash, file_name) = self.local_hash_name(base_dir);
+
+        let upload_filename = format!("{}__{}.upload.json", file_hash, file_name);
+        PathBuf::from(temp_dir).join(upload_filename)
+    }
+
+    /// local_bitmap_path 预分配下载的分片完成记录: {file_hash}__{file_name}.bitmap
+    pub fn local_bitmap_path(&self, base_dir: &str, temp_dir: &str) -> PathBuf {
+        let (file_hash, file_name) = self.local_hash_name(base_dir);
+
+        let bitmap_filename = format!("{}__{}.bitmap", file_hash, file_name);
+        PathBuf::from(temp_dir).join(bitmap_filename)
     }
+
+    fn local_hash_name(&self, base_dir: &str) -> (String, String) {
+        local_hash_name(&self.local_absolute_path_str(base_dir))
+    }
+}
+
+/// local_hash_name temp 下临时文件名的前缀: (本地绝对路径的 MD5, 文件名)
+pub fn local_hash_name(local_path: &Path) -> (String, String) {
+    // 计算文件路径的MD5哈希
+    let absolute_path_str = local_path
+        .canonicalize()
+        .unwrap_or(local_path.to_path_buf())
+        .to_string_lossy()
+        .to_string();
+    let file_hash = format!("{:x}", md5::compute(absolute_path_str.as_bytes()));
+
+    // 获取文件名
+    let file_name = local_path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("unknown")
+        .to_string();
+
+    (file_hash, file_name)
 }
 
 impl Display for HttpdMetaReader {
@@ -61,20 +136,268 @@ impl Display for HttpdMetaReader {
     }
 }
 
-pub fn reader_parse(token: String) -> Result<HttpdMetaReader, Box<dyn std::error::Error>> {
-    let t = Token::<Header, Claims, _>::parse_unverified(&token).unwrap();
-    let claims = t.claims().clone();
+/// PathPolicy 签名或清单中的路径不安全时的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PathPolicy {
+    #[default]
+    Reject, // 拒绝, 文件记为失败
+    Sanitize, // 去掉不安全的部分后继续
+}
 
-    let download_path = claims
-        .private
-        .get("download_path")
-        .unwrap()
-        .to_string()
-        .trim_matches('"')
-        .to_string();
-    let base64_decoded = BASE64_STANDARD.decode(download_path)?;
-    let meta_reader: HttpdMetaReader = from_slice(&base64_decoded)?;
-    Ok(meta_reader)
+impl FromStr for PathPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(PathPolicy::Reject),
+            "sanitize" => Ok(PathPolicy::Sanitize),
+            other => Err(format!(
+                "unknown path policy: {}, expected reject or sanitize",
+                other
+            )),
+        }
+    }
+}
+
+/// PathError 不安全的路径
+#[derive(Debug, Clone)]
+pub struct PathError {
+    pub path: String,
+    pub reason: &'static str,
+}
+
+impl Display for PathError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "unsafe path {:?}: {}", self.path, self.reason)
+    }
+}
+
+impl std::error::Error for PathError {}
+
+/// safe_relative 按 / 和 \ 拆分路径, 检查每一级目录
+fn safe_relative(path: &str, policy: PathPolicy) -> Result<String, PathError> {
+    let mut parts = Vec::new();
+    for (idx, part) in path.split(['/', '\\']).enumerate() {
+        let reason = if part.contains('\0') {
+            Some("nul byte")
+        } else if part == ".." {
+            Some("parent component")
+        } else if idx == 0 && part.is_empty() && !path.is_empty() {
+            Some("absolute path")
+        } else if idx == 0 && is_drive(part) {
+            Some("windows drive prefix")
+        } else {
+            None
+        };
+
+        match (reason, policy) {
+            (Some(reason), PathPolicy::Reject) => {
+                return Err(PathError {
+                    path: path.to_string(),
+                    reason,
+                });
+            }
+            (Some(_), PathPolicy::Sanitize) => continue,
+            (None, _) if part.is_empty() || part == "." => continue,
+            (None, _) => parts.push(part),
+        }
+    }
+    Ok(parts.join("/"))
+}
+
+fn is_drive(part: &str) -> bool {
+    let bytes = part.as_bytes();
+    bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':'
+}
+
+/// reader_parse 解析下载签名, 不校验签名, 只检查有效期
+pub fn reader_parse(token: String) -> Result<HttpdMetaReader, TokenError> {
+    TokenVerifier::default().reader_parse(&token)
+}
+
+/// writer_parse 解析上传签名, 路径保存在 upload_path 中
+pub fn writer_parse(token: String) -> Result<HttpdMetaReader, TokenError> {
+    TokenVerifier::default().writer_parse(&token)
+}
+
+/// exp/nbf 允许的时钟误差(秒)
+const TOKEN_LEEWAY: u64 = 30;
+
+/// TokenError 解析签名的错误
+#[derive(Debug, Clone)]
+pub enum TokenError {
+    Malformed(String),              // 不是合法的 JWT
+    Signature(String),              // 签名校验失败
+    UnknownKey(Option<String>),     // JWKS 中没有 kid 对应的公钥
+    Key(String),                    // 校验密钥配置错误
+    Expired { exp: u64, now: u64 }, // 签名已过期
+    NotYetValid { nbf: u64, now: u64 },
+    MissingClaim(String),
+    InvalidClaim { claim: String, reason: String },
+}
+
+impl Display for TokenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenError::Malformed(reason) => write!(f, "token malformed: {}", reason),
+            TokenError::Signature(reason) => write!(f, "token signature invalid: {}", reason),
+            TokenError::UnknownKey(kid) => write!(f, "token key not found, kid: {:?}", kid),
+            TokenError::Key(reason) => write!(f, "token key invalid: {}", reason),
+            TokenError::Expired { exp, now } => {
+                write!(f, "token expired, exp: {}, now: {}", exp, now)
+            }
+            TokenError::NotYetValid { nbf, now } => {
+                write!(f, "token not yet valid, nbf: {}, now: {}", nbf, now)
+            }
+            TokenError::MissingClaim(claim) => write!(f, "token missing claim: {}", claim),
+            TokenError::InvalidClaim { claim, reason } => {
+                write!(f, "token claim {} invalid: {}", claim, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TokenError {}
+
+enum VerifyKey {
+    Hmac(Vec<u8>),
+    Jwks(JwkSet),
+}
+
+/// TokenVerifier 校验签名和 exp/nbf
+/// 没有配置密钥时不校验签名, 只检查有效期
+pub struct TokenVerifier {
+    key: Option<VerifyKey>,
+    leeway: u64,
+}
+
+impl Default for TokenVerifier {
+    fn default() -> Self {
+        TokenVerifier {
+            key: None,
+            leeway: TOKEN_LEEWAY,
+        }
+    }
+}
+
+impl TokenVerifier {
+    /// hmac 使用共享密钥校验 HS256/HS384/HS512 签名
+    pub fn hmac(secret: &[u8]) -> Self {
+        TokenVerifier {
+            key: Some(VerifyKey::Hmac(secret.to_vec())),
+            ..Default::default()
+        }
+    }
+
+    /// jwks 使用 JWKS 中的公钥校验签名, 按 kid 查找
+    pub fn jwks(jwks: &str) -> Result<Self, TokenError> {
+        let jwks: JwkSet =
+            serde_json::from_str(jwks).map_err(|err| TokenError::Key(err.to_string()))?;
+        Ok(TokenVerifier {
+            key: Some(VerifyKey::Jwks(jwks)),
+            ..Default::default()
+        })
+    }
+
+    pub fn jwks_file(path: impl AsRef<Path>) -> Result<Self, TokenError> {
+        let jwks = std::fs::read_to_string(path.as_ref())
+            .map_err(|err| TokenError::Key(format!("read {}: {}", path.as_ref().display(), err)))?;
+        TokenVerifier::jwks(&jwks)
+    }
+
+    pub fn is_verified(&self) -> bool {
+        self.key.is_some()
+    }
+
+    pub fn reader_parse(&self, token: &str) -> Result<HttpdMetaReader, TokenError> {
+        self.parse(token, "download_path")
+    }
+
+    pub fn writer_parse(&self, token: &str) -> Result<HttpdMetaReader, TokenError> {
+        self.parse(token, "upload_path")
+    }
+
+    fn parse(&self, token: &str, claim: &str) -> Result<HttpdMetaReader, TokenError> {
+        let claims = self.verify(token)?;
+
+        let now = get_current_timestamp();
+        if let Some(exp) = numeric_claim(&claims, "exp")?
+            && exp.saturating_add(self.leeway) < now
+        {
+            return Err(TokenError::Expired { exp, now });
+        }
+        if let Some(nbf) = numeric_claim(&claims, "nbf")?
+            && nbf > now.saturating_add(self.leeway)
+        {
+            return Err(TokenError::NotYetValid { nbf, now });
+        }
+
+        let invalid = |reason: String| TokenError::InvalidClaim {
+            claim: claim.to_string(),
+            reason,
+        };
+        let meta_path = claims
+            .get(claim)
+            .and_then(|value| value.as_str())
+            .ok_or_else(|| TokenError::MissingClaim(claim.to_string()))?;
+        let base64_decoded = BASE64_STANDARD
+            .decode(meta_path)
+            .map_err(|err| invalid(err.to_string()))?;
+        from_slice(&base64_decoded).map_err(|err| invalid(err.to_string()))
+    }
+
+    fn verify(&self, token: &str) -> Result<HashMap<String, Value>, TokenError> {
+        let header = decode_header(token).map_err(|err| TokenError::Malformed(err.to_string()))?;
+
+        let mut validation = Validation::new(header.alg);
+        let decoding_key = match &self.key {
+            None => {
+                validation.insecure_disable_signature_validation();
+                DecodingKey::from_secret(&[])
+            }
+            Some(VerifyKey::Hmac(secret)) => DecodingKey::from_secret(secret),
+            Some(VerifyKey::Jwks(jwks)) => {
+                let jwk = match &header.kid {
+                    Some(kid) => jwks.find(kid),
+                    None if jwks.keys.len() == 1 => jwks.keys.first(),
+                    None => None,
+                }
+                .ok_or_else(|| TokenError::UnknownKey(header.kid.clone()))?;
+                DecodingKey::from_jwk(jwk).map_err(|err| TokenError::Key(err.to_string()))?
+            }
+        };
+        // 有效期由 parse 检查, 错误中带上具体时间
+        validation.required_spec_claims.clear();
+        validation.validate_exp = false;
+        validation.validate_nbf = false;
+        validation.validate_aud = false;
+
+        match decode::<HashMap<String, Value>>(token, &decoding_key, &validation) {
+            Ok(token_data) => Ok(token_data.claims),
+            Err(err) => match err.kind() {
+                ErrorKind::InvalidSignature | ErrorKind::InvalidAlgorithm => {
+                    Err(TokenError::Signature(err.to_string()))
+                }
+                ErrorKind::InvalidKeyFormat | ErrorKind::InvalidRsaKey(_) => {
+                    Err(TokenError::Key(err.to_string()))
+                }
+                _ => Err(TokenError::Malformed(err.to_string())),
+            },
+        }
+    }
+}
+
+fn numeric_claim(claims: &HashMap<String, Value>, claim: &str) -> Result<Option<u64>, TokenError> {
+    match claims.get(claim) {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => match value.as_f64() {
+            Some(number) if number >= 0.0 => Ok(Some(number as u64)),
+            _ => Err(TokenError::InvalidClaim {
+                claim: claim.to_string(),
+                reason: format!("not a timestamp: {}", value),
+            }),
+        },
+    }
 }
 
 pub async fn check_file_meta(file_path: PathBuf) -> Option<u64> {
@@ -93,3 +416,84 @@ pub async fn check_file_meta(file_path: PathBuf) -> Option<u64> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{EncodingKey, Header, encode};
+
+    fn token(secret: &[u8], exp: Option<u64>) -> String {
+        let meta = serde_json::json!({"proto": "http", "path": "a/b.bin", "prefix": "ds"});
+        let mut claims = serde_json::Map::new();
+        claims.insert(
+            "download_path".to_string(),
+            Value::String(BASE64_STANDARD.encode(rmp_serde::to_vec_named(&meta).unwrap())),
+        );
+        if let Some(exp) = exp {
+            claims.insert("exp".to_string(), Value::from(exp));
+        }
+        encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(secret),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_token_verify() {
+        let now = get_current_timestamp();
+        let verifier = TokenVerifier::hmac(b"secret");
+
+        let meta = verifier
+            .reader_parse(&token(b"secret", Some(now + 3600)))
+            .unwrap();
+        assert_eq!(meta.local_relative_path(), PathBuf::from("ds/a/b.bin"));
+
+        let err = verifier.reader_parse(&token(b"other", None)).unwrap_err();
+        assert!(matches!(err, TokenError::Signature(_)));
+
+        // 不配置密钥时不校验签名, 但仍然检查有效期
+        let err = TokenVerifier::default()
+            .reader_parse(&token(b"other", Some(now - 3600)))
+            .unwrap_err();
+        assert!(matches!(err, TokenError::Expired { .. }));
+
+        let err = verifier.writer_parse(&token(b"secret", None)).unwrap_err();
+        assert!(matches!(err, TokenError::MissingClaim(_)));
+
+        let err = reader_parse("not-a-token".to_string()).unwrap_err();
+        assert!(matches!(err, TokenError::Malformed(_)));
+    }
+
+    #[test]
+    fn test_safe_path() {
+        let meta = |prefix: &str, path: &str| HttpdMetaReader {
+            proto: "http".to_string(),
+            path: path.to_string(),
+            prefix: prefix.to_string(),
+        };
+
+        let safe = meta("ds/./v1", "a//b.bin")
+            .safe_path(PathPolicy::Reject)
+            .unwrap();
+        assert_eq!(safe.local_relative_path(), PathBuf::from("ds/v1/a/b.bin"));
+
+        for (prefix, path) in [
+            ("ds", "../../etc/cron.d/x"),
+            ("/etc", "x"),
+            ("ds", "C:\\Windows\\x"),
+            ("ds", "a\\..\\..\\x"),
+            ("ds", "a\0b"),
+            ("ds", ".."),
+        ] {
+            assert!(meta(prefix, path).safe_path(PathPolicy::Reject).is_err());
+        }
+
+        let safe = meta("/ds", "../../etc/cron.d/x")
+            .safe_path(PathPolicy::Sanitize)
+            .unwrap();
+        assert_eq!(safe.local_relative_path(), PathBuf::from("ds/etc/cron.d/x"));
+        assert!(meta("ds", "../..").safe_path(PathPolicy::Sanitize).is_err());
+    }
+}
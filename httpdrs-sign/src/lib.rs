@@ -1,12 +1,30 @@
-use crate::reader::{ReaderRequest, ReaderResponse};
+use reqwest::StatusCode;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
 
+use std::sync::Arc;
+
+use crate::error::HttpdError;
+use crate::jwtsign::TokenVerifier;
+use crate::reader::{ReaderBatchRequest, ReaderBatchResponse, ReaderRequest, ReaderResponse};
+use crate::retry::RetryPolicy;
+use crate::writer::{MultipartAction, WriterRequest, WriterResponse};
+
+pub mod error;
 pub mod jwtsign;
 pub mod reader;
+pub mod retry;
+pub mod s3;
+pub mod signer;
+pub mod writer;
 
 pub struct SignatureClient {
     client: reqwest::Client,
     network: String,
     reader_presign: String,
+    writer_presign: String,
+    verifier: Arc<TokenVerifier>, // 解析 sign 时校验签名和有效期
+    retry: RetryPolicy,           // 签名请求的重试策略
 }
 
 impl SignatureClient {
@@ -16,82 +34,206 @@ impl SignatureClient {
             client,
             network,
             reader_presign: reader_presign.to_string(),
+            writer_presign: "".to_string(),
+            verifier: Arc::new(TokenVerifier::default()),
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    pub fn new_writer(writer_presign: String, network: String) -> Self {
+        let client = reqwest::Client::new();
+        SignatureClient {
+            client,
+            network,
+            reader_presign: "".to_string(),
+            writer_presign,
+            verifier: Arc::new(TokenVerifier::default()),
+            retry: RetryPolicy::default(),
         }
     }
+
+    pub fn with_verifier(mut self, verifier: Arc<TokenVerifier>) -> Self {
+        self.verifier = verifier;
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn verifier(&self) -> &TokenVerifier {
+        &self.verifier
+    }
     pub async fn ping_get(&self) -> Result<String, reqwest::Error> {
         let response = self.client.get("https://www.baidu.com").send().await?;
         let text = response.text().await?;
         Ok(text)
     }
 
-    pub async fn reader_get(
-        &self,
-        sign_data: String,
-    ) -> Result<ReaderResponse, Box<dyn std::error::Error>> {
+    pub async fn reader_get(&self, sign_data: String) -> Result<ReaderResponse, HttpdError> {
         let req = ReaderRequest::new(self.network.as_str(), sign_data);
         let reader_presign = self.reader_presign.as_str();
         tracing::debug!("reader_presign: {}, req: {}", reader_presign, req);
 
-        let max_retries = 10;
-        let mut retry_count = 0;
+        self.presign_post(reader_presign, &req).await
+    }
+
+    /// reader_batch_get 批量获取下载链接, 请求 {reader_presign}/batch
+    /// Ok(None) 表示签名服务不支持批量接口, 调用方需要回退到单个请求
+    pub async fn reader_batch_get(
+        &self,
+        sign_data: Vec<String>,
+    ) -> Result<Option<ReaderBatchResponse>, HttpdError> {
+        let req = ReaderBatchRequest::new(self.network.as_str(), sign_data);
+        let reader_batch = format!("{}/batch", self.reader_presign);
+        tracing::debug!(
+            "reader_batch: {}, req: {}",
+            reader_batch,
+            req.download_signs.len()
+        );
+
+        let resp = self
+            .client
+            .post(&reader_batch)
+            .json(&req)
+            .send()
+            .await
+            .map_err(|err| self.presign_error(err.into()))?;
+        if matches!(
+            resp.status(),
+            StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED
+        ) {
+            tracing::warn!("reader_batch: {}, status: {}", reader_batch, resp.status());
+            return Ok(None);
+        }
+        if !resp.status().is_success() {
+            return Err(self.presign_error(HttpdError::status(resp.status(), reader_batch)));
+        }
+
+        let resp_data: ReaderBatchResponse = resp.json().await.map_err(|err| {
+            HttpdError::Presign(format!("decode response: {}", err.without_url()))
+        })?;
+        Ok(Some(resp_data))
+    }
+
+    pub async fn writer_get(&self, sign_data: String) -> Result<WriterResponse, HttpdError> {
+        let req = WriterRequest::new(self.network.as_str(), sign_data);
+        let writer_presign = self.writer_presign.as_str();
+        tracing::debug!("writer_presign: {}, req: {}", writer_presign, req);
+
+        self.presign_post(writer_presign, &req).await
+    }
+
+    pub async fn writer_multipart_get(
+        &self,
+        sign_data: String,
+        action: MultipartAction,
+        upload_id: String,
+        part_number: Option<u64>,
+    ) -> Result<WriterResponse, HttpdError> {
+        let req = WriterRequest::multipart(
+            self.network.as_str(),
+            sign_data,
+            action,
+            upload_id,
+            part_number,
+        );
+        let writer_presign = self.writer_presign.as_str();
+        tracing::debug!(
+            "writer_presign: {}, req: {}, action: {:?}, part: {:?}",
+            writer_presign,
+            req,
+            action,
+            part_number
+        );
+
+        self.presign_post(writer_presign, &req).await
+    }
+
+    /// presign_post 请求签名服务, 暂时不可用时按重试策略重试, code != 0 是签名被拒绝, 不重试
+    async fn presign_post<T, R>(&self, presign_api: &str, req: &T) -> Result<R, HttpdError>
+    where
+        T: Serialize + ?Sized,
+        R: DeserializeOwned + PresignResponse,
+    {
+        let mut attempt = 0;
         loop {
-            match self.client.post(reader_presign).json(&req).send().await {
-                Ok(resp) => {
-                    let resp_data: ReaderResponse = match resp.json().await {
-                        Ok(data) => data,
-                        Err(e) => {
-                            retry_count += 1;
-                            if retry_count >= max_retries {
-                                tracing::error!(
-                                    "reader_presign: {}, max_retries: {}",
-                                    reader_presign,
-                                    e
-                                );
-                                return Err("reader_presign: max_retries".into());
-                            }
-                            tokio::time::sleep(tokio::time::Duration::from_millis(
-                                1000 * retry_count,
-                            ))
-                            .await;
-                            continue;
-                        }
-                    };
-
-                    match resp_data.code {
-                        0 => return Ok(resp_data),
-                        _ => {
-                            retry_count += 1;
-                            if retry_count >= max_retries {
-                                tracing::error!(
-                                    "reader_presign: {}, retrying... {}",
-                                    reader_presign,
-                                    retry_count
-                                );
-                                return Err("reader_presign: status_code err".into());
-                            }
-                            tokio::time::sleep(tokio::time::Duration::from_millis(
-                                1000 * retry_count,
-                            ))
-                            .await;
-                            continue;
-                        }
-                    }
-                }
-                Err(e) => {
-                    retry_count += 1;
-                    if retry_count >= max_retries {
-                        tracing::error!("reader_presign: {}, error: {}", reader_presign, e);
-                        return Err(e.to_string().into());
-                    }
-                    tokio::time::sleep(tokio::time::Duration::from_millis(1000 * retry_count))
-                        .await;
-                }
+            attempt += 1;
+            let err = match self.presign_send::<T, R>(presign_api, req).await {
+                Ok(resp_data) if resp_data.code() == 0 => return Ok(resp_data),
+                Ok(resp_data) => HttpdError::Presign(format!(
+                    "code: {}, message: {}",
+                    resp_data.code(),
+                    resp_data.message()
+                )),
+                Err(err) => err,
+            };
+
+            if attempt >= self.retry.range_attempts || !self.retry.is_retryable(&err) {
+                tracing::error!("presign: {}, attempt: {}, {}", presign_api, attempt, err);
+                return Err(err);
             }
+            tracing::warn!("presign: {}, retrying... {}, {}", presign_api, attempt, err);
+            tokio::time::sleep(self.retry.backoff(attempt)).await;
+        }
+    }
+
+    async fn presign_send<T, R>(&self, presign_api: &str, req: &T) -> Result<R, HttpdError>
+    where
+        T: Serialize + ?Sized,
+        R: DeserializeOwned,
+    {
+        let resp = self
+            .client
+            .post(presign_api)
+            .json(req)
+            .send()
+            .await
+            .map_err(|err| self.presign_error(err.into()))?;
+        if !resp.status().is_success() {
+            return Err(self.presign_error(HttpdError::status(resp.status(), presign_api)));
+        }
+        resp.json()
+            .await
+            .map_err(|err| HttpdError::Presign(format!("decode response: {}", err.without_url())))
+    }
+
+    /// presign_error 签名服务的网络错误和可以重试的状态码是暂时不可用, 其他状态码是签名被拒绝
+    fn presign_error(&self, err: HttpdError) -> HttpdError {
+        let retryable = self.retry.is_retryable(&err);
+        let reason = match err {
+            HttpdError::Network(reason) => reason,
+            HttpdError::Status { status, message } => format!("status: {}, {}", status, message),
+            err => return err,
+        };
+        match retryable {
+            true => HttpdError::PresignUnavailable(reason),
+            false => HttpdError::Presign(reason),
         }
     }
+}
+
+pub trait PresignResponse {
+    fn code(&self) -> i32;
+    fn message(&self) -> &str;
+}
+
+impl PresignResponse for ReaderResponse {
+    fn code(&self) -> i32 {
+        self.code
+    }
+    fn message(&self) -> &str {
+        &self.message
+    }
+}
 
-    pub async fn writer_get(&self, sign_data: String) -> Result<String, reqwest::Error> {
-        Ok(sign_data)
+impl PresignResponse for WriterResponse {
+    fn code(&self) -> i32 {
+        self.code
+    }
+    fn message(&self) -> &str {
+        &self.message
     }
 }
 
@@ -108,4 +250,23 @@ mod tests {
         let result = signature.ping_get().await.unwrap();
         println!("{}", result)
     }
+
+    #[test]
+    fn test_presign_error() {
+        let signature = SignatureClient::new(String::new(), "public".to_string());
+        let retry = RetryPolicy::default();
+
+        // 连接失败和 5xx 是暂时不可用, 按重试策略重试
+        let err = signature.presign_error(HttpdError::Network("reset".to_string()));
+        assert!(matches!(err, HttpdError::PresignUnavailable(_)), "{}", err);
+        assert!(retry.is_retryable(&err));
+        let err = signature.presign_error(HttpdError::status(StatusCode::BAD_GATEWAY, "api"));
+        assert!(matches!(err, HttpdError::PresignUnavailable(_)), "{}", err);
+
+        // 其他状态码是签名被拒绝, 不重试
+        let err = signature.presign_error(HttpdError::status(StatusCode::NOT_FOUND, "api"));
+        assert!(matches!(err, HttpdError::Presign(_)), "{}", err);
+        assert!(!retry.is_retryable(&err));
+        assert_eq!(err.kind(), "presign");
+    }
 }
@@ -26,6 +26,8 @@ impl Display for ReaderRequest<'_> {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ReaderData {
     pub endpoint: String,
+    #[serde(default)]
+    pub expires_in: Option<u64>, // 链接有效期(秒), 签名服务可以不返回
 }
 
 #[allow(dead_code)]
@@ -35,3 +37,29 @@ pub struct ReaderResponse {
     pub message: String,
     pub data: ReaderData,
 }
+
+/// 批量签名请求, 响应中的 endpoint 和 download_signs 顺序一致
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReaderBatchRequest<'a> {
+    #[serde(borrow)]
+    pub network: &'a str,
+    pub download_signs: Vec<String>,
+}
+
+impl ReaderBatchRequest<'_> {
+    pub fn new(network: &'_ str, download_signs: Vec<String>) -> ReaderBatchRequest<'_> {
+        ReaderBatchRequest {
+            network,
+            download_signs,
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReaderBatchResponse {
+    pub code: i32,
+    pub message: String,
+    #[serde(default)]
+    pub data: Vec<ReaderData>,
+}
@@ -0,0 +1,133 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::str::FromStr;
+use std::time::Duration;
+
+use reqwest::StatusCode;
+
+use crate::error::HttpdError;
+
+/// StatusClass 可以重试的状态码, 单个状态码(429)或者一类(5xx)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusClass {
+    Code(u16),
+    Class(u16), // 5 -> 500..=599
+}
+
+impl StatusClass {
+    pub fn matches(&self, status: StatusCode) -> bool {
+        match self {
+            StatusClass::Code(code) => status.as_u16() == *code,
+            StatusClass::Class(class) => status.as_u16() / 100 == *class,
+        }
+    }
+}
+
+impl FromStr for StatusClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let invalid = || format!("invalid status class: {}, expected 5xx or 429", s);
+        match s.to_ascii_lowercase().strip_suffix("xx") {
+            Some(class) => match class.parse::<u16>() {
+                Ok(class @ 1..=5) => Ok(StatusClass::Class(class)),
+                _ => Err(invalid()),
+            },
+            None => match s.parse::<u16>() {
+                Ok(code @ 100..=599) => Ok(StatusClass::Code(code)),
+                _ => Err(invalid()),
+            },
+        }
+    }
+}
+
+/// RetryPolicy 请求和文件的重试策略
+/// range_attempts: 每个分片(以及每次签名请求)最多请求的次数
+/// file_attempts: 每个文件最多下载的次数, 分片失败或校验失败后重新下载缺失的部分
+/// 第 n 次重试前等待 base_delay * 2^(n-1), 不超过 max_delay, 再随机取其中的 [1/2, 1]
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub range_attempts: u32,
+    pub file_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub retry_status: Vec<StatusClass>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            range_attempts: 10,
+            file_attempts: 3,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+            retry_status: vec![
+                StatusClass::Class(5),
+                StatusClass::Code(408),
+                StatusClass::Code(429),
+            ],
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// is_retryable 网络错误, 签名服务暂时不可用和配置的状态码可以重试
+    /// 签名配置错误或被拒绝, token, 路径, 本地读写错误重试也不会成功
+    pub fn is_retryable(&self, err: &HttpdError) -> bool {
+        match err {
+            HttpdError::Network(_) | HttpdError::PresignUnavailable(_) => true,
+            HttpdError::Status { status, .. } => {
+                self.retry_status.iter().any(|class| class.matches(*status))
+            }
+            _ => false,
+        }
+    }
+
+    /// backoff 第 attempt 次失败后的等待时间, attempt 从 1 开始
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_delay);
+        let half = delay / 2;
+        half + half.mul_f64(jitter())
+    }
+}
+
+/// jitter 返回 [0, 1) 之间的随机数, 避免大量分片同时重试
+fn jitter() -> f64 {
+    let random = RandomState::new().build_hasher().finish();
+    (random >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_policy() {
+        let policy = RetryPolicy::default();
+        assert!(policy.is_retryable(&HttpdError::Network("reset".to_string())));
+        assert!(policy.is_retryable(&HttpdError::status(StatusCode::BAD_GATEWAY, "")));
+        assert!(policy.is_retryable(&HttpdError::status(StatusCode::TOO_MANY_REQUESTS, "")));
+        assert!(!policy.is_retryable(&HttpdError::status(StatusCode::NOT_FOUND, "")));
+        assert!(!policy.is_retryable(&HttpdError::Cancelled));
+        assert!(policy.is_retryable(&HttpdError::PresignUnavailable("reset".to_string())));
+        assert!(!policy.is_retryable(&HttpdError::Presign("endpoint is empty".to_string())));
+
+        for attempt in 1..=10 {
+            let delay = policy.backoff(attempt);
+            let max = policy
+                .base_delay
+                .saturating_mul(1 << (attempt - 1))
+                .min(policy.max_delay);
+            assert!(delay >= max / 2 && delay <= max, "{:?} {:?}", delay, max);
+        }
+
+        assert_eq!("5xx".parse(), Ok(StatusClass::Class(5)));
+        assert_eq!("429".parse(), Ok(StatusClass::Code(429)));
+        assert!("6xx".parse::<StatusClass>().is_err());
+        assert!("abc".parse::<StatusClass>().is_err());
+    }
+}
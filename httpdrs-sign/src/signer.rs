@@ -0,0 +1,259 @@
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use reqwest::Url;
+
+use crate::SignatureClient;
+use crate::error::HttpdError;
+use crate::jwtsign::{HttpdMetaReader, TokenError, TokenVerifier, reader_parse, writer_parse};
+use crate::retry::RetryPolicy;
+use crate::s3::S3Signer;
+use crate::writer::{MultipartAction, WriterResponse};
+
+/// Presigned 签名结果: 请求链接, 请求时需要附带的 header, 链接有效期(秒)
+#[derive(Debug, Clone, Default)]
+pub struct Presigned {
+    pub endpoint: String,
+    pub headers: Vec<(String, String)>,
+    pub expires_in: Option<u64>,
+}
+
+impl Presigned {
+    pub fn new(endpoint: String) -> Self {
+        Presigned {
+            endpoint,
+            ..Default::default()
+        }
+    }
+}
+
+/// Signer 把清单中的 sign 转换成可以直接请求的链接
+pub trait Signer: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// parse 解析 sign 得到文件的本地路径, 默认 sign 是 JWT, 只检查有效期
+    fn parse(&self, sign: &str) -> Result<HttpdMetaReader, HttpdError> {
+        Ok(reader_parse(sign.to_string())?)
+    }
+
+    fn presign_read<'a>(&'a self, sign: &'a str) -> BoxFuture<'a, Result<Presigned, HttpdError>>;
+
+    /// presign_read_batch 批量签名, 结果和 signs 顺序一致
+    /// Ok(None) 表示不支持批量签名, 调用方回退到单个请求
+    fn presign_read_batch<'a>(
+        &'a self,
+        signs: &'a [String],
+    ) -> BoxFuture<'a, Result<Option<Vec<Presigned>>, HttpdError>> {
+        let _ = signs;
+        Box::pin(async { Ok(None) })
+    }
+
+    /// parse_write 解析上传 sign 得到文件的本地路径
+    fn parse_write(&self, sign: &str) -> Result<HttpdMetaReader, HttpdError> {
+        Ok(writer_parse(sign.to_string())?)
+    }
+
+    /// presign_write 获取上传链接
+    fn presign_write<'a>(&'a self, sign: &'a str) -> BoxFuture<'a, Result<Presigned, HttpdError>> {
+        let _ = sign;
+        Box::pin(async move {
+            Err(HttpdError::Presign(format!(
+                "signer {} does not support upload",
+                self.name()
+            )))
+        })
+    }
+
+    /// presign_multipart 获取分片上传各阶段的链接, part_number 从 1 开始
+    fn presign_multipart<'a>(
+        &'a self,
+        sign: &'a str,
+        action: MultipartAction,
+        upload_id: &'a str,
+        part_number: Option<u64>,
+    ) -> BoxFuture<'a, Result<Presigned, HttpdError>> {
+        let _ = (sign, action, upload_id, part_number);
+        Box::pin(async move {
+            Err(HttpdError::Presign(format!(
+                "signer {} does not support upload",
+                self.name()
+            )))
+        })
+    }
+}
+
+/// HTTP 签名服务
+impl Signer for SignatureClient {
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    fn parse(&self, sign: &str) -> Result<HttpdMetaReader, HttpdError> {
+        Ok(self.verifier().reader_parse(sign)?)
+    }
+
+    fn parse_write(&self, sign: &str) -> Result<HttpdMetaReader, HttpdError> {
+        Ok(self.verifier().writer_parse(sign)?)
+    }
+
+    fn presign_read<'a>(&'a self, sign: &'a str) -> BoxFuture<'a, Result<Presigned, HttpdError>> {
+        Box::pin(async move {
+            let reader = self.reader_get(sign.to_string()).await?;
+            if reader.data.endpoint.is_empty() {
+                return Err(HttpdError::Presign("endpoint is empty".to_string()));
+            }
+            Ok(Presigned {
+                endpoint: reader.data.endpoint,
+                headers: Vec::new(),
+                expires_in: reader.data.expires_in,
+            })
+        })
+    }
+
+    fn presign_read_batch<'a>(
+        &'a self,
+        signs: &'a [String],
+    ) -> BoxFuture<'a, Result<Option<Vec<Presigned>>, HttpdError>> {
+        Box::pin(async move {
+            let Some(resp) = self.reader_batch_get(signs.to_vec()).await? else {
+                return Ok(None);
+            };
+            if resp.code != 0 {
+                return Err(HttpdError::Presign(format!(
+                    "batch code: {}, message: {}",
+                    resp.code, resp.message
+                )));
+            }
+            let presigned = resp
+                .data
+                .into_iter()
+                .map(|data| Presigned {
+                    endpoint: data.endpoint,
+                    headers: Vec::new(),
+                    expires_in: data.expires_in,
+                })
+                .collect();
+            Ok(Some(presigned))
+        })
+    }
+
+    fn presign_write<'a>(&'a self, sign: &'a str) -> BoxFuture<'a, Result<Presigned, HttpdError>> {
+        Box::pin(async move {
+            let writer = self.writer_get(sign.to_string()).await?;
+            writer_presigned(writer)
+        })
+    }
+
+    fn presign_multipart<'a>(
+        &'a self,
+        sign: &'a str,
+        action: MultipartAction,
+        upload_id: &'a str,
+        part_number: Option<u64>,
+    ) -> BoxFuture<'a, Result<Presigned, HttpdError>> {
+        Box::pin(async move {
+            let writer = self
+                .writer_multipart_get(sign.to_string(), action, upload_id.to_string(), part_number)
+                .await?;
+            writer_presigned(writer)
+        })
+    }
+}
+
+fn writer_presigned(writer: WriterResponse) -> Result<Presigned, HttpdError> {
+    if writer.data.endpoint.is_empty() {
+        return Err(HttpdError::Presign("endpoint is empty".to_string()));
+    }
+    Ok(Presigned::new(writer.data.endpoint))
+}
+
+/// PassthroughSigner 清单中的 sign 已经是可以直接下载的链接
+/// 本地路径: {host}/{url path}
+pub struct PassthroughSigner;
+
+impl Signer for PassthroughSigner {
+    fn name(&self) -> &'static str {
+        "passthrough"
+    }
+
+    fn parse(&self, sign: &str) -> Result<HttpdMetaReader, HttpdError> {
+        let invalid = |reason: &str| TokenError::Malformed(format!("{}: {}", reason, sign));
+        let url = Url::parse(sign).map_err(|err| invalid(&err.to_string()))?;
+        let prefix = url.host_str().ok_or_else(|| invalid("url without host"))?;
+        let prefix = prefix.to_string();
+        let path = url.path().trim_start_matches('/').to_string();
+        if path.is_empty() {
+            return Err(invalid("url without path").into());
+        }
+        Ok(HttpdMetaReader {
+            proto: url.scheme().to_string(),
+            path,
+            prefix,
+        })
+    }
+
+    fn presign_read<'a>(&'a self, sign: &'a str) -> BoxFuture<'a, Result<Presigned, HttpdError>> {
+        Box::pin(async move { Ok(Presigned::new(sign.to_string())) })
+    }
+}
+
+/// SignerConfig 选择签名方式
+#[derive(Debug, Clone)]
+pub enum SignerConfig {
+    Http {
+        presign_api: String,
+        network: String,
+    },
+    S3 {
+        access_key: String,
+        secret_key: String,
+        region: String,
+        endpoint: String,
+        virtual_host: bool,
+    },
+    Passthrough,
+}
+
+impl SignerConfig {
+    /// build 下载使用的签名, Http 的 presign_api 是下载签名接口
+    /// verifier 用于解析 JWT 格式的 sign, retry 是签名服务的重试策略
+    pub fn build(self, verifier: TokenVerifier, retry: RetryPolicy) -> Arc<dyn Signer> {
+        self.build_with(SignatureClient::new, verifier, retry)
+    }
+
+    /// build_writer 上传使用的签名, Http 的 presign_api 是上传签名接口
+    pub fn build_writer(self, verifier: TokenVerifier, retry: RetryPolicy) -> Arc<dyn Signer> {
+        self.build_with(SignatureClient::new_writer, verifier, retry)
+    }
+
+    fn build_with(
+        self,
+        http: fn(String, String) -> SignatureClient,
+        verifier: TokenVerifier,
+        retry: RetryPolicy,
+    ) -> Arc<dyn Signer> {
+        let verifier = Arc::new(verifier);
+        match self {
+            SignerConfig::Http {
+                presign_api,
+                network,
+            } => Arc::new(
+                http(presign_api, network)
+                    .with_verifier(verifier)
+                    .with_retry(retry),
+            ),
+            SignerConfig::S3 {
+                access_key,
+                secret_key,
+                region,
+                endpoint,
+                virtual_host,
+            } => Arc::new(
+                S3Signer::new(access_key, secret_key, region, endpoint)
+                    .with_virtual_host(virtual_host)
+                    .with_verifier(verifier),
+            ),
+            SignerConfig::Passthrough => Arc::new(PassthroughSigner),
+        }
+    }
+}
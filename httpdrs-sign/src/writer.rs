@@ -0,0 +1,74 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WriterRequest<'a> {
+    #[serde(borrow)]
+    pub network: &'a str,
+    pub upload_sign: String,
+
+    // 分片上传时使用, 普通上传不发送这些字段
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action: Option<MultipartAction>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub upload_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub part_number: Option<u64>,
+}
+
+impl WriterRequest<'_> {
+    pub fn new(network: &'_ str, upload_sign: String) -> WriterRequest<'_> {
+        WriterRequest {
+            network,
+            upload_sign,
+            action: None,
+            upload_id: "".to_string(),
+            part_number: None,
+        }
+    }
+
+    pub fn multipart(
+        network: &'_ str,
+        upload_sign: String,
+        action: MultipartAction,
+        upload_id: String,
+        part_number: Option<u64>,
+    ) -> WriterRequest<'_> {
+        WriterRequest {
+            network,
+            upload_sign,
+            action: Some(action),
+            upload_id,
+            part_number,
+        }
+    }
+}
+
+/// 分片上传的阶段, 签名服务为每个阶段返回对应的 S3 预签名地址
+/// initiate: POST ?uploads, part: PUT ?partNumber&uploadId, complete: POST ?uploadId
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MultipartAction {
+    Initiate,
+    Part,
+    Complete,
+}
+
+impl Display for WriterRequest<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} -> {}", self.network, self.upload_sign)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WriterData {
+    pub endpoint: String,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WriterResponse {
+    pub code: i32,
+    pub message: String,
+    pub data: WriterData,
+}
@@ -0,0 +1,644 @@
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use reqwest::Client;
+use serde::Deserialize;
+
+use httpdrs_core::httpd::{PathPolicy, RetryPolicy, SignerConfig, StatusClass, TokenVerifier};
+use httpdrs_core::request::DEFAULT_CHUNK_SIZE;
+
+/// 签名服务的默认地址, signer http 并且没有配置 presign_api 时使用
+pub const DEFAULT_PRESIGN_API: &str =
+    "http://internal-data.baai.ac.cn/api/v1/storage/sign/download/presign";
+
+/// {use_loc} 下的配置文件名
+pub const CONFIG_FILE: &str = "ihttpd.toml";
+
+/// 环境变量前缀, 例如 IHTTPD_MAX_PARALLEL, IHTTPD_DOWNLOAD_CHUNK_SIZE
+/// IHTTPD_CONFIG 指定额外的配置文件
+pub const ENV_PREFIX: &str = "IHTTPD_";
+
+/// 下载时并发的默认下限, 不超过 max_parallel
+pub const DEFAULT_MIN_PARALLEL: usize = 8;
+
+/// S3 分片上传的分片大小范围 (最后一片除外)
+const UPLOAD_MIN_CHUNK_SIZE: u64 = 1024 * 1024 * 5;
+const UPLOAD_MAX_CHUNK_SIZE: u64 = 1024 * 1024 * 1024 * 5;
+
+/// SignerArgs 选择签名方式的参数, Python 接口和命令行共用
+/// signer: http(签名服务), s3(本地 SigV4 签名), passthrough(清单中已经是下载链接)
+#[derive(Debug, Clone, Default)]
+pub struct SignerArgs {
+    pub signer: String,
+    pub access_key: Option<String>,
+    pub secret_key: Option<String>,
+    pub region: Option<String>,
+    pub endpoint: Option<String>,
+    pub virtual_host: bool,
+    pub jwt_secret: Option<String>, // 校验 JWT 签名的 HMAC 密钥
+    pub jwks_path: Option<String>,  // 校验 JWT 签名的 JWKS 公钥文件
+}
+
+impl SignerArgs {
+    pub fn config(
+        self,
+        presign_api: String,
+        network: String,
+    ) -> Result<(SignerConfig, TokenVerifier), String> {
+        let verifier = match (&self.jwt_secret, &self.jwks_path) {
+            (Some(_), Some(_)) => {
+                return Err("jwt_secret and jwks_path can not be used together".to_string());
+            }
+            (Some(secret), None) => TokenVerifier::hmac(secret.as_bytes()),
+            (None, Some(jwks_path)) => {
+                TokenVerifier::jwks_file(jwks_path).map_err(|err| err.to_string())?
+            }
+            (None, None) => TokenVerifier::default(),
+        };
+        Ok((self.signer_config(presign_api, network)?, verifier))
+    }
+
+    fn signer_config(self, presign_api: String, network: String) -> Result<SignerConfig, String> {
+        match self.signer.as_str() {
+            "http" => Ok(SignerConfig::Http {
+                presign_api,
+                network,
+            }),
+            "s3" => match (self.access_key, self.secret_key, self.endpoint) {
+                (Some(access_key), Some(secret_key), Some(endpoint)) => Ok(SignerConfig::S3 {
+                    access_key,
+                    secret_key,
+                    region: self.region.unwrap_or("us-east-1".to_string()),
+                    endpoint,
+                    virtual_host: self.virtual_host,
+                }),
+                _ => Err("signer s3 requires access_key, secret_key and endpoint".to_string()),
+            },
+            "passthrough" => Ok(SignerConfig::Passthrough),
+            other => Err(format!(
+                "unknown signer: {}, expected http, s3 or passthrough",
+                other
+            )),
+        }
+    }
+}
+
+/// path_policy 签名或清单中的路径不安全时: reject(记为失败), sanitize(去掉不安全的部分)
+pub fn path_policy(value: &str) -> Result<PathPolicy, String> {
+    value.parse()
+}
+
+/// retry_policy 未设置的参数使用默认值, retry_status 为逗号分隔的状态码, 例如 "5xx,408,429"
+pub fn retry_policy(
+    range_attempts: Option<u32>,
+    file_attempts: Option<u32>,
+    retry_status: Option<&str>,
+) -> Result<RetryPolicy, String> {
+    let mut retry = RetryPolicy::default();
+    if let Some(range_attempts) = range_attempts {
+        retry.range_attempts = range_attempts.max(1);
+    }
+    if let Some(file_attempts) = file_attempts {
+        retry.file_attempts = file_attempts.max(1);
+    }
+    if let Some(retry_status) = retry_status {
+        retry.retry_status = retry_status
+            .split(',')
+            .filter(|value| !value.trim().is_empty())
+            .map(|value| value.parse::<StatusClass>())
+            .collect::<Result<_, _>>()?;
+    }
+    Ok(retry)
+}
+
+/// ConfigLayer 一层配置: 配置文件, 环境变量或调用参数, 未设置的项使用下一层
+/// 配置文件中的名字和命令行参数相同, 使用下划线, 例如 max_parallel = 64
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigLayer {
+    pub use_loc: Option<String>,
+    pub presign_api: Option<String>,
+    pub network: Option<String>,
+    #[serde(alias = "bandwidth")]
+    pub max_bandwidth: Option<u64>, // MB/s
+    #[serde(alias = "parallel")]
+    pub max_parallel: Option<u64>,
+    pub min_parallel: Option<u64>, // 只用于下载
+    pub preallocate: Option<bool>,
+    pub signer: Option<String>,
+    pub s3_access_key: Option<String>,
+    pub s3_secret_key: Option<String>,
+    pub s3_region: Option<String>,
+    pub s3_endpoint: Option<String>,
+    pub s3_virtual_host: Option<bool>,
+    pub jwt_secret: Option<String>,
+    pub jwks_path: Option<String>,
+    pub path_policy: Option<String>,
+    pub range_attempts: Option<u32>,
+    pub file_attempts: Option<u32>,
+    pub retry_status: Option<String>,
+    pub download: TransferLayer, // [download]
+    pub upload: TransferLayer,   // [upload]
+}
+
+/// TransferLayer 下载或上传的分片, 文件并发和 HTTP 客户端参数, 时间单位为秒
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct TransferLayer {
+    pub chunk_size: Option<u64>,
+    pub min_chunk_size: Option<u64>, // 只用于下载
+    pub max_chunk_size: Option<u64>, // 只用于下载
+    pub max_files: Option<u64>,
+    pub merge_buffer: Option<u64>, // 只用于下载
+    pub connect_timeout: Option<u64>,
+    pub timeout: Option<u64>,
+    pub pool_idle_timeout: Option<u64>,
+    pub pool_max_idle_per_host: Option<u64>,
+    pub user_agent: Option<String>,
+}
+
+fn parse<T: FromStr>(value: &str) -> Result<Option<T>, String>
+where
+    T::Err: std::fmt::Display,
+{
+    value
+        .trim()
+        .parse::<T>()
+        .map(Some)
+        .map_err(|err| format!("{}: {}", value, err))
+}
+
+impl ConfigLayer {
+    /// from_file 读取 toml 配置文件
+    pub fn from_file(path: &Path) -> Result<ConfigLayer, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|err| format!("read config {}: {}", path.display(), err))?;
+        toml::from_str(&content).map_err(|err| format!("parse config {}: {}", path.display(), err))
+    }
+
+    /// from_env 读取 IHTTPD_* 环境变量, 其他变量忽略
+    /// 不认识的变量或者不是 UTF-8 的值返回错误, 错误中带有变量名
+    pub fn from_env(
+        vars: impl IntoIterator<Item = (OsString, OsString)>,
+    ) -> Result<ConfigLayer, String> {
+        let mut layer = ConfigLayer::default();
+        for (name, value) in vars {
+            if !name.as_encoded_bytes().starts_with(ENV_PREFIX.as_bytes()) {
+                continue;
+            }
+            let Some(name) = name.to_str() else {
+                return Err(format!(
+                    "environment {}: name is not valid UTF-8",
+                    name.display()
+                ));
+            };
+            let key = &name[ENV_PREFIX.len()..];
+            // IHTTPD_CONFIG 是文件路径, 在 Config::load 中读取
+            if key == "CONFIG" {
+                continue;
+            }
+            let Some(value) = value.to_str() else {
+                return Err(format!("environment {}: value is not valid UTF-8", name));
+            };
+            layer
+                .set(&key.to_lowercase(), value)
+                .map_err(|err| format!("environment {}: {}", name, err))?;
+        }
+        Ok(layer)
+    }
+
+    /// set 按名字设置一项, [download] 和 [upload] 中的项加上前缀, 例如 download_chunk_size
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let text = || Some(value.to_string());
+        match key {
+            "use_loc" => self.use_loc = text(),
+            "presign_api" => self.presign_api = text(),
+            "network" => self.network = text(),
+            "max_bandwidth" | "bandwidth" => self.max_bandwidth = parse(value)?,
+            "max_parallel" | "parallel" => self.max_parallel = parse(value)?,
+            "min_parallel" => self.min_parallel = parse(value)?,
+            "preallocate" => self.preallocate = parse(value)?,
+            "signer" => self.signer = text(),
+            "s3_access_key" => self.s3_access_key = text(),
+            "s3_secret_key" => self.s3_secret_key = text(),
+            "s3_region" => self.s3_region = text(),
+            "s3_endpoint" => self.s3_endpoint = text(),
+            "s3_virtual_host" => self.s3_virtual_host = parse(value)?,
+            "jwt_secret" => self.jwt_secret = text(),
+            "jwks_path" => self.jwks_path = text(),
+            "path_policy" => self.path_policy = text(),
+            "range_attempts" => self.range_attempts = parse(value)?,
+            "file_attempts" => self.file_attempts = parse(value)?,
+            "retry_status" => self.retry_status = text(),
+            _ => {
+                if let Some(key) = key.strip_prefix("download_") {
+                    return self.download.set(key, value);
+                }
+                if let Some(key) = key.strip_prefix("upload_") {
+                    return self.upload.set(key, value);
+                }
+                return Err(format!("unknown config: {}", key));
+            }
+        }
+        Ok(())
+    }
+
+    /// merge 逐项合并, self 中已经设置的项优先
+    pub fn merge(self, lower: ConfigLayer) -> ConfigLayer {
+        ConfigLayer {
+            use_loc: self.use_loc.or(lower.use_loc),
+            presign_api: self.presign_api.or(lower.presign_api),
+            network: self.network.or(lower.network),
+            max_bandwidth: self.max_bandwidth.or(lower.max_bandwidth),
+            max_parallel: self.max_parallel.or(lower.max_parallel),
+            min_parallel: self.min_parallel.or(lower.min_parallel),
+            preallocate: self.preallocate.or(lower.preallocate),
+            signer: self.signer.or(lower.signer),
+            s3_access_key: self.s3_access_key.or(lower.s3_access_key),
+            s3_secret_key: self.s3_secret_key.or(lower.s3_secret_key),
+            s3_region: self.s3_region.or(lower.s3_region),
+            s3_endpoint: self.s3_endpoint.or(lower.s3_endpoint),
+            s3_virtual_host: self.s3_virtual_host.or(lower.s3_virtual_host),
+            jwt_secret: self.jwt_secret.or(lower.jwt_secret),
+            jwks_path: self.jwks_path.or(lower.jwks_path),
+            path_policy: self.path_policy.or(lower.path_policy),
+            range_attempts: self.range_attempts.or(lower.range_attempts),
+            file_attempts: self.file_attempts.or(lower.file_attempts),
+            retry_status: self.retry_status.or(lower.retry_status),
+            download: self.download.merge(lower.download),
+            upload: self.upload.merge(lower.upload),
+        }
+    }
+}
+
+impl TransferLayer {
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "chunk_size" => self.chunk_size = parse(value)?,
+            "min_chunk_size" => self.min_chunk_size = parse(value)?,
+            "max_chunk_size" => self.max_chunk_size = parse(value)?,
+            "max_files" => self.max_files = parse(value)?,
+            "merge_buffer" => self.merge_buffer = parse(value)?,
+            "connect_timeout" => self.connect_timeout = parse(value)?,
+            "timeout" => self.timeout = parse(value)?,
+            "pool_idle_timeout" => self.pool_idle_timeout = parse(value)?,
+            "pool_max_idle_per_host" => self.pool_max_idle_per_host = parse(value)?,
+            "user_agent" => self.user_agent = Some(value.to_string()),
+            _ => return Err(format!("unknown config: {}", key)),
+        }
+        Ok(())
+    }
+
+    fn merge(self, lower: TransferLayer) -> TransferLayer {
+        TransferLayer {
+            chunk_size: self.chunk_size.or(lower.chunk_size),
+            min_chunk_size: self.min_chunk_size.or(lower.min_chunk_size),
+            max_chunk_size: self.max_chunk_size.or(lower.max_chunk_size),
+            max_files: self.max_files.or(lower.max_files),
+            merge_buffer: self.merge_buffer.or(lower.merge_buffer),
+            connect_timeout: self.connect_timeout.or(lower.connect_timeout),
+            timeout: self.timeout.or(lower.timeout),
+            pool_idle_timeout: self.pool_idle_timeout.or(lower.pool_idle_timeout),
+            pool_max_idle_per_host: self.pool_max_idle_per_host.or(lower.pool_max_idle_per_host),
+            user_agent: self.user_agent.or(lower.user_agent),
+        }
+    }
+
+    /// resolve 合并默认值并校验, name 用于错误信息
+    fn resolve(self, name: &str, defaults: TransferConfig) -> Result<TransferConfig, String> {
+        let positive = |key: &str, value: Option<u64>, default: u64| match value {
+            Some(0) => Err(format!("{}.{} must be greater than 0", name, key)),
+            Some(value) => Ok(value),
+            None => Ok(default),
+        };
+        let seconds = |key: &str, value: Option<u64>, default: Duration| {
+            positive(key, value, default.as_secs()).map(Duration::from_secs)
+        };
+        // 只设置 chunk_size 时调整范围扩大到包含 chunk_size
+        let chunk_size = positive("chunk_size", self.chunk_size, defaults.chunk_size)?;
+        let min_chunk_size = positive(
+            "min_chunk_size",
+            self.min_chunk_size,
+            defaults.min_chunk_size.min(chunk_size),
+        )?;
+        let max_chunk_size = positive(
+            "max_chunk_size",
+            self.max_chunk_size,
+            defaults.max_chunk_size.max(chunk_size),
+        )?;
+        if !(min_chunk_size..=max_chunk_size).contains(&chunk_size) {
+            return Err(format!(
+                "{}.chunk_size must be between min_chunk_size {} and max_chunk_size {}",
+                name, min_chunk_size, max_chunk_size
+            ));
+        }
+        let client = &defaults.client;
+        Ok(TransferConfig {
+            chunk_size,
+            min_chunk_size,
+            max_chunk_size,
+            max_files: positive("max_files", self.max_files, defaults.max_files as u64)? as usize,
+            merge_buffer: positive("merge_buffer", self.merge_buffer, defaults.merge_buffer)?,
+            client: ClientConfig {
+                connect_timeout: seconds(
+                    "connect_timeout",
+                    self.connect_timeout,
+                    client.connect_timeout,
+                )?,
+                timeout: seconds("timeout", self.timeout, client.timeout)?,
+                pool_idle_timeout: seconds(
+                    "pool_idle_timeout",
+                    self.pool_idle_timeout,
+                    client.pool_idle_timeout,
+                )?,
+                pool_max_idle_per_host: self
+                    .pool_max_idle_per_host
+                    .map_or(client.pool_max_idle_per_host, |value| value as usize),
+                user_agent: self.user_agent.unwrap_or(client.user_agent.clone()),
+            },
+        })
+    }
+}
+
+/// ClientConfig 下载或上传使用的 HTTP 客户端
+#[derive(Debug, Clone)]
+pub struct ClientConfig {
+    pub connect_timeout: Duration,
+    pub timeout: Duration, // 单个请求的总超时
+    pub pool_idle_timeout: Duration,
+    pub pool_max_idle_per_host: usize,
+    pub user_agent: String,
+}
+
+impl ClientConfig {
+    pub fn build(&self) -> reqwest::Result<Client> {
+        Client::builder()
+            .pool_max_idle_per_host(self.pool_max_idle_per_host)
+            .pool_idle_timeout(self.pool_idle_timeout)
+            .connect_timeout(self.connect_timeout)
+            .timeout(self.timeout)
+            .user_agent(self.user_agent.as_str())
+            .build()
+    }
+}
+
+/// TransferConfig 下载或上传的参数
+#[derive(Debug, Clone)]
+pub struct TransferConfig {
+    pub chunk_size: u64,     // 分片大小, 下载时是还没有吞吐量数据时的初始值
+    pub min_chunk_size: u64, // 下载时按吞吐量, 延迟和失败率调整分片大小的范围
+    pub max_chunk_size: u64, // 两者和 chunk_size 相同时不调整
+    pub max_files: usize,    // 同时处理的文件数量, 主要受限于存储的 QPS
+    pub merge_buffer: u64,   // 合并分片时的写入缓冲
+    pub client: ClientConfig,
+}
+
+impl TransferConfig {
+    pub fn download() -> Self {
+        TransferConfig {
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            min_chunk_size: 1024 * 1024,
+            max_chunk_size: 1024 * 1024 * 64,
+            max_files: 10000,
+            merge_buffer: 500 * 1024 * 1024,
+            client: ClientConfig {
+                connect_timeout: Duration::from_secs(10),
+                timeout: Duration::from_secs(300),
+                pool_idle_timeout: Duration::from_secs(30),
+                pool_max_idle_per_host: 1000,
+                user_agent: "baai-downloader".to_string(),
+            },
+        }
+    }
+
+    pub fn upload() -> Self {
+        TransferConfig {
+            chunk_size: UPLOAD_MIN_CHUNK_SIZE,
+            min_chunk_size: UPLOAD_MIN_CHUNK_SIZE,
+            max_chunk_size: UPLOAD_MIN_CHUNK_SIZE,
+            max_files: 10000,
+            merge_buffer: 500 * 1024 * 1024,
+            client: ClientConfig {
+                connect_timeout: Duration::from_secs(10),
+                timeout: Duration::from_secs(3600),
+                pool_idle_timeout: Duration::from_secs(30),
+                pool_max_idle_per_host: 1000,
+                user_agent: "baai-uploader".to_string(),
+            },
+        }
+    }
+}
+
+/// Config 合并所有配置层并校验后的参数
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub use_loc: String,
+    pub presign_api: String,
+    pub network: String,
+    pub max_bandwidth: u64, // MB/s
+    pub max_parallel: usize,
+    pub min_parallel: usize, // 下载时按吞吐量和拥塞在 [min_parallel, max_parallel] 之间调整并发
+    pub preallocate: bool,
+    pub signer: SignerArgs,
+    pub path_policy: PathPolicy,
+    pub retry: RetryPolicy,
+    pub download: TransferConfig,
+    pub upload: TransferConfig,
+}
+
+/// user_config_path 用户配置文件: $XDG_CONFIG_HOME/ihttpd/config.toml 或 ~/.config/ihttpd/config.toml
+pub fn user_config_path() -> Option<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join("ihttpd").join("config.toml"))
+}
+
+impl Config {
+    /// load 从低到高合并: 默认值, 用户配置文件, {use_loc}/ihttpd.toml,
+    /// config_file 或 IHTTPD_CONFIG 指定的配置文件, IHTTPD_* 环境变量, args
+    /// 默认位置的配置文件不存在时跳过, 指定的配置文件不存在时返回错误
+    pub fn load(args: ConfigLayer, config_file: Option<&Path>) -> Result<Config, String> {
+        let env_layer = ConfigLayer::from_env(env::vars_os())?;
+        let config_file = config_file
+            .map(Path::to_path_buf)
+            .or_else(|| env::var_os(format!("{}CONFIG", ENV_PREFIX)).map(PathBuf::from));
+
+        let mut layer = args.merge(env_layer);
+        if let Some(config_file) = config_file {
+            layer = layer.merge(ConfigLayer::from_file(&config_file)?);
+        }
+
+        // {use_loc} 可能来自上面任何一层或用户配置文件
+        let user_layer = match user_config_path() {
+            Some(path) if path.is_file() => ConfigLayer::from_file(&path)?,
+            _ => ConfigLayer::default(),
+        };
+        let use_loc = layer
+            .use_loc
+            .clone()
+            .or(user_layer.use_loc.clone())
+            .unwrap_or(".".to_string());
+        let use_loc_path = Path::new(&use_loc).join(CONFIG_FILE);
+        if use_loc_path.is_file() {
+            let use_loc_layer = ConfigLayer::from_file(&use_loc_path)?;
+            if use_loc_layer.use_loc.is_some() {
+                return Err(format!(
+                    "config {}: use_loc can not be set in {}",
+                    use_loc_path.display(),
+                    CONFIG_FILE
+                ));
+            }
+            layer = layer.merge(use_loc_layer);
+        }
+
+        Config::resolve(layer.merge(user_layer))
+    }
+
+    /// resolve 合并默认值并校验, 不读取配置文件和环境变量
+    pub fn resolve(layer: ConfigLayer) -> Result<Config, String> {
+        for (key, value) in [
+            ("merge_buffer", layer.upload.merge_buffer),
+            ("min_chunk_size", layer.upload.min_chunk_size),
+            ("max_chunk_size", layer.upload.max_chunk_size),
+        ] {
+            if value.is_some() {
+                return Err(format!("upload.{} is not supported", key));
+            }
+        }
+        let download = layer
+            .download
+            .resolve("download", TransferConfig::download())?;
+        let upload = layer.upload.resolve("upload", TransferConfig::upload())?;
+        if !(UPLOAD_MIN_CHUNK_SIZE..=UPLOAD_MAX_CHUNK_SIZE).contains(&upload.chunk_size) {
+            return Err(format!(
+                "upload.chunk_size must be between {} and {}",
+                UPLOAD_MIN_CHUNK_SIZE, UPLOAD_MAX_CHUNK_SIZE
+            ));
+        }
+
+        let max_parallel = match layer.max_parallel {
+            Some(0) => return Err("max_parallel must be greater than 0".to_string()),
+            max_parallel => max_parallel.unwrap_or(200) as usize,
+        };
+        let min_parallel = match layer.min_parallel {
+            Some(0) => return Err("min_parallel must be greater than 0".to_string()),
+            Some(min_parallel) if min_parallel as usize > max_parallel => {
+                return Err(format!(
+                    "min_parallel {} must not be greater than max_parallel {}",
+                    min_parallel, max_parallel
+                ));
+            }
+            min_parallel => min_parallel.map_or(DEFAULT_MIN_PARALLEL.min(max_parallel), |value| {
+                value as usize
+            }),
+        };
+
+        let config = Config {
+            use_loc: layer.use_loc.unwrap_or(".".to_string()),
+            presign_api: layer.presign_api.unwrap_or(DEFAULT_PRESIGN_API.to_string()),
+            network: layer.network.unwrap_or("private".to_string()),
+            max_bandwidth: layer.max_bandwidth.unwrap_or(100),
+            max_parallel,
+            min_parallel,
+            preallocate: layer.preallocate.unwrap_or(false),
+            signer: SignerArgs {
+                signer: layer.signer.unwrap_or("http".to_string()),
+                access_key: layer.s3_access_key,
+                secret_key: layer.s3_secret_key,
+                region: layer.s3_region,
+                endpoint: layer.s3_endpoint,
+                virtual_host: layer.s3_virtual_host.unwrap_or(false),
+                jwt_secret: layer.jwt_secret,
+                jwks_path: layer.jwks_path,
+            },
+            path_policy: path_policy(layer.path_policy.as_deref().unwrap_or("reject"))?,
+            retry: retry_policy(
+                layer.range_attempts,
+                layer.file_attempts,
+                layer.retry_status.as_deref(),
+            )?,
+            download,
+            upload,
+        };
+        // 签名参数和 JWKS 文件在开始前校验
+        config.signer_config()?;
+        Ok(config)
+    }
+
+    /// signer_config 下载和上传运行时使用的签名配置
+    pub fn signer_config(&self) -> Result<(SignerConfig, TokenVerifier), String> {
+        self.signer
+            .clone()
+            .config(self.presign_api.clone(), self.network.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_layers() {
+        let file: ConfigLayer = toml::from_str(
+            "use_loc = \"/data\"\nparallel = 16\npreallocate = true\n\n[download]\nchunk_size = 8388608\ntimeout = 60\n",
+        )
+        .unwrap();
+        let var = |name: &str, value: &str| (OsString::from(name), OsString::from(value));
+        let env_layer = ConfigLayer::from_env([
+            var("IHTTPD_MAX_PARALLEL", "32"),
+            var("IHTTPD_DOWNLOAD_USER_AGENT", "test"),
+            var("PATH", "/bin"),
+        ])
+        .unwrap();
+        let args = ConfigLayer {
+            max_bandwidth: Some(10),
+            ..Default::default()
+        };
+
+        let config = Config::resolve(args.merge(env_layer).merge(file)).unwrap();
+        assert_eq!(config.use_loc, "/data");
+        assert_eq!(config.max_parallel, 32);
+        assert_eq!(config.min_parallel, DEFAULT_MIN_PARALLEL);
+        assert_eq!(config.max_bandwidth, 10);
+        assert!(config.preallocate);
+        assert_eq!(config.download.chunk_size, 8388608);
+        assert_eq!(config.download.min_chunk_size, 1024 * 1024);
+        assert_eq!(config.download.client.timeout, Duration::from_secs(60));
+        assert_eq!(config.download.client.user_agent, "test");
+        assert_eq!(config.upload.client.user_agent, "baai-uploader");
+
+        assert!(toml::from_str::<ConfigLayer>("paralel = 16").is_err());
+        let err = ConfigLayer::from_env([var("IHTTPD_PARALEL", "1")]).unwrap_err();
+        assert!(err.contains("IHTTPD_PARALEL"), "{}", err);
+        assert!(ConfigLayer::from_env([var("IHTTPD_MAX_PARALLEL", "x")]).is_err());
+
+        // 只有 IHTTPD_* 的值需要是 UTF-8
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStringExt;
+            let invalid = || OsString::from_vec(vec![0x66, 0xff]);
+            assert!(ConfigLayer::from_env([(OsString::from("LANG"), invalid())]).is_ok());
+            let err =
+                ConfigLayer::from_env([(OsString::from("IHTTPD_SIGNER"), invalid())]).unwrap_err();
+            assert!(err.contains("IHTTPD_SIGNER"), "{}", err);
+        }
+
+        let invalid = |layer: &str| Config::resolve(toml::from_str(layer).unwrap()).is_err();
+        assert!(invalid("signer = \"s3\""));
+        assert!(invalid("max_parallel = 0"));
+        assert!(invalid("max_parallel = 4\nmin_parallel = 8"));
+        assert!(invalid("[download]\nchunk_size = 0"));
+        assert!(invalid(
+            "[download]\nchunk_size = 1024\nmin_chunk_size = 4096"
+        ));
+        assert!(invalid("[upload]\nmax_chunk_size = 10485760"));
+        assert!(invalid("[upload]\nchunk_size = 1024"));
+        assert!(invalid("retry_status = \"abc\""));
+    }
+}
@@ -5,9 +5,11 @@
 //! httpdrs - A lightweight HTTP client for storage read/write operations, implemented in Rust.
 
 pub mod bandwidth;
+pub mod config;
 pub mod prelude;
 pub mod read;
-mod write;
+mod signal;
+pub mod write;
 
 pub mod logger {
     pub use httpdrs_logger::*;
@@ -0,0 +1,194 @@
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use httpdrs_core::io;
+
+use crate::config::TransferConfig;
+
+/// 一个分片期望的下载时间, 单个连接的吞吐量乘以这个时间就是分片大小
+const TARGET_RANGE_TIME: Duration = Duration::from_secs(2);
+
+/// 首字节延迟占分片时间的比例不超过 1/LATENCY_FACTOR, 延迟高时使用更大的分片
+const LATENCY_FACTOR: f64 = 4.0;
+
+/// 单个文件的分片数量上限, 超过时使用更大的分片, 但不超过 max_chunk_size
+const MAX_PARTS: u64 = 1000;
+
+/// 分片大小按 1MiB 对齐
+const CHUNK_ALIGN: u64 = 1024 * 1024;
+
+/// 指数加权平均的系数, 越大越偏向最近的分片
+const EWMA_ALPHA: f64 = 0.2;
+
+/// 分片失败率对分片大小的最大缩小比例
+const MAX_ERROR_SHRINK: f64 = 0.75;
+
+#[derive(Debug, Default)]
+struct ChunkStats {
+    throughput: Option<f64>, // 单个连接的吞吐量 bytes/s, 不含首字节延迟
+    latency: f64,            // 首字节延迟, 秒
+    error_rate: f64,         // 分片请求的失败率
+}
+
+/// ChunkSizer 按文件大小和最近分片的吞吐量, 延迟, 失败率选择分片大小
+/// 每个分片连接完成或失败时更新统计, 每个文件开始下载时选择一次分片大小
+/// 快的连接使用更大的分片减少请求和分片文件数量, 慢或者经常失败的连接使用更小的分片
+#[derive(Debug)]
+pub struct ChunkSizer {
+    initial: u64, // 还没有统计时的分片大小
+    min: u64,
+    max: u64,
+    stats: Mutex<ChunkStats>,
+}
+
+impl ChunkSizer {
+    pub fn new(download: &TransferConfig) -> Self {
+        ChunkSizer {
+            initial: download.chunk_size,
+            min: download.min_chunk_size,
+            max: download.max_chunk_size,
+            stats: Mutex::new(ChunkStats::default()),
+        }
+    }
+
+    /// observe 记录一个完成的分片, elapsed 是整个请求的时间, latency 是收到响应头的时间
+    pub fn observe(&self, bytes: u64, elapsed: Duration, latency: Duration) {
+        let mut stats = self.stats.lock().unwrap();
+        stats.error_rate *= 1.0 - EWMA_ALPHA;
+        stats.latency = ewma(Some(stats.latency), latency.as_secs_f64());
+
+        // 传输时间太短时吞吐量不准确, 只记录延迟
+        let transfer = elapsed.saturating_sub(latency).as_secs_f64();
+        if transfer >= 0.001 {
+            stats.throughput = Some(ewma(stats.throughput, bytes as f64 / transfer));
+        }
+    }
+
+    /// failure 记录一次失败的分片请求
+    pub fn failure(&self) {
+        let mut stats = self.stats.lock().unwrap();
+        stats.error_rate = ewma(Some(stats.error_rate), 1.0);
+    }
+
+    /// chunk_size 文件的分片大小, 整个文件只有一个分片时等于文件大小
+    pub fn chunk_size(&self, require_size: u64) -> u64 {
+        if self.min == self.max {
+            return self.initial;
+        }
+        let chunk = {
+            let stats = self.stats.lock().unwrap();
+            let chunk = match stats.throughput {
+                Some(throughput) => {
+                    let range_time = TARGET_RANGE_TIME
+                        .as_secs_f64()
+                        .max(stats.latency * LATENCY_FACTOR);
+                    throughput * range_time
+                }
+                None => self.initial as f64,
+            };
+            // 失败多时缩小分片, 重试时损失更少
+            (chunk * (1.0 - stats.error_rate.min(MAX_ERROR_SHRINK))) as u64
+        };
+
+        let chunk = chunk.max(require_size.div_ceil(MAX_PARTS));
+        let chunk = match chunk >= CHUNK_ALIGN {
+            true => chunk - chunk % CHUNK_ALIGN,
+            false => chunk,
+        };
+        let chunk = chunk.clamp(self.min, self.max);
+
+        // 小文件不超过 1.5 个分片时整个下载, 不需要合并
+        if require_size > 0 && require_size <= self.max && require_size <= chunk + chunk / 2 {
+            return require_size;
+        }
+        chunk
+    }
+}
+
+fn ewma(current: Option<f64>, value: f64) -> f64 {
+    match current {
+        Some(current) => current * (1.0 - EWMA_ALPHA) + value * EWMA_ALPHA,
+        None => value,
+    }
+}
+
+/// 分片下载的布局记录, 保存在 temp 目录下
+/// 断点续传时使用上次的分片大小, 已经下载的分片不会因为分片大小变化而失效
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkLayout {
+    pub require_size: u64,
+    pub chunk_size: u64,
+}
+
+impl ChunkLayout {
+    pub async fn load(layout_path: &Path) -> Option<Self> {
+        let layout_data = fs::read(layout_path).await.ok()?;
+        match serde_json::from_slice::<ChunkLayout>(&layout_data) {
+            Ok(layout) if layout.chunk_size > 0 => Some(layout),
+            Ok(_) => None,
+            Err(err) => {
+                tracing::warn!("download_layout, parse {:?} err: {}", layout_path, err);
+                None
+            }
+        }
+    }
+
+    pub async fn save(&self, layout_path: &Path) -> std::io::Result<()> {
+        io::write_replace(layout_path, &serde_json::to_vec(self)?).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIB: u64 = 1024 * 1024;
+
+    #[test]
+    fn test_chunk_size() {
+        let sizer = ChunkSizer::new(&TransferConfig::download());
+
+        // 没有统计时使用初始值, 小文件整个下载, 大文件限制分片数量
+        assert_eq!(sizer.chunk_size(100 * MIB), 5 * MIB);
+        assert_eq!(sizer.chunk_size(6 * MIB), 6 * MIB);
+        assert_eq!(sizer.chunk_size(200 * 1024 * MIB), 64 * MIB);
+
+        // 100MiB/s 的连接, 2 秒一个分片
+        for _ in 0..10 {
+            sizer.observe(
+                10 * MIB,
+                Duration::from_millis(110),
+                Duration::from_millis(10),
+            );
+        }
+        assert_eq!(sizer.chunk_size(1024 * MIB), 64 * MIB);
+
+        // 1MiB/s 的连接
+        for _ in 0..30 {
+            sizer.observe(MIB, Duration::from_millis(1010), Duration::from_millis(10));
+        }
+        assert_eq!(sizer.chunk_size(1024 * MIB), 2 * MIB);
+
+        // 连续失败时缩小到下限
+        for _ in 0..10 {
+            sizer.failure();
+        }
+        assert_eq!(sizer.chunk_size(1024 * MIB), MIB);
+
+        // 不调整时总是使用 chunk_size
+        let mut fixed = TransferConfig::download();
+        fixed.min_chunk_size = fixed.chunk_size;
+        fixed.max_chunk_size = fixed.chunk_size;
+        let sizer = ChunkSizer::new(&fixed);
+        sizer.observe(
+            10 * MIB,
+            Duration::from_millis(110),
+            Duration::from_millis(10),
+        );
+        assert_eq!(sizer.chunk_size(6 * MIB), 5 * MIB);
+    }
+}
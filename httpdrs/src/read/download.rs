@@ -1,50 +1,79 @@
 use std::sync::Arc;
 
 use reqwest::Client;
-use tokio::sync::{Semaphore, mpsc};
-use tokio::time::Instant;
+use tokio::fs;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::{self, Instant};
 
 use httpdrs_core::httpd;
-use httpdrs_core::httpd::{Bandwidth, SignatureClient};
-use httpdrs_core::request;
+use httpdrs_core::httpd::{Bandwidth, HttpdError, HttpdMetaReader, PathPolicy, Signer};
+use httpdrs_core::read::presign::PresignCache;
+use httpdrs_core::{request, verify};
 
+use crate::read::chunk::ChunkLayout;
 use crate::read::merge::{MergeMessage, MergeSender};
-use crate::read::state::RUNTIME;
+use crate::read::partial::PartialFile;
+use crate::read::report::{FileResult, FileStatus};
 use crate::read::stream;
 
 pub async fn download_file(
     bandwidth: Arc<Bandwidth>,
-    jobs: Arc<Semaphore>,
     client_down: Arc<Client>,
-    client_sign: Arc<SignatureClient>,
+    presign_cache: Arc<PresignCache>,
     merge_sender: Arc<MergeSender>,
+    args: Arc<stream::Args>,
     request_reader: Arc<request::FSReader>,
-) -> Option<(String, tokio::time::Duration)> {
+) -> Result<(String, tokio::time::Duration), HttpdError> {
     let start = Instant::now();
-    let chunk_size = request_reader.chunk_size;
     let sign = &request_reader.request_sign;
-    let total_parts = request_reader.total_parts();
     let require_size = request_reader.require_size;
 
-    let data_path = RUNTIME.get().unwrap().data_path.read().await.clone();
-    let temp_path = RUNTIME.get().unwrap().temp_path.read().await.clone();
+    let data_path = args.data_path.clone();
 
-    let args = stream::Args::new(data_path.to_string(), temp_path.to_string());
-
-    let reader_ref = Arc::new(httpd::reader_parse(sign.clone()).ok()?);
+    let reader_ref = match meta_reader(presign_cache.signer(), &request_reader, args.path_policy) {
+        Ok(reader_ref) => Arc::new(reader_ref),
+        Err(err) => {
+            let result = FileResult::new(
+                &request_reader,
+                request_reader.request_path.clone().unwrap_or_default(),
+                FileStatus::Failed,
+                0,
+                start.elapsed(),
+            );
+            args.report(result.with_error(&err));
+            return Err(err);
+        }
+    };
+    let relative_path = reader_ref
+        .local_relative_path()
+        .to_string_lossy()
+        .to_string();
+    if request_reader.request_path.is_some() {
+        presign_cache.direct(sign);
+    }
     let local_path = reader_ref.local_absolute_path_str(data_path.as_str());
 
     if let Some(local_size) = httpd::check_file_meta(local_path.clone()).await {
         if local_path.exists() {
             if local_size == request_reader.require_size {
-                RUNTIME.get()?.add_completed(0, local_size);
-                return Some((
-                    reader_ref
-                        .local_relative_path()
-                        .to_string_lossy()
-                        .to_string(),
-                    start.elapsed(),
-                ));
+                match verify::verify_file(local_path.clone(), request_reader.digest.clone()).await {
+                    Ok(_) => {
+                        args.runtime.add_download(1, local_size);
+                        args.report(FileResult::new(
+                            &request_reader,
+                            relative_path.clone(),
+                            FileStatus::Skipped,
+                            0,
+                            start.elapsed(),
+                        ));
+                        return Ok((relative_path, start.elapsed()));
+                    }
+                    Err(err) => {
+                        // 大小一致但内容损坏, 删除后重新下载
+                        tracing::warn!("download_verify, local_path: {:?}, {}", local_path, err);
+                        fs::remove_file(&local_path).await.unwrap_or(());
+                    }
+                }
             } else {
                 tracing::warn!(
                     "download_check, require_size: {}, local_size: {}, local_path: {:?}",
@@ -58,8 +87,183 @@ pub async fn download_file(
         }
     }
 
-    let (tx_part, mut rx_part) = mpsc::channel::<(u64, usize, i32)>(100);
-    let reader_merge = Arc::clone(&reader_ref);
+    // 分片大小: 有上次的布局记录时继续使用, 否则按文件大小和最近的吞吐量选择
+    let layout_path = reader_ref.local_layout_path(data_path.as_str(), args.temp_path.as_str());
+    let layout = match ChunkLayout::load(&layout_path).await {
+        Some(layout) if layout.require_size == require_size => {
+            tracing::info!(
+                "download_layout, resume chunk_size: {}, local_path: {:?}",
+                layout.chunk_size,
+                local_path
+            );
+            layout
+        }
+        _ => ChunkLayout {
+            require_size,
+            chunk_size: args.chunks.chunk_size(require_size),
+        },
+    };
+    let request_reader = request_reader.with_chunk_size(layout.chunk_size);
+    if request_reader.total_parts() > 1
+        && let Err(err) = layout.save(&layout_path).await
+    {
+        // 没有记录只影响断点续传
+        tracing::warn!("download_layout, save {:?} err: {}", layout_path, err);
+    }
+
+    // 分片失败或校验失败后重新下载, 已经完成的分片不会重复下载
+    let retry = &args.retry;
+    let mut attempt = 0;
+    let result = loop {
+        attempt += 1;
+        let outcome = download_parts(
+            &bandwidth,
+            &client_down,
+            &presign_cache,
+            &merge_sender,
+            &args,
+            &request_reader,
+            &reader_ref,
+        )
+        .await;
+
+        let err = match outcome.assembled {
+            Err(err) => err,
+            Ok(_) => {
+                match verify::verify_file(local_path.clone(), request_reader.digest.clone()).await {
+                    Ok(_) => {
+                        args.runtime.add_completed(1, 0);
+                        fs::remove_file(&layout_path).await.unwrap_or(());
+                        break Ok(());
+                    }
+                    Err(err) => {
+                        tracing::error!(
+                            "download_verify, attempt: {}/{}, local_path: {:?}, {}",
+                            attempt,
+                            retry.file_attempts,
+                            local_path,
+                            err
+                        );
+                        // 校验失败: 删除文件后重新下载
+                        fs::remove_file(&local_path).await.unwrap_or(());
+                        HttpdError::Verify(err)
+                    }
+                }
+            }
+        };
+
+        // 撤回本次统计的字节, 重新下载时会再次统计
+        args.runtime.sub_completed(0, outcome.completed_bytes);
+        args.runtime.sub_download(0, outcome.download_bytes);
+
+        let retryable = matches!(err, HttpdError::Verify(_)) || retry.is_retryable(&err);
+        if attempt >= retry.file_attempts || !retryable {
+            args.runtime.add_uncompleted(1, require_size);
+            break Err(err);
+        }
+        let delay = retry.backoff(attempt);
+        tracing::warn!(
+            "download_requeue, attempt: {}/{}, delay: {:?}, local_path: {:?}, {}",
+            attempt,
+            retry.file_attempts,
+            delay,
+            local_path,
+            err
+        );
+        time::sleep(delay).await;
+    };
+
+    // 文件处理结束, 释放缓存的链接
+    presign_cache.remove(sign);
+
+    let report = FileResult::new(
+        &request_reader,
+        relative_path.clone(),
+        FileStatus::Completed,
+        attempt,
+        start.elapsed(),
+    );
+    match result {
+        Ok(_) => {
+            args.report(report);
+            Ok((relative_path, start.elapsed()))
+        }
+        Err(err) => {
+            args.report(report.with_error(&err));
+            Err(err)
+        }
+    }
+}
+
+/// meta_reader 获取文件的本地路径, url 清单直接使用清单中的路径, 不解析签名
+/// 路径不安全时返回 PathError
+pub(crate) fn meta_reader(
+    signer: &Arc<dyn Signer>,
+    request_reader: &request::FSReader,
+    path_policy: PathPolicy,
+) -> Result<HttpdMetaReader, HttpdError> {
+    let httpd_reader = match &request_reader.request_path {
+        Some(path) => HttpdMetaReader::from_path(path),
+        None => signer.parse(&request_reader.request_sign)?,
+    };
+    Ok(httpd_reader.safe_path(path_policy)?)
+}
+
+/// 一次下载的结果: 文件是否已经组装到目标路径, 以及本次统计的字节
+struct PartsOutcome {
+    assembled: Result<(), HttpdError>,
+    completed_bytes: u64,
+    download_bytes: u64,
+}
+
+/// download_parts 下载所有分片并组装成目标文件
+async fn download_parts(
+    bandwidth: &Arc<Bandwidth>,
+    client_down: &Arc<Client>,
+    presign_cache: &Arc<PresignCache>,
+    merge_sender: &Arc<MergeSender>,
+    args: &Arc<stream::Args>,
+    request_reader: &Arc<request::FSReader>,
+    reader_ref: &Arc<HttpdMetaReader>,
+) -> PartsOutcome {
+    let chunk_size = request_reader.chunk_size;
+    let sign = &request_reader.request_sign;
+    let total_parts = request_reader.total_parts();
+    let require_size = request_reader.require_size;
+
+    let data_path = args.data_path.clone();
+    let temp_path = args.temp_path.clone();
+    let local_path = reader_ref.local_absolute_path_str(data_path.as_str());
+
+    let mut outcome = PartsOutcome {
+        assembled: Err(HttpdError::Cancelled),
+        completed_bytes: 0,
+        download_bytes: 0,
+    };
+
+    // 预分配模式下分片直接写入目标文件的 .partial
+    let partial = if args.preallocate && total_parts > 1 {
+        match PartialFile::open(
+            local_path.clone(),
+            reader_ref.local_bitmap_path(data_path.as_str(), temp_path.as_str()),
+            require_size,
+            chunk_size,
+        )
+        .await
+        {
+            Ok(partial) => Some(partial),
+            Err(err) => {
+                tracing::error!("download_err, open partial {:?} err: {}", local_path, err);
+                outcome.assembled = Err(err.into());
+                return outcome;
+            }
+        }
+    } else {
+        None
+    };
+
+    let (tx_part, mut rx_part) = mpsc::channel::<(u64, Result<(usize, i32), HttpdError>)>(100);
+    let reader_merge = Arc::clone(reader_ref);
 
     // 在循环外部创建信号量
     for idx_part in 0..total_parts {
@@ -70,25 +274,51 @@ pub async fn download_file(
         } else {
             part_end
         };
-        let part_size = part_end - part_start;
 
-        let reader_ = Arc::clone(&reader_ref);
-        let bandwidth_ = Arc::clone(&bandwidth);
-        let jobs_ = Arc::clone(&jobs);
+        let reader_ = Arc::clone(reader_ref);
+        let bandwidth_ = Arc::clone(bandwidth);
         let tx_part_ = tx_part.clone();
         let sign_ = sign.clone();
-        let args_ = Arc::clone(&args);
+        let args_ = Arc::clone(args);
+        let partial_ = partial.clone();
 
-        let client_down_span = Arc::clone(&client_down);
-        let client_sign_span = Arc::clone(&client_sign);
+        let client_down_span = Arc::clone(client_down);
+        let presign_cache_span = Arc::clone(presign_cache);
 
         tokio::spawn(async move {
             // 检查这个分片是否已经下载
-            let range =
-                stream::Range::new(idx_part, part_start, part_end, total_parts, sign_, args_);
+            let range = stream::Range::new(
+                idx_part,
+                part_start,
+                part_end,
+                total_parts,
+                chunk_size,
+                sign_,
+                args_,
+                partial_,
+            );
+
+            let partial_done = match &range.partial {
+                Some(partial) => partial.is_done(idx_part).await,
+                None => false,
+            };
+            if partial_done {
+                tracing::info!(
+                    "download_range, skip: ({}){}-{}",
+                    range.idx_part,
+                    range.start_pos,
+                    range.end_pos
+                );
+                // 0: skip, 1: down
+                let _ = tx_part_
+                    .send((idx_part, Ok((range.size() as usize, 0))))
+                    .await;
+                return;
+            }
 
             let (range_path, total_parts) = range.path(reader_.clone());
-            if total_parts > 1
+            if range.partial.is_none()
+                && total_parts > 1
                 && range_path.exists()
                 && let Some(local_size) = httpd::check_file_meta(range_path.clone()).await
                 && local_size == range.size()
@@ -99,96 +329,102 @@ pub async fn download_file(
                     range.start_pos,
                     range.end_pos
                 );
-                // 0: skip, 1: down, 2: fail
-                tx_part_
-                    .send((idx_part, local_size as usize, 0))
-                    .await
-                    .unwrap();
+                // 0: skip, 1: down
+                let _ = tx_part_
+                    .send((idx_part, Ok((local_size as usize, 0))))
+                    .await;
                 return;
             }
 
-            let _permit = jobs_.acquire().await.unwrap(); // 下载器并发控制
-            let _ = bandwidth_.permit(part_size, format!("{}", idx_part)).await; // 获取可以使用带宽后才可以下载
-            {
-                let jobs_count = jobs_.available_permits();
-                tracing::info!("download_jobs: available {}", jobs_count);
-            }
+            // 分片并发控制, 并发按吞吐量和拥塞调整
+            let parallel = Arc::clone(&range.args.parallel);
+            let Ok(_permit) = parallel.acquire().await else {
+                let _ = tx_part_.send((idx_part, Err(HttpdError::Cancelled))).await;
+                return;
+            };
+            tracing::info!("download_jobs: parallel {}", parallel.limit());
 
-            let (length, state) = match stream::stream_download_range(
+            let resp_range = stream::stream_download_range(
+                bandwidth_,
                 client_down_span,
-                client_sign_span,
+                presign_cache_span,
                 reader_,
                 range,
             )
             .await
-            {
-                Some((resp_len, resp_state)) => (resp_len, resp_state),
-                None => (0, 2),
-            };
+            .map(|(resp_len, resp_state)| (resp_len, resp_state as i32));
 
-            // 0: skip, 1: down, 2: fail
-            tx_part_
-                .send((idx_part, length, state as i32))
-                .await
-                .unwrap();
+            // 0: skip, 1: down, Err: fail
+            let _ = tx_part_.send((idx_part, resp_range)).await;
         });
     }
     drop(tx_part);
 
     let mut completed_parts = 0;
-    while let Some((_idx_part, range_length, range_state)) = rx_part.recv().await {
-        match range_state {
+    let mut range_err = None; // 第一个失败分片的错误
+    while let Some((_idx_part, range_result)) = rx_part.recv().await {
+        match range_result {
             // 根据状态修改文件处理大小
-            0 => {
+            Ok((range_length, 0)) => {
                 completed_parts += 1;
-                RUNTIME.get()?.add_download(0, range_length as u64)
+                outcome.download_bytes += range_length as u64;
+                args.runtime.add_download(0, range_length as u64)
             }
-            1 => {
+            Ok((range_length, _)) => {
                 completed_parts += 1;
-                RUNTIME.get()?.add_completed(0, range_length as u64)
+                outcome.completed_bytes += range_length as u64;
+                args.runtime.add_completed(0, range_length as u64)
             }
-            _ => {
-                RUNTIME.get()?.add_download(0, range_length as u64);
+            Err(err) => {
+                range_err.get_or_insert(err);
             }
         }
     }
 
-    // 合并逻辑, 状态只修改文件数量
-    match total_parts {
-        1 => {
-            // 不需要合并
-            if completed_parts == 1 {
-                RUNTIME.get()?.add_completed(1, 0)
+    // 预分配模式下没有全部完成时保存 bitmap, 下次从已完成的分片继续
+    if completed_parts != total_parts
+        && let Some(partial) = &partial
+        && let Err(err) = partial.flush().await
+    {
+        tracing::warn!("download_err, save bitmap {:?} err: {}", local_path, err);
+    }
+
+    // 合并逻辑, 文件数量由调用方在校验后统计
+    outcome.assembled = match total_parts {
+        // 下载失败的, 不进行合并
+        _ if completed_parts != total_parts => Err(range_err.unwrap_or(HttpdError::Cancelled)),
+        // 不需要合并
+        1 => Ok(()),
+        _ if partial.is_some() => {
+            // 预分配模式不需要合并, 全部完成后改名
+            let partial = partial.unwrap();
+            if partial.completed().await {
+                partial.finish().await.map_err(|err| {
+                    tracing::error!("download_err, finish partial {:?} err: {}", local_path, err);
+                    err.into()
+                })
             } else {
-                RUNTIME.get()?.add_uncompleted(1, 0);
+                Err(HttpdError::Verify("partial parts incomplete".to_string()))
             }
         }
         _ => {
-            if completed_parts == total_parts {
-                // 需要合并
-                RUNTIME.get()?.add_completed(1, 0);
-                merge_sender
-                    .send(MergeMessage {
-                        reader: Arc::clone(&reader_merge),
-                        total_parts,
-                        total_bytes: require_size,
-                        data_path,
-                        temp_path,
-                    })
-                    .await
-                    .unwrap();
-            } else {
-                // 下载失败的, 失败数量+1，不尽兴合并
-                RUNTIME.get()?.add_uncompleted(1, 0);
+            // 需要合并, 等待合并完成后再校验
+            let (tx_done, rx_done) = oneshot::channel();
+            let message = MergeMessage {
+                reader: Arc::clone(&reader_merge),
+                total_parts,
+                total_bytes: require_size,
+                chunk_size,
+                data_path,
+                temp_path,
+                done: tx_done,
+            };
+            match merge_sender.send(message).await {
+                Ok(_) => rx_done.await.unwrap_or(Err(HttpdError::Cancelled)),
+                Err(_) => Err(HttpdError::Cancelled),
             }
         }
-    }
+    };
 
-    Some((
-        reader_ref
-            .local_relative_path()
-            .to_string_lossy()
-            .to_string(),
-        start.elapsed(),
-    ))
+    outcome
 }
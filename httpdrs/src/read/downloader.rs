@@ -3,54 +3,56 @@ use std::sync::Arc;
 use csv::Reader;
 use reqwest::Client;
 use tokio::sync::{Semaphore, mpsc};
+use tokio::time::Instant;
 use tokio_util::sync::CancellationToken;
 
-use httpdrs_core::httpd;
-use httpdrs_core::httpd::Bandwidth;
-use httpdrs_core::httpd::SignatureClient;
+use httpdrs_core::httpd::{Bandwidth, HttpdError, PathPolicy, Signer};
+use httpdrs_core::io::MetaColumns;
+use httpdrs_core::read::presign::PresignCache;
+use httpdrs_core::read::reader::MetaRow;
 use httpdrs_core::request;
 
-use crate::read::download::download_file;
+use crate::read::download::{download_file, meta_reader};
 use crate::read::merge::MergeSender;
-use crate::read::meta;
-use crate::read::state::RUNTIME;
+use crate::read::queue::Job;
+use crate::read::report::{FileResult, FileStatus};
+use crate::read::stream;
 
 // 下载流程
 pub(crate) async fn down(
     bandwidth: Arc<Bandwidth>,
-    jobs: Arc<Semaphore>,
     client_down: Arc<Client>,
-    client_sign: Arc<SignatureClient>,
+    presign_cache: Arc<PresignCache>,
     tx_merge: Arc<MergeSender>,
+    args: Arc<stream::Args>,
+    mut rx_job: mpsc::Receiver<Job>,
     cancel: CancellationToken,
 ) {
-    let meta_path = RUNTIME.get().unwrap().meta_path.read().await.clone();
-    let data_path = RUNTIME.get().unwrap().data_path.read().await.clone();
+    let meta_path = args.runtime.meta_path.read().await.clone();
+    let path_policy = args.path_policy;
+    let args_read = Arc::clone(&args);
 
-    let (tx_meta, mut rx_meta) = mpsc::channel::<String>(2);
-    tokio::spawn(meta::read_meta("".to_string(), tx_meta, cancel.clone(), 2));
+    let signer = Arc::clone(presign_cache.signer());
 
     // 获取未下载的文件
-    let (tx_read, mut rx_read) = mpsc::channel::<(String, String, u64)>(100);
+    let (tx_read, mut rx_read) = mpsc::channel::<(String, Arc<request::FSReader>)>(100);
 
     let stop_down = cancel.clone();
     let stop = tokio::spawn(async move {
-        // 文件下载并发控制10000, 主要受限于存储的QPS
-        let semaphore = Arc::new(Semaphore::new(10000));
+        // 文件下载并发控制, 主要受限于存储的QPS
+        let semaphore = Arc::new(Semaphore::new(args.download.max_files));
 
-        while let Some((_meta_path, sign, size)) = rx_read.recv().await {
+        while let Some((_meta_path, request_reader)) = rx_read.recv().await {
             if stop_down.is_cancelled() {
                 break;
             }
 
             let bandwidth_ = Arc::clone(&bandwidth);
-            let parallel_ = Arc::clone(&jobs);
             let client_down_ = Arc::clone(&client_down);
-            let client_sign_ = Arc::clone(&client_sign);
+            let presign_cache_ = Arc::clone(&presign_cache);
             let tx_merge_ = Arc::clone(&tx_merge);
             let semaphore_ = Arc::clone(&semaphore);
-
-            let request_reader = request::FSReader::new(sign, size);
+            let args_ = Arc::clone(&args);
 
             // 开启一个异步任务下载文件
             tokio::spawn(async move {
@@ -59,69 +61,133 @@ pub(crate) async fn down(
                     "download_submit, available_permits: {}",
                     semaphore_.available_permits()
                 );
-                download_file(
+                let request_sign = request_reader.request_sign.clone();
+                if let Err(err) = download_file(
                     bandwidth_,
-                    parallel_,
                     client_down_,
-                    client_sign_,
+                    presign_cache_,
                     tx_merge_,
+                    args_,
                     request_reader,
                 )
                 .await
+                {
+                    tracing::error!("download_file, {}, sign: {}", err.kind(), request_sign);
+                }
             });
         }
     });
 
     let stop_read = cancel.clone();
     tokio::spawn(async move {
-        while let Some(meta_name) = rx_meta.recv().await {
+        let checker = Arc::new(RowChecker {
+            path_policy,
+            signer,
+            args: args_read,
+            tx_read,
+        });
+        while let Some(job) = rx_job.recv().await {
             if stop_read.is_cancelled() {
                 break;
             }
-            let tx_sender = tx_read.clone();
-            let data_path = data_path.clone();
-            let csv_meta_path = format!("{}/{}", meta_path, meta_name);
-
-            tokio::spawn(async move {
-                let mut csv_reader = match Reader::from_path(csv_meta_path.as_str()) {
-                    Ok(csv_reader) => {
-                        csv_reader
-                    }
-                    Err(err) => {
-                        tracing::error!("read csv: {} {}", csv_meta_path,  err);
-                        return;
-                    }
-                };
-
-                for raw_result in csv_reader.records() {
-                    let raw_line = raw_result.unwrap();
-                    let sign = raw_line.get(0).unwrap().to_string();
-                    let size = raw_line.get(1).unwrap().parse::<u64>().unwrap();
-                    let httpd_reader = httpd::reader_parse(sign.clone()).unwrap();
-                    if let Some(reader_size) =
-                        httpd_reader.check_local_file(data_path.as_str()).await
-                    {
-                        if reader_size == size {
-                            RUNTIME.get().unwrap().add_download(1, size);
-                            continue;
-                        }
-                        tx_sender
-                            .send((csv_meta_path.clone(), sign, size))
-                            .await
-                            .unwrap();
-                    } else {
-                        tx_sender
-                            .send((csv_meta_path.clone(), sign, size))
-                            .await
-                            .unwrap();
+            match job {
+                Job::Meta(meta_name) => {
+                    let checker = Arc::clone(&checker);
+                    let csv_meta_path = format!("{}/{}", meta_path, meta_name);
+                    tokio::spawn(async move { checker.check_meta(csv_meta_path).await });
+                }
+                Job::Row(meta_row) => {
+                    if !checker.check_row(meta_row, "push", None).await {
+                        break;
                     }
                 }
-            });
+            }
         }
-        // 检查完毕
-        drop(tx_read);
+        // 检查完毕, 正在读取的清单结束后 tx_read 释放
     });
 
     // 等到处理完成
     stop.await.unwrap();
 }
+
+/// RowChecker 检查清单中的文件, 需要下载的发送给下载任务
+/// 签名无效或路径不安全的记为失败, 本地已经存在的文件由下载任务校验后跳过
+struct RowChecker {
+    path_policy: PathPolicy,
+    signer: Arc<dyn Signer>,
+    args: Arc<stream::Args>,
+    tx_read: mpsc::Sender<(String, Arc<request::FSReader>)>,
+}
+
+impl RowChecker {
+    async fn check_meta(&self, csv_meta_path: String) {
+        let mut csv_reader = match Reader::from_path(csv_meta_path.as_str()) {
+            Ok(csv_reader) => csv_reader,
+            Err(err) => {
+                tracing::error!("read csv: {} {}", csv_meta_path, err);
+                return;
+            }
+        };
+
+        // 清单格式和可选的摘要列: md5, sha256, crc32c
+        let meta_columns = match csv_reader.headers() {
+            Ok(headers) => MetaColumns::new(headers),
+            Err(err) => {
+                tracing::error!("read csv: {} {}", csv_meta_path, err);
+                return;
+            }
+        };
+
+        for raw_result in csv_reader.records() {
+            let raw_line = match raw_result {
+                Ok(raw_line) => raw_line,
+                Err(err) => {
+                    tracing::error!("read csv: {} {}", csv_meta_path, err);
+                    continue;
+                }
+            };
+            let meta_row = match meta_columns.row(&raw_line) {
+                Ok(meta_row) => meta_row,
+                Err(err) => {
+                    tracing::error!("read csv: {} {}", csv_meta_path, err);
+                    continue;
+                }
+            };
+            let line = raw_line.position().map(|pos| pos.line());
+            if !self.check_row(meta_row, &csv_meta_path, line).await {
+                break;
+            }
+        }
+    }
+
+    /// check_row 下载任务已经结束时返回 false
+    async fn check_row(&self, meta_row: MetaRow, source: &str, line: Option<u64>) -> bool {
+        let size = meta_row.size;
+        let request_reader = request::FSReader::from_row(meta_row, self.args.download.chunk_size);
+        let start = Instant::now();
+        // 签名无效或过期时直接记为失败, 不等到获取下载链接
+        if let Err(err) = meta_reader(&self.signer, &request_reader, self.path_policy) {
+            let result = FileResult::new(
+                &request_reader,
+                request_reader.request_path.clone().unwrap_or_default(),
+                FileStatus::Failed,
+                0,
+                start.elapsed(),
+            );
+            self.args.report(result.with_error(&err));
+            tracing::error!("download_parse, {}, line: {:?}, {}", source, line, err);
+            // 不安全的路径不会写入磁盘, 记为失败
+            if matches!(err, HttpdError::Path(_)) {
+                self.args.runtime.add_rejected(1, size);
+            } else {
+                self.args.runtime.add_uncompleted(1, size);
+            }
+            return true;
+        }
+        // 本地已经存在的文件在下载任务中校验摘要, 和下载一样受 max_files 限制并发执行
+        self.tx_read
+            .send((source.to_string(), request_reader))
+            .await
+            .is_ok()
+    }
+}
@@ -0,0 +1,65 @@
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use csv::Reader;
+
+use httpdrs_core::httpd::{HttpdError, PathPolicy, Signer};
+use httpdrs_core::read::reader::{MetaColumns, MetaRow};
+use httpdrs_core::request;
+
+use crate::read::download::meta_reader;
+
+/// ManifestFile 清单中的一行和它在 data 目录中的相对路径
+/// 签名无效或路径不安全时 path 是对应的错误, 和下载时的处理相同
+#[derive(Debug)]
+pub struct ManifestFile {
+    pub row: MetaRow,
+    pub path: Result<PathBuf, HttpdError>,
+    pub line: Option<u64>,
+}
+
+impl ManifestFile {
+    /// relative_path 以 / 分隔的相对路径, 路径无效时为 None
+    pub fn relative_path(&self) -> Option<String> {
+        let path = self.path.as_ref().ok()?;
+        Some(
+            path.components()
+                .map(|component| component.as_os_str().to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+                .join("/"),
+        )
+    }
+}
+
+/// read_manifest 读取清单中的所有文件, 不访问网络
+/// 格式错误的行跳过, 清单不存在或不是 csv 时返回错误
+pub fn read_manifest(
+    meta_path: &str,
+    signer: &Arc<dyn Signer>,
+    path_policy: PathPolicy,
+) -> Result<Vec<ManifestFile>, HttpdError> {
+    let mut csv_reader = Reader::from_path(meta_path).map_err(io::Error::other)?;
+    let meta_columns = MetaColumns::new(csv_reader.headers().map_err(io::Error::other)?);
+
+    let mut files = Vec::new();
+    for raw_result in csv_reader.records() {
+        let raw_line = raw_result.map_err(io::Error::other)?;
+        let line = raw_line.position().map(|pos| pos.line());
+        let row = match meta_columns.row(&raw_line) {
+            Ok(row) => row,
+            Err(err) => {
+                tracing::error!("read csv: {} {}", meta_path, err);
+                continue;
+            }
+        };
+        let request_reader = request::FSReader::from_row(row.clone(), request::DEFAULT_CHUNK_SIZE);
+        let path = meta_reader(signer, &request_reader, path_policy)
+            .map(|httpd_reader| httpd_reader.local_relative_path());
+        if let Err(err) = &path {
+            tracing::error!("download_parse, {}, line: {:?}, {}", meta_path, line, err);
+        }
+        files.push(ManifestFile { row, path, line });
+    }
+    Ok(files)
+}
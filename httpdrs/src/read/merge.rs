@@ -1,9 +1,9 @@
 use std::sync::Arc;
 
-use httpdrs_core::httpd::HttpdMetaReader;
+use httpdrs_core::httpd::{HttpdError, HttpdMetaReader};
 use tokio::fs;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, oneshot};
 use tokio::time::Instant;
 use tokio_util::sync::CancellationToken;
 
@@ -13,8 +13,10 @@ pub struct MergeMessage {
     pub(crate) reader: Arc<HttpdMetaReader>,
     pub(crate) total_parts: u64,
     pub(crate) total_bytes: u64,
+    pub(crate) chunk_size: u64,
     pub(crate) data_path: String,
     pub(crate) temp_path: String,
+    pub(crate) done: oneshot::Sender<Result<(), HttpdError>>, // 合并结果, 下载方等待后再校验
 }
 
 pub type MergeSender = mpsc::Sender<MergeMessage>;
@@ -22,7 +24,12 @@ pub type MergeSender = mpsc::Sender<MergeMessage>;
 pub type MergeReceiver = mpsc::Receiver<MergeMessage>;
 
 /// 获取队列文件进行合并
-pub async fn init(mut merge_receiver: MergeReceiver, cancel: CancellationToken) {
+/// merge_buffer 合并时累积到这个大小再写入目标文件
+pub async fn init(
+    mut merge_receiver: MergeReceiver,
+    merge_buffer: usize,
+    cancel: CancellationToken,
+) {
     let (tx_merge, mut rx_merge) = mpsc::channel::<u64>(3000);
 
     let stop = tokio::spawn(async move {
@@ -37,10 +44,12 @@ pub async fn init(mut merge_receiver: MergeReceiver, cancel: CancellationToken)
     while let Some(message) = merge_receiver.recv().await {
         let tx_merge_ = tx_merge.clone();
         tokio::spawn(async move {
-            match download_merge(
+            let merged = match download_merge(
                 Arc::clone(&message.reader),
                 message.total_parts,
                 message.total_bytes,
+                message.chunk_size,
+                merge_buffer,
                 message.data_path.as_str(),
                 message.temp_path.as_str(),
             )
@@ -48,12 +57,15 @@ pub async fn init(mut merge_receiver: MergeReceiver, cancel: CancellationToken)
             {
                 Ok(use_ms) => {
                     tracing::info!("download_merge, use: {:?}", use_ms);
+                    Ok(())
                 }
                 Err(e) => {
                     tracing::error!("download_merge, error: {}", e);
+                    Err(e)
                 }
             };
-            tx_merge_.send(message.total_parts).await.unwrap();
+            let _ = message.done.send(merged);
+            let _ = tx_merge_.send(message.total_parts).await;
         });
     }
     drop(tx_merge);
@@ -69,15 +81,15 @@ pub async fn init(mut merge_receiver: MergeReceiver, cancel: CancellationToken)
     }
 }
 
-const BIG_CHUNK_SIZE: usize = 500 * 1024 * 1024;
-
 pub async fn download_merge(
     reader: Arc<HttpdMetaReader>,
     total_parts: u64,
     total_bytes: u64,
+    chunk_size: u64,
+    merge_buffer: usize,
     data_path: &str,
     temp_path: &str,
-) -> Result<tokio::time::Duration, Box<dyn std::error::Error>> {
+) -> Result<tokio::time::Duration, HttpdError> {
     let start = Instant::now();
     let file_path = reader.local_absolute_path_str(data_path);
 
@@ -97,18 +109,17 @@ pub async fn download_merge(
         .await?;
     let mut writer = tokio::io::BufWriter::with_capacity(8 * 1024 * 1024, dest_file);
 
-    let mut big_buffer = Vec::with_capacity(BIG_CHUNK_SIZE);
+    let mut big_buffer = Vec::with_capacity(merge_buffer);
     let mut current_size = 0;
 
     // 保存分片路径，避免在循环中重复构造路径
 
     for idx_part in 0..total_parts {
-        let part_path = reader.local_part_path(data_path, idx_part, temp_path);
+        let part_path = reader.local_part_path(data_path, idx_part, chunk_size, temp_path);
 
         let mut part_file = fs::File::open(&part_path).await?;
         let metadata = part_file.metadata().await?;
         let part_size = metadata.len();
-        let chunk_size = 1024 * 1024 * 5;
 
         let mut part_data = Vec::new();
 
@@ -131,7 +142,7 @@ pub async fn download_merge(
         current_size += part_data.len();
 
         // 缓冲区满了就写入
-        if current_size >= BIG_CHUNK_SIZE {
+        if current_size >= merge_buffer {
             writer.write_all(&big_buffer[..current_size]).await?;
             big_buffer.clear();
             current_size = 0;
@@ -147,7 +158,7 @@ pub async fn download_merge(
     writer.flush().await?;
 
     for idx_part in 0..total_parts {
-        let part_path = reader.local_part_path(data_path, idx_part, temp_path);
+        let part_path = reader.local_part_path(data_path, idx_part, chunk_size, temp_path);
         tokio::fs::remove_file(part_path).await.unwrap_or(());
     }
     Ok(start.elapsed())
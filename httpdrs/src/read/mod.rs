@@ -1,9 +1,16 @@
+pub mod chunk;
 pub mod download;
 pub mod downloader;
+pub mod manifest;
 pub mod merge;
-pub mod meta;
+pub mod object;
+pub mod parallel;
+pub mod partial;
+pub mod queue;
 pub mod reader;
+pub mod report;
 pub mod runtime;
+pub mod session;
 pub mod state;
 pub mod stream;
 pub mod watch;
@@ -0,0 +1,668 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use bytes::{Bytes, BytesMut};
+use futures::StreamExt;
+use reqwest::header::CONTENT_RANGE;
+use reqwest::{Client, Response, StatusCode};
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::sync::Semaphore;
+use tokio::time::Instant;
+use tokio::{fs, runtime};
+use tokio_util::sync::CancellationToken;
+
+use httpdrs_core::httpd::{
+    Bandwidth, HttpdError, PathPolicy, RetryPolicy, SignerConfig, TokenVerifier,
+};
+use httpdrs_core::read::presign::PresignCache;
+use httpdrs_core::verify::{self, FileDigest};
+
+use crate::bandwidth;
+use crate::config::ClientConfig;
+use crate::read::manifest;
+use crate::read::stream;
+
+/// ObjectConfig 直接读取对象到内存的参数
+pub struct ObjectConfig {
+    pub signer: SignerConfig,
+    pub verifier: TokenVerifier,
+    pub retry: RetryPolicy,
+    pub max_parallel: usize,        // 同时进行的分块请求
+    pub block_size: u64,            // 每次请求的大小, 也是缓存的单位
+    pub cache_path: Option<String>, // 本地块缓存目录, None 时不缓存
+    pub max_bandwidth: Option<u64>, // MB/s, None 时不限制
+    pub path_policy: PathPolicy,    // 清单中的路径不安全时拒绝或去掉
+    pub client: ClientConfig,       // 超时, 连接池和 user agent
+}
+
+/// ManifestEntry 清单中的一个文件, path 和下载到 data 目录时的相对路径相同
+#[derive(Debug, Clone)]
+pub struct ManifestEntry {
+    pub path: String,
+    pub sign: String,
+    pub size: u64,
+    pub digest: FileDigest,
+}
+
+/// ObjectReader 按 sign 读取对象, 不经过 data 目录
+/// 和下载共用签名缓存, 分片请求和重试策略, 多个线程可以同时使用
+pub struct ObjectReader {
+    rt: runtime::Runtime,
+    client: Arc<Client>,
+    presign_cache: Arc<PresignCache>,
+    jobs: Arc<Semaphore>,
+    bandwidth: Option<Arc<Bandwidth>>,
+    retry: RetryPolicy,
+    path_policy: PathPolicy,
+    max_parallel: usize,
+    block_size: u64,
+    cache: Option<BlockCache>,
+    sizes: Mutex<HashMap<String, u64>>,
+    token: CancellationToken,
+}
+
+impl ObjectReader {
+    pub fn new(config: ObjectConfig) -> Result<Arc<Self>, HttpdError> {
+        let rt = runtime::Builder::new_multi_thread()
+            .worker_threads(thread::available_parallelism().map_or(4, |n| n.get()))
+            .enable_all()
+            .build()?;
+        let client = config.client.build()?;
+
+        let max_parallel = config.max_parallel.max(1);
+        let signer = config.signer.build(config.verifier, config.retry.clone());
+        let token = CancellationToken::new();
+        let bandwidth = config.max_bandwidth.map(|max_bandwidth| {
+            let bandwidth = Bandwidth::new(1024 * 1024 * (max_bandwidth + 1));
+            rt.spawn(bandwidth::reset_period(
+                Arc::clone(&bandwidth),
+                token.clone(),
+            ));
+            bandwidth
+        });
+        Ok(Arc::new(ObjectReader {
+            rt,
+            client: Arc::new(client),
+            presign_cache: PresignCache::new(signer, None),
+            jobs: Arc::new(Semaphore::new(max_parallel)),
+            bandwidth,
+            retry: config.retry,
+            path_policy: config.path_policy,
+            max_parallel,
+            block_size: config.block_size.max(1),
+            cache: config.cache_path.map(|path| BlockCache::new(path.into())),
+            sizes: Mutex::new(HashMap::new()),
+            token,
+        }))
+    }
+
+    /// size 对象的大小, 同一个 sign 只请求一次
+    pub fn size(&self, sign: &str) -> Result<u64, HttpdError> {
+        self.rt.block_on(self.object_size(sign))
+    }
+
+    /// read_bytes 并发读取所有分块后组装到内存, 有摘要时校验
+    /// 校验失败时丢弃缓存重新读取, 最多 retry.file_attempts 次
+    pub fn read_bytes(
+        &self,
+        sign: &str,
+        size: Option<u64>,
+        digest: &FileDigest,
+    ) -> Result<Vec<u8>, HttpdError> {
+        self.rt.block_on(async {
+            let start = Instant::now();
+            let size = match size {
+                Some(size) => size,
+                None => self.object_size(sign).await?,
+            };
+            let mut attempt = 0;
+            loop {
+                attempt += 1;
+                let data = self.read_at(sign, size, 0, size).await?;
+                let Err(err) = verify::verify_bytes(&data, digest) else {
+                    tracing::info!(
+                        "download_object, use: {:?}, size: {}, attempt: {}",
+                        start.elapsed(),
+                        size,
+                        attempt
+                    );
+                    return Ok(data);
+                };
+                tracing::warn!("download_object, verify err: {}, attempt: {}", err, attempt);
+                if let Some(cache) = &self.cache {
+                    cache.remove(sign).await;
+                }
+                if attempt >= self.retry.file_attempts {
+                    return Err(HttpdError::Verify(err));
+                }
+            }
+        })
+    }
+
+    /// read_range 读取 [offset, offset + len), 超出对象大小的部分忽略
+    pub fn read_range(
+        &self,
+        sign: &str,
+        size: Option<u64>,
+        offset: u64,
+        len: u64,
+    ) -> Result<Vec<u8>, HttpdError> {
+        self.rt.block_on(async {
+            let size = match size {
+                Some(size) => size,
+                None => self.object_size(sign).await?,
+            };
+            self.read_at(sign, size, offset, len).await
+        })
+    }
+
+    /// read_to_file 按顺序把分块写入 {local_path}.partial, 校验通过后改名
+    /// 同时在内存中的分块不超过 max_parallel
+    pub fn read_to_file(
+        &self,
+        sign: &str,
+        size: Option<u64>,
+        local_path: &Path,
+        digest: &FileDigest,
+    ) -> Result<u64, HttpdError> {
+        self.rt.block_on(async {
+            let start = Instant::now();
+            let size = match size {
+                Some(size) => size,
+                None => self.object_size(sign).await?,
+            };
+            let mut partial_path = local_path.as_os_str().to_owned();
+            partial_path.push(".partial");
+            let partial_path = PathBuf::from(partial_path);
+            if let Some(parent) = local_path.parent() {
+                fs::create_dir_all(parent).await?;
+            }
+
+            let result = async {
+                let file = fs::File::create(&partial_path).await?;
+                let mut writer = BufWriter::new(file);
+                let mut fetches = futures::stream::iter(0..size.div_ceil(self.block_size))
+                    .map(|idx| self.block(sign, size, idx))
+                    .buffered(self.max_parallel);
+                while let Some(block) = fetches.next().await {
+                    writer.write_all(&block?).await?;
+                }
+                writer.flush().await?;
+                verify::verify_file(partial_path.clone(), digest.clone())
+                    .await
+                    .map_err(HttpdError::Verify)?;
+                fs::rename(&partial_path, local_path).await?;
+                Ok(())
+            }
+            .await;
+            if let Err(err) = result {
+                tracing::error!("download_object, save {:?} err: {}", local_path, err);
+                let _ = fs::remove_file(&partial_path).await;
+                if matches!(err, HttpdError::Verify(_))
+                    && let Some(cache) = &self.cache
+                {
+                    cache.remove(sign).await;
+                }
+                return Err(err);
+            }
+            tracing::info!(
+                "download_object, use: {:?}, size: {}, save: {:?}",
+                start.elapsed(),
+                size,
+                local_path
+            );
+            Ok(size)
+        })
+    }
+
+    /// manifest 读取清单, 路径和下载到 data 目录时相同
+    /// 签名无效或路径不安全的行跳过, url 清单中的链接不需要签名
+    pub fn manifest(&self, meta_path: &str) -> Result<Vec<ManifestEntry>, HttpdError> {
+        let files =
+            manifest::read_manifest(meta_path, self.presign_cache.signer(), self.path_policy)?;
+
+        let mut entries = Vec::with_capacity(files.len());
+        for file in files {
+            let Some(path) = file.relative_path() else {
+                continue;
+            };
+            let row = file.row;
+            if row.path.is_some() {
+                self.presign_cache.direct(&row.sign);
+            }
+            self.sizes
+                .lock()
+                .unwrap()
+                .insert(row.sign.clone(), row.size);
+            entries.push(ManifestEntry {
+                path,
+                sign: row.sign,
+                size: row.size,
+                digest: row.digest,
+            });
+        }
+        tracing::info!(
+            "download_object, manifest: {}, rows: {}",
+            meta_path,
+            entries.len()
+        );
+        Ok(entries)
+    }
+
+    /// open 打开一个可以 seek 的对象, 读取时才请求需要的分块
+    pub fn open(self: &Arc<Self>, sign: &str, size: Option<u64>) -> Result<RemoteFile, HttpdError> {
+        let size = match size {
+            Some(size) => size,
+            None => self.size(sign)?,
+        };
+        Ok(RemoteFile {
+            reader: Arc::clone(self),
+            sign: sign.to_string(),
+            size,
+            pos: 0,
+            buffer: None,
+        })
+    }
+
+    pub fn block_size(&self) -> u64 {
+        self.block_size
+    }
+
+    /// object_size 请求第一个字节, 从 Content-Range 中获取大小
+    async fn object_size(&self, sign: &str) -> Result<u64, HttpdError> {
+        if let Some(size) = self.sizes.lock().unwrap().get(sign) {
+            return Ok(*size);
+        }
+        let size = match self.request(sign, "bytes=0-0", response_size).await {
+            Ok(size) => size,
+            // 空对象没有可以读取的范围
+            Err(err) if err.is_status(StatusCode::RANGE_NOT_SATISFIABLE) => 0,
+            Err(err) => return Err(err),
+        };
+        self.sizes.lock().unwrap().insert(sign.to_string(), size);
+        Ok(size)
+    }
+
+    /// read_at 读取覆盖 [offset, offset + len) 的分块并截取
+    async fn read_at(
+        &self,
+        sign: &str,
+        size: u64,
+        offset: u64,
+        len: u64,
+    ) -> Result<Vec<u8>, HttpdError> {
+        let end = offset.saturating_add(len).min(size);
+        if offset >= end {
+            return Ok(Vec::new());
+        }
+        let first = offset / self.block_size;
+        let last = (end - 1) / self.block_size;
+        let blocks = self.blocks(sign, size, first, last).await?;
+        Ok(assemble(&blocks, first, self.block_size, offset, end))
+    }
+
+    /// blocks 并发读取 first..=last 的分块, 按顺序返回
+    async fn blocks(
+        &self,
+        sign: &str,
+        size: u64,
+        first: u64,
+        last: u64,
+    ) -> Result<Vec<Bytes>, HttpdError> {
+        let mut blocks = Vec::with_capacity((last - first + 1) as usize);
+        let mut fetches = futures::stream::iter(first..=last)
+            .map(|idx| self.block(sign, size, idx))
+            .buffered(self.max_parallel);
+        while let Some(block) = fetches.next().await {
+            blocks.push(block?);
+        }
+        Ok(blocks)
+    }
+
+    /// block 优先从本地缓存读取, 没有时请求存储并写入缓存
+    async fn block(&self, sign: &str, size: u64, idx: u64) -> Result<Bytes, HttpdError> {
+        let start = idx * self.block_size;
+        let end = (start + self.block_size).min(size);
+        if let Some(cache) = &self.cache
+            && let Some(block) = cache.get(sign, self.block_size, idx, end - start).await
+        {
+            return Ok(block);
+        }
+
+        let header = format!("bytes={}-{}", start, end - 1);
+        let block = self
+            .request(sign, &header, async |resp| {
+                self.response_bytes(resp, start, end, size).await
+            })
+            .await?;
+        if let Some(cache) = &self.cache {
+            cache.put(sign, self.block_size, idx, &block).await;
+        }
+        Ok(block)
+    }
+
+    /// response_bytes 读取对象 [start, end) 的响应, 每块读取前申请带宽
+    /// 先检查是请求的范围, 超出范围大小时立即停止, 不会把整个对象读入内存
+    /// 存储不支持 Range 时, 只有读取整个对象才接受 200
+    async fn response_bytes(
+        &self,
+        resp: Response,
+        start: u64,
+        end: u64,
+        object_size: u64,
+    ) -> Result<Bytes, HttpdError> {
+        if !(resp.status() == StatusCode::OK && start == 0 && end == object_size) {
+            stream::check_range(&resp, start, end)?;
+        }
+
+        let size = end - start;
+        let mut bytes = BytesMut::with_capacity(size as usize);
+        let mut resp_stream = resp.bytes_stream();
+        while let Some(chunk) = resp_stream.next().await {
+            let chunk = chunk?;
+            if (bytes.len() + chunk.len()) as u64 > size {
+                return Err(HttpdError::Network(format!(
+                    "range size: {}, resp_len: {}",
+                    size,
+                    bytes.len() + chunk.len()
+                )));
+            }
+            if let Some(bandwidth) = &self.bandwidth {
+                let _ = bandwidth
+                    .permit(chunk.len() as u64, "object".to_string())
+                    .await;
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+        if bytes.len() as u64 != size {
+            return Err(HttpdError::Network(format!(
+                "range size: {}, resp_len: {}",
+                size,
+                bytes.len()
+            )));
+        }
+        Ok(bytes.freeze())
+    }
+
+    /// request 发送分片请求, 和下载文件共用 stream_request_retry: 403 时重新签名, 其他错误按重试策略重试
+    async fn request<T>(
+        &self,
+        sign: &str,
+        range: &str,
+        handle: impl AsyncFn(Response) -> Result<T, HttpdError>,
+    ) -> Result<T, HttpdError> {
+        let _permit = self
+            .jobs
+            .acquire()
+            .await
+            .map_err(|_| HttpdError::Cancelled)?;
+        stream::stream_request_retry(
+            Arc::clone(&self.client),
+            &self.presign_cache,
+            sign,
+            range,
+            &self.retry,
+            &format!("object, range: {}", range),
+            |resp, _| handle(resp),
+            |_| {},
+        )
+        .await
+        .map(|(value, _)| value)
+    }
+}
+
+/// response_size 206 时从 Content-Range 中获取大小, 存储不支持 Range 时使用 Content-Length
+async fn response_size(resp: Response) -> Result<u64, HttpdError> {
+    if resp.status() == StatusCode::PARTIAL_CONTENT {
+        let total = resp
+            .headers()
+            .get(CONTENT_RANGE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.rsplit_once('/'))
+            .and_then(|(_, total)| total.trim().parse().ok());
+        return total.ok_or_else(|| HttpdError::Network("invalid content-range".to_string()));
+    }
+    resp.content_length()
+        .ok_or_else(|| HttpdError::Network("missing content-length".to_string()))
+}
+
+impl Drop for ObjectReader {
+    fn drop(&mut self) {
+        self.token.cancel();
+    }
+}
+
+/// assemble 从 first 开始的连续分块中截取 [offset, end)
+fn assemble(blocks: &[Bytes], first: u64, block_size: u64, offset: u64, end: u64) -> Vec<u8> {
+    let mut data = Vec::with_capacity((end - offset) as usize);
+    for (idx, block) in (first..).zip(blocks) {
+        let block_start = idx * block_size;
+        let from = offset.saturating_sub(block_start) as usize;
+        let to = ((end - block_start) as usize).min(block.len());
+        data.extend_from_slice(&block[from..to]);
+    }
+    data
+}
+
+/// BlockCache 本地块缓存, {cache_path}/{md5(sign)}/{block_size}-{idx}
+/// 缓存只是加速, 读写失败时忽略
+struct BlockCache {
+    path: PathBuf,
+    seq: AtomicU64,
+}
+
+impl BlockCache {
+    fn new(path: PathBuf) -> Self {
+        BlockCache {
+            path,
+            seq: AtomicU64::new(0),
+        }
+    }
+
+    fn sign_path(&self, sign: &str) -> PathBuf {
+        self.path
+            .join(format!("{:x}", md5::compute(sign.as_bytes())))
+    }
+
+    fn block_path(&self, sign: &str, block_size: u64, idx: u64) -> PathBuf {
+        self.sign_path(sign).join(format!("{}-{}", block_size, idx))
+    }
+
+    /// get 大小不一致的缓存当作没有
+    async fn get(&self, sign: &str, block_size: u64, idx: u64, size: u64) -> Option<Bytes> {
+        let data = fs::read(self.block_path(sign, block_size, idx))
+            .await
+            .ok()?;
+        (data.len() as u64 == size).then(|| Bytes::from(data))
+    }
+
+    /// put 先写入临时文件再改名, 其他进程不会读到写了一半的块
+    async fn put(&self, sign: &str, block_size: u64, idx: u64, block: &Bytes) {
+        let block_path = self.block_path(sign, block_size, idx);
+        let temp_path = block_path.with_extension(format!(
+            "{}.{}.tmp",
+            std::process::id(),
+            self.seq.fetch_add(1, Ordering::Relaxed)
+        ));
+        let result = async {
+            fs::create_dir_all(self.sign_path(sign)).await?;
+            fs::write(&temp_path, block).await?;
+            fs::rename(&temp_path, &block_path).await
+        }
+        .await;
+        if let Err(err) = result {
+            tracing::warn!("download_object, cache {:?} err: {}", block_path, err);
+            let _ = fs::remove_file(&temp_path).await;
+        }
+    }
+
+    async fn remove(&self, sign: &str) {
+        let _ = fs::remove_dir_all(self.sign_path(sign)).await;
+    }
+}
+
+/// RemoteFile 可以 seek 的远程对象, 每次读取只请求覆盖的分块
+/// 保留最后读取的一块, 顺序的小读取不会重复请求
+pub struct RemoteFile {
+    reader: Arc<ObjectReader>,
+    sign: String,
+    size: u64,
+    pos: u64,
+    buffer: Option<(u64, Bytes)>, // (块序号, 数据)
+}
+
+impl RemoteFile {
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// read_at 读取 [offset, offset + len), 不改变当前位置
+    pub fn read_at(&mut self, offset: u64, len: u64) -> Result<Vec<u8>, HttpdError> {
+        let end = offset.saturating_add(len).min(self.size);
+        if offset >= end {
+            return Ok(Vec::new());
+        }
+        let block_size = self.reader.block_size;
+        let first = offset / block_size;
+        let last = (end - 1) / block_size;
+
+        // 在缓冲的块中直接返回
+        if let Some((idx, block)) = &self.buffer
+            && first == *idx
+            && last == *idx
+        {
+            let from = (offset - idx * block_size) as usize;
+            let to = (end - idx * block_size) as usize;
+            return Ok(block[from..to].to_vec());
+        }
+
+        let reader = &self.reader;
+        let blocks = reader
+            .rt
+            .block_on(reader.blocks(&self.sign, self.size, first, last))?;
+        let data = assemble(&blocks, first, block_size, offset, end);
+        self.buffer = blocks.into_iter().last().map(|block| (last, block));
+        Ok(data)
+    }
+
+    /// read_next 从当前位置读取最多 len 字节
+    pub fn read_next(&mut self, len: u64) -> Result<Vec<u8>, HttpdError> {
+        let data = self.read_at(self.pos, len)?;
+        self.pos += data.len() as u64;
+        Ok(data)
+    }
+}
+
+impl Read for RemoteFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let data = self.read_next(buf.len() as u64).map_err(io::Error::other)?;
+        buf[..data.len()].copy_from_slice(&data);
+        Ok(data.len())
+    }
+}
+
+impl Seek for RemoteFile {
+    /// seek 可以超过文件末尾, 之后的读取返回空
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.size.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+        let Some(pos) = pos else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative position",
+            ));
+        };
+        self.pos = pos;
+        Ok(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_block_cache() {
+        let blocks = vec![Bytes::from_static(b"0123"), Bytes::from_static(b"4567")];
+        assert_eq!(assemble(&blocks, 2, 4, 9, 15), b"123456");
+        assert_eq!(assemble(&blocks[1..], 3, 4, 12, 14), b"45");
+
+        let path = std::env::temp_dir().join(format!("httpdrs-object-{}", std::process::id()));
+        let cache = BlockCache::new(path.clone());
+        cache.put("sign", 4, 1, &blocks[0]).await;
+        assert_eq!(cache.get("sign", 4, 1, 4).await, Some(blocks[0].clone()));
+        // 块大小不同或长度不一致时不使用缓存
+        assert_eq!(cache.get("sign", 8, 1, 4).await, None);
+        assert_eq!(cache.get("sign", 4, 1, 3).await, None);
+        cache.remove("sign").await;
+        assert_eq!(cache.get("sign", 4, 1, 4).await, None);
+        let _ = std::fs::remove_dir_all(path);
+    }
+
+    #[test]
+    fn test_range_ignored() {
+        // 忽略 Range, 总是返回 200 和整个对象
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/object", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0u8; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    let n = stream.read(&mut buf).unwrap();
+                    if n == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buf[..n]);
+                }
+                let response =
+                    "HTTP/1.1 200 OK\r\nContent-Length: 10\r\nConnection: close\r\n\r\nabcdefghij";
+                let _ = io::Write::write_all(&mut stream, response.as_bytes());
+            }
+        });
+
+        let reader = |block_size| {
+            ObjectReader::new(ObjectConfig {
+                signer: SignerConfig::Passthrough,
+                verifier: TokenVerifier::default(),
+                retry: RetryPolicy::default(),
+                max_parallel: 2,
+                block_size,
+                cache_path: None,
+                max_bandwidth: None,
+                path_policy: PathPolicy::default(),
+                client: crate::config::TransferConfig::download().client,
+            })
+            .unwrap()
+        };
+
+        // 读取一部分时拒绝 200, 不使用整个对象
+        let small = reader(4);
+        let err = small.read_range(&url, Some(10), 4, 4).unwrap_err();
+        assert!(err.is_status(StatusCode::OK), "{}", err);
+        let err = small
+            .read_bytes(&url, Some(10), &FileDigest::default())
+            .unwrap_err();
+        assert!(err.is_status(StatusCode::OK), "{}", err);
+
+        // 一个分块就是整个对象时接受 200
+        let large = reader(16);
+        assert_eq!(large.size(&url).unwrap(), 10);
+        let data = large
+            .read_bytes(&url, None, &FileDigest::default())
+            .unwrap();
+        assert_eq!(data, b"abcdefghij");
+    }
+}
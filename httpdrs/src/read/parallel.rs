@@ -0,0 +1,283 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use indicatif::HumanBytes;
+use reqwest::StatusCode;
+use tokio::sync::{AcquireError, OwnedSemaphorePermit, Semaphore};
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+use httpdrs_core::httpd::HttpdError;
+
+/// 调整并发的周期, 一个周期最多增加或减少一次
+const ADJUST_PERIOD: Duration = Duration::from_secs(1);
+
+/// 吞吐量比上次调整时高出这个比例才继续增加并发
+const GROWTH_RATIO: f64 = 1.05;
+
+/// 每次增加的并发占 max 的比例, 至少为 1
+const INCREASE_DIVISOR: usize = 32;
+
+#[derive(Debug)]
+struct ParallelState {
+    limit: usize,          // 当前并发
+    debt: usize,           // 减少并发时正在使用的许可, 归还时收回
+    slow_start: bool,      // 第一次拥塞前每个周期翻倍
+    base_throughput: f64,  // 上次调整后的吞吐量 bytes/s
+    window_start: Instant, // 本周期开始时间
+    last_decrease: Option<Instant>,
+}
+
+/// Parallel 分片下载的并发控制 (AIMD)
+/// 有分片在等待并且吞吐量还在增加时增加并发: 第一次拥塞前每个周期翻倍, 之后每个周期加固定值
+/// 429, 5xx, 超时和连接错误时并发减半, 一个周期最多减少一次
+/// 并发在 [min, max] 之间, 两者相同时不调整
+#[derive(Debug)]
+pub struct Parallel {
+    semaphore: Arc<Semaphore>,
+    min: usize,
+    max: usize,
+    step: usize,
+    limit: AtomicUsize,    // 当前并发, 用于进度输出
+    bytes: AtomicU64,      // 本周期下载的字节
+    congestion: AtomicU64, // 本周期的拥塞错误
+    waiting: AtomicU64,    // 本周期等待许可的次数
+    state: Mutex<ParallelState>,
+}
+
+impl Parallel {
+    pub fn new(min: usize, max: usize) -> Arc<Self> {
+        let max = max.max(1);
+        let min = min.clamp(1, max);
+        Arc::new(Parallel {
+            semaphore: Arc::new(Semaphore::new(min)),
+            min,
+            max,
+            step: (max / INCREASE_DIVISOR).max(1),
+            limit: AtomicUsize::new(min),
+            bytes: AtomicU64::new(0),
+            congestion: AtomicU64::new(0),
+            waiting: AtomicU64::new(0),
+            state: Mutex::new(ParallelState {
+                limit: min,
+                debt: 0,
+                slow_start: true,
+                base_throughput: 0.0,
+                window_start: Instant::now(),
+                last_decrease: None,
+            }),
+        })
+    }
+
+    /// limit 当前并发
+    pub fn limit(&self) -> usize {
+        self.limit.load(Ordering::Relaxed)
+    }
+
+    /// acquire 获取一个分片的许可, 许可释放时并发已经减少的不再归还
+    pub async fn acquire(self: &Arc<Self>) -> Result<ParallelPermit, AcquireError> {
+        if self.semaphore.available_permits() == 0 {
+            self.waiting.fetch_add(1, Ordering::Relaxed);
+        }
+        let permit = Arc::clone(&self.semaphore).acquire_owned().await?;
+        Ok(ParallelPermit {
+            permit: Some(permit),
+            parallel: Arc::clone(self),
+        })
+    }
+
+    /// record 记录下载的字节, 用于计算本周期的吞吐量
+    pub fn record(&self, bytes: u64) {
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// failure 记录一次失败的分片请求, 存储过载或网络拥塞时减少并发
+    pub fn failure(&self, err: &HttpdError) {
+        if !is_congestion(err) {
+            return;
+        }
+        self.congestion.fetch_add(1, Ordering::Relaxed);
+
+        let mut state = self.state.lock().unwrap();
+        state.slow_start = false;
+        if state.limit <= self.min
+            || state
+                .last_decrease
+                .is_some_and(|last| last.elapsed() < ADJUST_PERIOD)
+        {
+            return;
+        }
+        state.last_decrease = Some(Instant::now());
+        let limit = (state.limit / 2).max(self.min);
+        tracing::warn!(
+            "download_parallel, decrease: {} -> {}, {}",
+            state.limit,
+            limit,
+            err
+        );
+        // 吞吐量基准按同样比例减少, 否则减少后的吞吐量很难超过减少前的基准, 并发不会再增加
+        state.base_throughput *= limit as f64 / state.limit as f64;
+        self.resize(&mut state, limit);
+    }
+
+    /// adjust 每个周期调用一次, 按本周期的吞吐量增加并发
+    pub fn adjust(&self) {
+        let mut state = self.state.lock().unwrap();
+        let elapsed = state.window_start.elapsed().as_secs_f64();
+        state.window_start = Instant::now();
+        let bytes = self.bytes.swap(0, Ordering::Relaxed);
+        let congestion = self.congestion.swap(0, Ordering::Relaxed);
+        let waiting = self.waiting.swap(0, Ordering::Relaxed);
+        if elapsed <= 0.0 {
+            return;
+        }
+        let throughput = bytes as f64 / elapsed;
+
+        // 没有分片等待时并发已经够用, 有拥塞时不增加
+        if congestion > 0 || waiting == 0 || state.limit >= self.max {
+            return;
+        }
+        if throughput < state.base_throughput * GROWTH_RATIO {
+            return;
+        }
+        let limit = match state.slow_start {
+            true => state.limit * 2,
+            false => state.limit + self.step,
+        }
+        .min(self.max);
+        tracing::info!(
+            "download_parallel, increase: {} -> {}, throughput: {}/s",
+            state.limit,
+            limit,
+            HumanBytes(throughput as u64)
+        );
+        state.base_throughput = throughput;
+        self.resize(&mut state, limit);
+    }
+
+    fn resize(&self, state: &mut ParallelState, limit: usize) {
+        if limit > state.limit {
+            // 先抵消还没有收回的许可
+            let add = limit - state.limit;
+            let cancel = add.min(state.debt);
+            state.debt -= cancel;
+            self.semaphore.add_permits(add - cancel);
+        } else {
+            // 空闲的许可直接收回, 正在使用的归还时收回
+            let remove = state.limit - limit;
+            state.debt += remove - self.semaphore.forget_permits(remove);
+        }
+        state.limit = limit;
+        self.limit.store(limit, Ordering::Relaxed);
+    }
+}
+
+/// ParallelPermit 分片的许可, 释放时归还或在并发减少后收回
+#[derive(Debug)]
+pub struct ParallelPermit {
+    permit: Option<OwnedSemaphorePermit>,
+    parallel: Arc<Parallel>,
+}
+
+impl Drop for ParallelPermit {
+    fn drop(&mut self) {
+        let Some(permit) = self.permit.take() else {
+            return;
+        };
+        let mut state = self.parallel.state.lock().unwrap();
+        if state.debt > 0 {
+            state.debt -= 1;
+            permit.forget();
+        }
+    }
+}
+
+/// is_congestion 存储过载或网络拥塞: 429, 5xx, 超时, 连接失败, 响应中断
+fn is_congestion(err: &HttpdError) -> bool {
+    match err {
+        HttpdError::Network(_) => true,
+        HttpdError::Status { status, .. } => {
+            *status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+        }
+        _ => false,
+    }
+}
+
+/// adjust_period 定时调整并发, cancel 后结束
+pub async fn adjust_period(parallel: Arc<Parallel>, cancel: CancellationToken) {
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(ADJUST_PERIOD) => parallel.adjust(),
+            _ = cancel.cancelled() => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIB: u64 = 1024 * 1024;
+
+    /// 模拟一个周期: 下载 bytes 字节, waiting 表示有分片在等待
+    fn period(parallel: &Parallel, bytes: u64, waiting: bool) {
+        parallel.state.lock().unwrap().window_start = Instant::now() - ADJUST_PERIOD;
+        parallel.record(bytes);
+        if waiting {
+            parallel.waiting.fetch_add(1, Ordering::Relaxed);
+        }
+        parallel.adjust();
+    }
+
+    #[tokio::test]
+    async fn test_parallel_aimd() {
+        let parallel = Parallel::new(2, 64);
+        assert_eq!(parallel.limit(), 2);
+
+        // 有分片等待并且吞吐量增加时翻倍
+        period(&parallel, 10 * MIB, true);
+        assert_eq!(parallel.limit(), 4);
+        // 没有分片等待或吞吐量没有增加时不变
+        period(&parallel, 100 * MIB, false);
+        period(&parallel, 10 * MIB, true);
+        assert_eq!(parallel.limit(), 4);
+
+        // 503 时减半, 空闲的许可直接收回, 正在使用的归还时收回
+        let permits = vec![
+            parallel.acquire().await.unwrap(),
+            parallel.acquire().await.unwrap(),
+            parallel.acquire().await.unwrap(),
+        ];
+        parallel.failure(&HttpdError::status(StatusCode::SERVICE_UNAVAILABLE, ""));
+        assert_eq!(parallel.limit(), 2);
+        assert_eq!(parallel.semaphore.available_permits(), 0);
+        drop(permits);
+        assert_eq!(parallel.semaphore.available_permits(), 2);
+
+        // 同一周期内只减少一次, 404 不是拥塞
+        parallel.failure(&HttpdError::Network("timeout".to_string()));
+        parallel.state.lock().unwrap().last_decrease = None;
+        parallel.failure(&HttpdError::status(StatusCode::NOT_FOUND, ""));
+        assert_eq!(parallel.limit(), 2);
+
+        // 有拥塞的周期不增加, 之后每次增加固定值
+        period(&parallel, 20 * MIB, true);
+        assert_eq!(parallel.limit(), 2);
+        period(&parallel, 20 * MIB, true);
+        assert_eq!(parallel.limit(), 4);
+        period(&parallel, 40 * MIB, true);
+        assert_eq!(parallel.limit(), 6);
+
+        // 减少后吞吐量和减少前一样时可以继续增加, 之后吞吐量不变时保持
+        parallel.state.lock().unwrap().last_decrease = None;
+        parallel.failure(&HttpdError::status(StatusCode::TOO_MANY_REQUESTS, ""));
+        assert_eq!(parallel.limit(), 3);
+        period(&parallel, 40 * MIB, true);
+        assert_eq!(parallel.limit(), 3);
+        period(&parallel, 40 * MIB, true);
+        assert_eq!(parallel.limit(), 5);
+        period(&parallel, 40 * MIB, true);
+        assert_eq!(parallel.limit(), 5);
+    }
+}
@@ -0,0 +1,240 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::fs;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+use httpdrs_core::io;
+
+/// bitmap 文件头: total_parts(u64) + chunk_size(u64), 布局变化时重新下载
+const BITMAP_HEADER_SIZE: usize = 16;
+
+/// 保存 bitmap 的最小间隔, 中断时最多重新下载这段时间内完成的分片
+const BITMAP_SAVE_PERIOD: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+struct Bitmap {
+    bits: Vec<u8>,
+    dirty: bool,        // 有完成的分片还没有保存
+    last_save: Instant, // 上次保存的时间
+}
+
+/// PartialFile 预分配下载
+/// 目标文件先写入同目录的 `.partial`, 所有分片共用一个文件句柄按偏移写入
+/// 完成的分片记录在 temp 下的 bitmap 中用于断点续传, 全部完成后改名为目标文件
+/// bitmap 按周期保存, 保存前先落盘数据, 记录为完成的分片一定已经写入磁盘
+#[derive(Debug)]
+pub struct PartialFile {
+    pub local_path: PathBuf,
+    pub partial_path: PathBuf,
+    pub bitmap_path: PathBuf,
+    total_parts: u64,
+    chunk_size: u64,
+    partial_file: Arc<std::fs::File>,
+    bitmap: Mutex<Bitmap>,
+}
+
+impl PartialFile {
+    pub async fn open(
+        local_path: PathBuf,
+        bitmap_path: PathBuf,
+        require_size: u64,
+        chunk_size: u64,
+    ) -> std::io::Result<Arc<Self>> {
+        let total_parts = require_size.div_ceil(chunk_size);
+        let mut partial_name = local_path.file_name().unwrap_or_default().to_os_string();
+        partial_name.push(".partial");
+        let partial_path = local_path.with_file_name(partial_name);
+
+        if let Some(parent) = partial_path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        if let Some(parent) = bitmap_path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        let bitmap_size = total_parts.div_ceil(8) as usize;
+        let partial_size = fs::metadata(&partial_path)
+            .await
+            .map(|meta| meta.len())
+            .ok();
+        let bits = match (partial_size, fs::read(&bitmap_path).await) {
+            (Some(size), Ok(data))
+                if size == require_size
+                    && data.len() == BITMAP_HEADER_SIZE + bitmap_size
+                    && data[..BITMAP_HEADER_SIZE] == bitmap_header(total_parts, chunk_size) =>
+            {
+                Some(data[BITMAP_HEADER_SIZE..].to_vec())
+            }
+            _ => None,
+        };
+        let (partial_file, bits) = match bits {
+            Some(bits) => {
+                let partial_file = fs::OpenOptions::new()
+                    .write(true)
+                    .open(&partial_path)
+                    .await?;
+                (partial_file, bits)
+            }
+            None => {
+                // 没有可用的记录, 重新预分配
+                let partial_file = fs::OpenOptions::new()
+                    .create(true)
+                    .truncate(true)
+                    .write(true)
+                    .open(&partial_path)
+                    .await?;
+                partial_file.set_len(require_size).await?;
+                (partial_file, vec![0u8; bitmap_size])
+            }
+        };
+
+        let partial = PartialFile {
+            local_path,
+            partial_path,
+            bitmap_path,
+            total_parts,
+            chunk_size,
+            partial_file: Arc::new(partial_file.into_std().await),
+            bitmap: Mutex::new(Bitmap {
+                bits,
+                dirty: false,
+                last_save: Instant::now(),
+            }),
+        };
+        partial.save(&mut *partial.bitmap.lock().await).await?;
+        Ok(Arc::new(partial))
+    }
+
+    pub async fn is_done(&self, idx_part: u64) -> bool {
+        let bitmap = self.bitmap.lock().await;
+        bitmap.bits[(idx_part / 8) as usize] & (1 << (idx_part % 8)) != 0
+    }
+
+    pub async fn completed(&self) -> bool {
+        let bitmap = self.bitmap.lock().await;
+        (0..self.total_parts)
+            .all(|idx_part| bitmap.bits[(idx_part / 8) as usize] & (1 << (idx_part % 8)) != 0)
+    }
+
+    /// write_at 在 offset 处写入数据, 在阻塞线程中按偏移写入, 不移动共用句柄的位置
+    pub async fn write_at(&self, offset: u64, data: Vec<u8>) -> std::io::Result<()> {
+        let partial_file = Arc::clone(&self.partial_file);
+        tokio::task::spawn_blocking(move || write_all_at(&partial_file, offset, &data)).await?
+    }
+
+    /// mark_done 记录分片完成, 数据写入后再调用
+    /// 距离上次保存超过 BITMAP_SAVE_PERIOD 时落盘数据并保存 bitmap
+    pub async fn mark_done(&self, idx_part: u64) -> std::io::Result<()> {
+        let mut bitmap = self.bitmap.lock().await;
+        bitmap.bits[(idx_part / 8) as usize] |= 1 << (idx_part % 8);
+        bitmap.dirty = true;
+        if bitmap.last_save.elapsed() < BITMAP_SAVE_PERIOD {
+            return Ok(());
+        }
+        self.sync_data().await?;
+        self.save(&mut bitmap).await
+    }
+
+    /// flush 落盘数据并保存还没有保存的 bitmap, 下载没有全部完成时调用
+    pub async fn flush(&self) -> std::io::Result<()> {
+        let mut bitmap = self.bitmap.lock().await;
+        if !bitmap.dirty {
+            return Ok(());
+        }
+        self.sync_data().await?;
+        self.save(&mut bitmap).await
+    }
+
+    /// finish 落盘并改名为目标文件, 删除 bitmap
+    pub async fn finish(&self) -> std::io::Result<()> {
+        let partial_file = Arc::clone(&self.partial_file);
+        tokio::task::spawn_blocking(move || partial_file.sync_all()).await??;
+        fs::rename(&self.partial_path, &self.local_path).await?;
+        fs::remove_file(&self.bitmap_path).await.unwrap_or(());
+        Ok(())
+    }
+
+    async fn sync_data(&self) -> std::io::Result<()> {
+        let partial_file = Arc::clone(&self.partial_file);
+        tokio::task::spawn_blocking(move || partial_file.sync_data()).await?
+    }
+
+    async fn save(&self, bitmap: &mut Bitmap) -> std::io::Result<()> {
+        let mut data = bitmap_header(self.total_parts, self.chunk_size).to_vec();
+        data.extend_from_slice(&bitmap.bits);
+        io::write_replace(&self.bitmap_path, &data).await?;
+        bitmap.dirty = false;
+        bitmap.last_save = Instant::now();
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn write_all_at(file: &std::fs::File, offset: u64, data: &[u8]) -> std::io::Result<()> {
+    use std::os::unix::fs::FileExt;
+    file.write_all_at(data, offset)
+}
+
+#[cfg(windows)]
+fn write_all_at(file: &std::fs::File, mut offset: u64, mut data: &[u8]) -> std::io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !data.is_empty() {
+        match file.seek_write(data, offset)? {
+            0 => return Err(std::io::ErrorKind::WriteZero.into()),
+            n => {
+                data = &data[n..];
+                offset += n as u64;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn bitmap_header(total_parts: u64, chunk_size: u64) -> [u8; BITMAP_HEADER_SIZE] {
+    let mut header = [0u8; BITMAP_HEADER_SIZE];
+    header[..8].copy_from_slice(&total_parts.to_le_bytes());
+    header[8..].copy_from_slice(&chunk_size.to_le_bytes());
+    header
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_partial_resume() {
+        let temp_dir = std::env::temp_dir().join(format!("httpdrs-partial-{}", std::process::id()));
+        let local_path = temp_dir.join("data/a.bin");
+        let bitmap_path = temp_dir.join("temp/a.bitmap");
+
+        let partial = PartialFile::open(local_path.clone(), bitmap_path.clone(), 10, 4)
+            .await
+            .unwrap();
+        partial.write_at(4, b"4567".to_vec()).await.unwrap();
+        partial.mark_done(1).await.unwrap();
+        partial.flush().await.unwrap();
+        drop(partial);
+
+        // 重新打开时保留已完成的分片
+        let partial = PartialFile::open(local_path.clone(), bitmap_path.clone(), 10, 4)
+            .await
+            .unwrap();
+        assert!(!partial.is_done(0).await);
+        assert!(partial.is_done(1).await);
+
+        partial.write_at(0, b"0123".to_vec()).await.unwrap();
+        partial.mark_done(0).await.unwrap();
+        partial.write_at(8, b"89".to_vec()).await.unwrap();
+        partial.mark_done(2).await.unwrap();
+        assert!(partial.completed().await);
+
+        partial.finish().await.unwrap();
+        assert_eq!(fs::read(&local_path).await.unwrap(), b"0123456789");
+        assert!(!bitmap_path.exists());
+
+        fs::remove_dir_all(&temp_dir).await.unwrap();
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use httpdrs_core::read::reader::MetaRow;
+
+use crate::read::state::RuntimeContext;
+
+/// Job 提交的下载任务
+#[derive(Debug, Clone)]
+pub enum Job {
+    Meta(String), // {use_loc}/meta 下的清单文件名
+    Row(MetaRow), // 单个文件, 格式和清单中的一行相同
+}
+
+/// QueueClosed 队列已经关闭, 或者下载已经结束
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueClosed;
+
+impl Display for QueueClosed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "job queue closed")
+    }
+}
+
+impl std::error::Error for QueueClosed {}
+
+pub type JobReceiver = mpsc::UnboundedReceiver<Job>;
+
+/// JobQueue 提交下载任务, 每次提交 O(1)
+/// close 后不再接收任务, 已经提交的任务处理完成后下载结束
+#[derive(Debug, Clone)]
+pub struct JobQueue {
+    sender: Arc<Mutex<Option<mpsc::UnboundedSender<Job>>>>,
+}
+
+impl JobQueue {
+    pub fn new() -> (JobQueue, JobReceiver) {
+        let (tx_job, rx_job) = mpsc::unbounded_channel::<Job>();
+        let queue = JobQueue {
+            sender: Arc::new(Mutex::new(Some(tx_job))),
+        };
+        (queue, rx_job)
+    }
+
+    pub fn push(&self, job: Job) -> Result<(), QueueClosed> {
+        match self.sender.lock().unwrap().as_ref() {
+            Some(sender) => sender.send(job).map_err(|_| QueueClosed),
+            None => Err(QueueClosed),
+        }
+    }
+
+    pub fn push_meta(&self, meta_name: String) -> Result<(), QueueClosed> {
+        self.push(Job::Meta(meta_name))
+    }
+
+    pub fn push_row(&self, meta_row: MetaRow) -> Result<(), QueueClosed> {
+        self.push(Job::Row(meta_row))
+    }
+
+    /// close 结束提交, 重复调用没有影响
+    pub fn close(&self) {
+        self.sender.lock().unwrap().take();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        match self.sender.lock().unwrap().as_ref() {
+            Some(sender) => sender.is_closed(),
+            None => true,
+        }
+    }
+}
+
+/// dispatch 把提交的任务分发给统计(reader)和下载(downloader)
+/// 同一个清单文件只处理一次, 单个文件直接计入需要下载的数量
+pub(crate) async fn dispatch(
+    runtime: Arc<RuntimeContext>,
+    mut rx_job: JobReceiver,
+    tx_count: mpsc::Sender<String>,
+    tx_down: mpsc::Sender<Job>,
+    cancel: CancellationToken,
+) {
+    let mut meta_names = HashSet::new();
+    let mut row_count = 0;
+    loop {
+        let job = tokio::select! {
+            job = rx_job.recv() => job,
+            _ = cancel.cancelled() => break,
+        };
+        let Some(job) = job else {
+            break;
+        };
+
+        let job = match job {
+            Job::Meta(meta_name) => {
+                let meta_name = meta_name.trim().to_string();
+                if meta_name.is_empty() || !meta_names.insert(meta_name.clone()) {
+                    // 已经提交过的清单跳过
+                    continue;
+                }
+                if tx_count.send(meta_name.clone()).await.is_err() {
+                    break;
+                }
+                Job::Meta(meta_name)
+            }
+            Job::Row(meta_row) => {
+                row_count += 1;
+                runtime.add_require(1, meta_row.size);
+                Job::Row(meta_row)
+            }
+        };
+        if tx_down.send(job).await.is_err() {
+            break;
+        }
+    }
+
+    tracing::info!(
+        "download_queue, meta: {}, rows: {}",
+        meta_names.len(),
+        row_count
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_job_queue() {
+        let (queue, rx_job) = JobQueue::new();
+        queue.push_meta("m0.bin".to_string()).unwrap();
+        queue.push_meta(" m0.bin ".to_string()).unwrap();
+        queue.push_meta("m1.bin".to_string()).unwrap();
+        queue.close();
+        assert!(queue.is_closed());
+        assert_eq!(queue.push_meta("m2.bin".to_string()), Err(QueueClosed));
+
+        let (tx_count, mut rx_count) = mpsc::channel::<String>(10);
+        let (tx_down, mut rx_down) = mpsc::channel::<Job>(10);
+        // 队列关闭后 dispatch 结束, 同一个清单只分发一次
+        dispatch(
+            Arc::new(RuntimeContext::default()),
+            rx_job,
+            tx_count,
+            tx_down,
+            CancellationToken::new(),
+        )
+        .await;
+
+        let mut counted = vec![];
+        while let Some(meta_name) = rx_count.recv().await {
+            counted.push(meta_name);
+        }
+        assert_eq!(counted, vec!["m0.bin", "m1.bin"]);
+        let mut downloaded = vec![];
+        while let Some(Job::Meta(meta_name)) = rx_down.recv().await {
+            downloaded.push(meta_name);
+        }
+        assert_eq!(downloaded, counted);
+    }
+}
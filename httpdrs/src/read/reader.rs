@@ -1,18 +1,23 @@
+use std::sync::Arc;
+
 use csv::Reader;
 use tokio::sync::mpsc;
 use tokio::time::Instant;
 use tokio_util::sync::CancellationToken;
 
-use crate::read::meta;
-use crate::read::state::RUNTIME;
+use httpdrs_core::io::MetaColumns;
 
-pub(crate) async fn init(cancel: CancellationToken) {
-    let start = Instant::now();
+use crate::read::state::RuntimeContext;
 
-    let meta_path = RUNTIME.get().unwrap().meta_path.read().await.to_string();
+/// init 统计提交的清单中需要下载的文件数量和大小
+pub(crate) async fn init(
+    runtime: Arc<RuntimeContext>,
+    mut rx_meta: mpsc::Receiver<String>,
+    cancel: CancellationToken,
+) {
+    let start = Instant::now();
 
-    let (tx_meta, mut rx_meta) = mpsc::channel::<String>(100);
-    tokio::spawn(meta::read_meta("".to_string(), tx_meta, cancel.clone(), 1));
+    let meta_path = runtime.meta_path.read().await.to_string();
 
     let (tx, mut rx) = mpsc::channel::<(String, u64, u64)>(2);
 
@@ -22,7 +27,7 @@ pub(crate) async fn init(cancel: CancellationToken) {
             if stop_wait.is_cancelled() {
                 break;
             }
-            RUNTIME.get().unwrap().add_require(count, bytes);
+            runtime.add_require(count, bytes);
         }
     });
 
@@ -38,15 +43,19 @@ pub(crate) async fn init(cancel: CancellationToken) {
             let tx_sender = tx.clone();
             tokio::spawn(async move {
                 let mut csv_reader = Reader::from_path(meta_path.as_str()).unwrap();
+                let meta_columns = MetaColumns::new(csv_reader.headers().unwrap());
 
                 let mut require_bytes = 0;
                 let mut require_count = 0;
                 for raw_result in csv_reader.records() {
                     tracing::debug!("init reading: {}, {:?}", meta_path, raw_result);
                     let raw_line = raw_result.unwrap();
-                    let size = raw_line.get(1).unwrap().parse::<u64>().unwrap();
+                    // 无效的行在下载时跳过, 这里也不统计
+                    let Ok(meta_row) = meta_columns.row(&raw_line) else {
+                        continue;
+                    };
                     require_count += 1;
-                    require_bytes += size;
+                    require_bytes += meta_row.size;
                 }
                 tx_sender
                     .send((meta_path.clone(), require_bytes, require_count))
@@ -0,0 +1,369 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+use httpdrs_core::httpd::HttpdError;
+use httpdrs_core::request::FSReader;
+
+/// FileStatus 文件的处理结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileStatus {
+    Completed, // 下载并校验完成
+    Skipped,   // 本地已经存在并且校验通过
+    Failed,
+    Rejected, // 路径不安全, 没有下载
+}
+
+impl FileStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FileStatus::Completed => "completed",
+            FileStatus::Skipped => "skipped",
+            FileStatus::Failed => "failed",
+            FileStatus::Rejected => "rejected",
+        }
+    }
+}
+
+/// FileResult 一个文件的处理结果, 写入 report.jsonl 和 report.csv
+#[derive(Debug, Clone, Serialize)]
+pub struct FileResult {
+    pub path: String,
+    pub size: u64,
+    pub status: FileStatus,
+    pub attempts: u32,
+    pub error: Option<&'static str>, // HttpdError::kind
+    pub message: Option<String>,
+    pub duration_ms: u64,
+    #[serde(skip)]
+    pub request: Arc<FSReader>, // 失败的文件按清单格式写入 failed.bin
+}
+
+impl FileResult {
+    pub fn new(
+        request: &Arc<FSReader>,
+        path: String,
+        status: FileStatus,
+        attempts: u32,
+        duration: Duration,
+    ) -> Self {
+        FileResult {
+            path,
+            size: request.require_size,
+            status,
+            attempts,
+            error: None,
+            message: None,
+            duration_ms: duration.as_millis() as u64,
+            request: Arc::clone(request),
+        }
+    }
+
+    /// with_error 记为失败, 路径不安全时记为拒绝
+    pub fn with_error(mut self, err: &HttpdError) -> Self {
+        self.status = match err {
+            HttpdError::Path(_) => FileStatus::Rejected,
+            _ => FileStatus::Failed,
+        };
+        self.error = Some(err.kind());
+        self.message = Some(err.to_string());
+        self
+    }
+
+    fn is_failed(&self) -> bool {
+        matches!(self.status, FileStatus::Failed | FileStatus::Rejected)
+    }
+}
+
+pub type ReportSender = mpsc::UnboundedSender<FileResult>;
+
+pub type ReportReceiver = mpsc::UnboundedReceiver<FileResult>;
+
+type Waiters = HashMap<String, Vec<oneshot::Sender<FileResult>>>;
+
+/// FileWaiters 等待单个文件的处理结果, 按 sign 匹配
+/// 报告结束后不再接收等待, 没有结果的等待收到 RecvError
+#[derive(Debug, Clone)]
+pub struct FileWaiters {
+    waiters: Arc<Mutex<Option<Waiters>>>,
+}
+
+impl Default for FileWaiters {
+    fn default() -> Self {
+        FileWaiters {
+            waiters: Arc::new(Mutex::new(Some(HashMap::new()))),
+        }
+    }
+}
+
+impl FileWaiters {
+    /// register 报告已经结束时返回 None
+    pub fn register(&self, sign: &str) -> Option<oneshot::Receiver<FileResult>> {
+        let mut guard = self.waiters.lock().unwrap();
+        let waiters = guard.as_mut()?;
+        let (tx_result, rx_result) = oneshot::channel();
+        waiters.entry(sign.to_string()).or_default().push(tx_result);
+        Some(rx_result)
+    }
+
+    fn notify(&self, result: &FileResult) {
+        let mut guard = self.waiters.lock().unwrap();
+        let Some(waiters) = guard.as_mut() else {
+            return;
+        };
+        for tx_result in waiters
+            .remove(&result.request.request_sign)
+            .into_iter()
+            .flatten()
+        {
+            let _ = tx_result.send(result.clone());
+        }
+    }
+
+    fn close(&self) {
+        self.waiters.lock().unwrap().take();
+    }
+}
+
+/// init 接收文件结果写入 {report_path} 下的 report.jsonl, report.csv
+/// 失败的文件按清单格式写入 failed.bin(sign 清单) 和 failed_url.bin(url 清单), 可以直接放入 meta 重新下载
+/// 取消后写入已经收到的结果再退出
+/// 每个结果写入后通知等待这个文件的调用方
+pub async fn init(
+    mut report_receiver: ReportReceiver,
+    report_path: String,
+    waiters: FileWaiters,
+    cancel: CancellationToken,
+) {
+    let mut report = match ReportFiles::create(Path::new(&report_path)) {
+        Ok(report) => Some(report),
+        Err(err) => {
+            tracing::error!("download_report, create {} err: {}", report_path, err);
+            None
+        }
+    };
+
+    loop {
+        let result = tokio::select! {
+            biased;
+            result = report_receiver.recv() => result,
+            _ = cancel.cancelled() => report_receiver.try_recv().ok(),
+        };
+        let Some(result) = result else {
+            break;
+        };
+        // 写入失败后不再写入报告, 仍然通知等待的调用方
+        if let Some(Err(err)) = report.as_mut().map(|report| report.write(&result)) {
+            tracing::error!("download_report, write err: {}", err);
+            report = None;
+        }
+        waiters.notify(&result);
+    }
+    waiters.close();
+
+    match report.map(|mut report| report.flush()) {
+        Some(Ok(_)) => tracing::info!("download_report: {}", report_path),
+        Some(Err(err)) => tracing::error!("download_report, flush err: {}", err),
+        None => {}
+    }
+}
+
+struct ReportFiles {
+    report_path: PathBuf,
+    jsonl: BufWriter<File>,
+    csv: csv::Writer<File>,
+    failed: Option<csv::Writer<File>>,
+    failed_url: Option<csv::Writer<File>>,
+}
+
+impl ReportFiles {
+    fn create(report_path: &Path) -> std::io::Result<Self> {
+        std::fs::create_dir_all(report_path)?;
+        // 上一次运行留下的失败清单
+        for name in ["failed.bin", "failed_url.bin"] {
+            std::fs::remove_file(report_path.join(name)).unwrap_or(());
+        }
+        Ok(ReportFiles {
+            report_path: report_path.to_path_buf(),
+            jsonl: BufWriter::new(File::create(report_path.join("report.jsonl"))?),
+            csv: csv::Writer::from_path(report_path.join("report.csv"))?,
+            failed: None,
+            failed_url: None,
+        })
+    }
+
+    fn write(&mut self, result: &FileResult) -> std::io::Result<()> {
+        serde_json::to_writer(&mut self.jsonl, result)?;
+        self.jsonl.write_all(b"\n")?;
+        self.csv.serialize(result)?;
+
+        if !result.is_failed() {
+            return Ok(());
+        }
+        let request = &result.request;
+        let size = request.require_size.to_string();
+        let (writer, mut record) = match &request.request_path {
+            Some(path) => (
+                failed_writer(
+                    &mut self.failed_url,
+                    &self.report_path.join("failed_url.bin"),
+                    &["url", "path", "size", "md5", "sha256", "crc32c"],
+                )?,
+                vec![request.request_sign.as_str(), path.as_str(), size.as_str()],
+            ),
+            None => (
+                failed_writer(
+                    &mut self.failed,
+                    &self.report_path.join("failed.bin"),
+                    &["sign", "size", "extn", "md5", "sha256", "crc32c"],
+                )?,
+                vec![
+                    request.request_sign.as_str(),
+                    size.as_str(),
+                    request.extn.as_str(),
+                ],
+            ),
+        };
+        let digest = &request.digest;
+        record.extend(
+            [&digest.md5, &digest.sha256, &digest.crc32c]
+                .map(|value| value.as_deref().unwrap_or_default()),
+        );
+        writer.write_record(&record)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.jsonl.flush()?;
+        self.csv.flush()?;
+        for writer in [&mut self.failed, &mut self.failed_url]
+            .into_iter()
+            .flatten()
+        {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// failed_writer 第一次有失败的文件时才创建失败清单
+fn failed_writer<'a>(
+    writer: &'a mut Option<csv::Writer<File>>,
+    path: &Path,
+    headers: &[&str],
+) -> std::io::Result<&'a mut csv::Writer<File>> {
+    if writer.is_none() {
+        let mut failed = csv::Writer::from_path(path)?;
+        failed.write_record(headers)?;
+        *writer = Some(failed);
+    }
+    Ok(writer.as_mut().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpdrs_core::io::MetaColumns;
+    use httpdrs_core::read::reader::MetaRow;
+    use httpdrs_core::request::DEFAULT_CHUNK_SIZE;
+    use httpdrs_core::verify::FileDigest;
+
+    #[test]
+    fn test_failed_manifest() {
+        let report_path =
+            std::env::temp_dir().join(format!("httpdrs-report-{}", std::process::id()));
+        let mut report = ReportFiles::create(&report_path).unwrap();
+
+        let request = FSReader::from_row(
+            MetaRow {
+                sign: "token".to_string(),
+                path: None,
+                size: 12,
+                extn: "bin".to_string(),
+                digest: FileDigest {
+                    md5: Some("25f9e794323b453885f5181f1b624d0b".to_string()),
+                    ..Default::default()
+                },
+            },
+            DEFAULT_CHUNK_SIZE,
+        );
+        let result = FileResult::new(
+            &request,
+            "a/b.bin".to_string(),
+            FileStatus::Failed,
+            3,
+            Duration::ZERO,
+        )
+        .with_error(&HttpdError::Verify("md5 mismatch".to_string()));
+        report.write(&result).unwrap();
+        let result = FileResult::new(
+            &request,
+            "a/c.bin".to_string(),
+            FileStatus::Completed,
+            1,
+            Duration::ZERO,
+        );
+        report.write(&result).unwrap();
+        report.flush().unwrap();
+
+        // 失败清单可以按 meta/*.bin 的格式重新读取
+        let mut csv_reader = csv::Reader::from_path(report_path.join("failed.bin")).unwrap();
+        let meta_columns = MetaColumns::new(csv_reader.headers().unwrap());
+        let rows: Vec<MetaRow> = csv_reader
+            .records()
+            .map(|record| meta_columns.row(&record.unwrap()).unwrap())
+            .collect();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].sign, "token");
+        assert_eq!(rows[0].size, 12);
+        assert_eq!(rows[0].extn, "bin");
+        assert_eq!(rows[0].digest, request.digest);
+        assert!(!report_path.join("failed_url.bin").exists());
+
+        let report_csv = std::fs::read_to_string(report_path.join("report.csv")).unwrap();
+        assert!(report_csv.contains("a/b.bin,12,failed,3,verify,"));
+        assert!(report_csv.contains("a/c.bin,12,completed,1,,,0"));
+
+        std::fs::remove_dir_all(report_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_file_waiters() {
+        let waiters = FileWaiters::default();
+        let request = FSReader::from_row(
+            MetaRow {
+                sign: "token".to_string(),
+                path: None,
+                size: 12,
+                extn: String::new(),
+                digest: FileDigest::default(),
+            },
+            DEFAULT_CHUNK_SIZE,
+        );
+        let rx_done = waiters.register("token").unwrap();
+        let rx_other = waiters.register("other").unwrap();
+
+        let result = FileResult::new(
+            &request,
+            "a/b.bin".to_string(),
+            FileStatus::Completed,
+            1,
+            Duration::ZERO,
+        );
+        waiters.notify(&result);
+        assert_eq!(rx_done.await.unwrap().status, FileStatus::Completed);
+
+        // 报告结束后没有结果的等待返回错误, 也不能再注册
+        waiters.close();
+        assert!(rx_other.await.is_err());
+        assert!(waiters.register("token").is_none());
+    }
+}
@@ -3,56 +3,120 @@ use std::thread;
 use std::time::Duration;
 
 use futures::future::join_all;
-use reqwest::Client;
 use tokio::runtime;
-use tokio::sync::{Semaphore, mpsc};
+use tokio::sync::mpsc;
 use tokio_util::sync::CancellationToken;
 
-use httpdrs_core::httpd::SignatureClient;
+use httpdrs_core::httpd::{HttpdError, PathPolicy, RetryPolicy, SignerConfig, TokenVerifier};
+use httpdrs_core::read::presign;
+use httpdrs_core::read::presign::{PresignCache, PresignRequest};
 
-use crate::bandwidth;
+use crate::config::{DEFAULT_MIN_PARALLEL, TransferConfig};
 use crate::core::{httpd, pbar};
 use crate::read::merge::MergeMessage;
-use crate::read::state::{META, RUNTIME, init_runtime};
-use crate::read::{downloader, merge, reader, watch};
-
+use crate::read::parallel::{self, Parallel};
+use crate::read::queue::{Job, JobReceiver};
+use crate::read::report::{FileResult, FileWaiters};
+use crate::read::session::SessionConfig;
+use crate::read::state::{Progress, RuntimeContext};
+use crate::read::{downloader, merge, queue, reader, report, stream, watch};
+use crate::{bandwidth, signal};
+
+#[allow(clippy::too_many_arguments)]
 pub fn start_multi_thread(
     max_bandwidth: u64,
     max_parallel: usize,
     use_loc: String,
-    presign_api: String,
-    network: String,
-) -> Result<(), Box<dyn std::error::Error>> {
+    signer: SignerConfig,
+    verifier: TokenVerifier,
+    preallocate: bool,
+    path_policy: PathPolicy,
+    retry: RetryPolicy,
+    rx_job: JobReceiver,
+) -> Result<(), HttpdError> {
+    let config = SessionConfig {
+        max_bandwidth,
+        max_parallel,
+        min_parallel: DEFAULT_MIN_PARALLEL.min(max_parallel),
+        use_loc,
+        signer,
+        verifier,
+        preallocate,
+        path_policy,
+        retry,
+        download: TransferConfig::download(),
+        progress: None,
+    };
+    let runtime_context = Arc::new(config.runtime_context());
+    run(
+        config,
+        runtime_context,
+        rx_job,
+        FileWaiters::default(),
+        CancellationToken::new(),
+    )
+}
+
+/// run 在当前线程运行一次下载, 提交的任务处理完成, 收到退出信号或 cancel 后返回
+pub(crate) fn run(
+    config: SessionConfig,
+    runtime_context: Arc<RuntimeContext>,
+    rx_job: JobReceiver,
+    waiters: FileWaiters,
+    rt_token: CancellationToken,
+) -> Result<(), HttpdError> {
     let start = tokio::time::Instant::now();
+    let SessionConfig {
+        max_bandwidth,
+        max_parallel,
+        min_parallel,
+        use_loc,
+        signer,
+        verifier,
+        preallocate,
+        path_policy,
+        retry,
+        download,
+        progress,
+    } = config;
 
     let rt = runtime::Builder::new_multi_thread()
-        .worker_threads(thread::available_parallelism().unwrap().get())
+        .worker_threads(thread::available_parallelism().map_or(4, |n| n.get()))
         .enable_all()
-        .build()
-        .unwrap();
-
-    let rt_token = CancellationToken::new();
-
-    let meta_path = format!("{}/meta", use_loc);
-    let data_path = format!("{}/data", use_loc);
-    let temp_path = format!("{}/temp", use_loc);
-    init_runtime(meta_path, data_path, temp_path);
-
-    let client_down = Arc::new(
-        Client::builder()
-            .pool_max_idle_per_host(1000)
-            .pool_idle_timeout(Duration::from_secs(30))
-            .connect_timeout(Duration::from_secs(10))
-            .timeout(Duration::from_secs(300))
-            .user_agent("baai-downloader")
-            .build()
-            .expect("failed to build reqwest client"),
+        .build()?;
+
+    let data_path = runtime_context.data_path.blocking_read().clone();
+    let temp_path = runtime_context.temp_path.blocking_read().clone();
+    // 每个文件的处理结果写入 {use_loc}/report
+    let (tx_report, rx_report) = mpsc::unbounded_channel::<FileResult>();
+    let report_path = format!("{}/report", use_loc);
+    let client_down = Arc::new(download.client.build()?);
+    let merge_buffer = download.merge_buffer as usize;
+    let httpd_parallel = Parallel::new(min_parallel, max_parallel); // 分片并发控制, 按吞吐量和拥塞调整
+    let args = stream::Args::new(
+        data_path,
+        temp_path,
+        preallocate,
+        path_policy,
+        tx_report,
+        retry.clone(),
+        download,
+        Arc::clone(&httpd_parallel),
+        Arc::clone(&runtime_context),
     );
 
-    let client_sign = Arc::new(SignatureClient::new(presign_api, network));
+    let verified = verifier.is_verified();
+    let signer = signer.build(verifier, retry);
+    tracing::info!(
+        "Runtime signer: {}, verify token: {}",
+        signer.name(),
+        verified
+    );
+    // 批量签名: 最多 100 个 sign 或等待 20ms 合并为一次请求
+    let (tx_presign, rx_presign) = mpsc::channel::<PresignRequest>(1000);
+    let presign_cache = PresignCache::new(Arc::clone(&signer), Some(tx_presign)); // 同一文件的分片共享下载链接
 
     let httpd_bandwidth = httpd::Bandwidth::new(1024 * 1024 * (max_bandwidth + 1)); // 网络带宽控制
-    let httpd_jobs = Arc::new(Semaphore::new(max_parallel)); // 下载器并发控制
 
     // 处理合并的队列
     let (tx_merge, rx_merge) = mpsc::channel::<MergeMessage>(100);
@@ -61,85 +125,124 @@ pub fn start_multi_thread(
 
     let pb = pbar::create();
 
+    rt.spawn(presign::batch(
+        Arc::clone(&signer),
+        rx_presign,
+        100,
+        Duration::from_millis(20),
+    ));
     rt.spawn(bandwidth::reset_period(
         Arc::clone(&httpd_bandwidth),
         rt_token.clone(),
     ));
-    rt.spawn(watch::init(pb.clone(), rt_token.clone()));
+    rt.spawn(parallel::adjust_period(
+        Arc::clone(&httpd_parallel),
+        rt_token.clone(),
+    ));
+    rt.spawn(watch::init(
+        pb.clone(),
+        Arc::clone(&runtime_context),
+        Some(Arc::clone(&httpd_parallel)),
+        progress.clone(),
+        rt_token.clone(),
+    ));
+
+    // 提交的任务分发给统计和下载, 队列关闭后结束
+    let (tx_count, rx_count) = mpsc::channel::<String>(100);
+    let (tx_down, rx_down) = mpsc::channel::<Job>(100);
+    rt.spawn(queue::dispatch(
+        Arc::clone(&runtime_context),
+        rx_job,
+        tx_count,
+        tx_down,
+        rt_token.clone(),
+    ));
 
-    let spawn_read = rt.spawn(reader::init(rt_token.clone()));
+    let spawn_read = rt.spawn(reader::init(
+        Arc::clone(&runtime_context),
+        rx_count,
+        rt_token.clone(),
+    ));
     let spawn_down = rt.spawn(downloader::down(
         Arc::clone(&httpd_bandwidth),
-        Arc::clone(&httpd_jobs),
         Arc::clone(&client_down),
-        Arc::clone(&client_sign),
+        Arc::clone(&presign_cache),
         Arc::new(tx_merge),
+        Arc::clone(&args),
+        rx_down,
         rt_token.clone(),
     ));
-    let spawn_merge = rt.spawn(merge::init(rx_merge, rt_token.clone()));
+    let spawn_merge = rt.spawn(merge::init(rx_merge, merge_buffer, rt_token.clone()));
+    let report_token = rt_token.child_token();
+    let spawn_report = rt.spawn(report::init(
+        rx_report,
+        report_path.clone(),
+        waiters,
+        report_token.clone(),
+    ));
+    drop(args);
 
     // 等待所以任务处理完成
-    rt.block_on(async move {
-        #[cfg(unix)]
-        let signal_future = async {
-            let mut sigint =
-                tokio::signal::unix::signal(tokio::signal::unix::SignalKind::interrupt())
-                    .expect("");
-            let mut sigterm =
-                tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
-                    .expect("");
-
-            tokio::select! {
-                _ = sigint.recv() => {
-                    println!("\n收到中断信号 (Ctrl+C)，正在退出...");
-                },
-                _ = sigterm.recv() => {
-                    println!("\n收到终止信号，正在退出...");
-                }
-            }
-        };
-
-        #[cfg(windows)]
-        let signal_future = async {
-            tokio::signal::ctrl_c()
-                .await
-                .expect("Failed to install Ctrl+C handler");
-            println!("\n收到 Ctrl+C 信号，正在退出...");
-        };
-
+    let cancelled = rt.block_on(async move {
         let event_tasks = vec![spawn_read, spawn_down, spawn_merge];
         tokio::select! {
             _ = join_all(event_tasks) => {
                 tracing::info!("所有任务处理完成");
+                false
             }
-            _ = signal_future => {
+            _ = signal::shutdown() => {
                 rt_token.cancel();
+                true
             }
+            // Session::cancel
+            _ = rt_token.cancelled() => true,
         }
     });
 
-    META.lock().unwrap().iter().for_each(|(k, v)| {
-        tracing::info!("download_meta: {} = {}", k, v);
-    });
+    // 写入已经收到的文件结果
+    report_token.cancel();
+    let _ = rt.block_on(spawn_report);
     rt.shutdown_background();
 
-    let runtime = { RUNTIME.get().unwrap().snapshot() };
+    let runtime = runtime_context.snapshot();
 
     pb.set_length(runtime.require_count);
     pb.set_position(runtime.download_count + runtime.completed_count + runtime.uncompleted_count);
     let avg_speed = 1000 * runtime.completed_bytes as u128 / (start.elapsed().as_millis() + 1);
     let process_bytes =
         runtime.completed_bytes + runtime.uncompleted_bytes + runtime.download_bytes;
-    pb.set_message(pbar::format(
-        runtime.require_bytes,
-        avg_speed as u64,
-        1.0,
-        process_bytes,
-        0,
+    pb.set_message(watch::format_parallel(
+        pbar::format(
+            runtime.require_bytes,
+            avg_speed as u64,
+            1.0,
+            process_bytes,
+            0,
+        ),
+        Some(httpd_parallel.limit()),
     ));
 
+    // 结束时回调最终的统计, 取消时比例小于 1
+    if let Some(progress) = progress {
+        progress(&Progress {
+            stats: runtime.clone(),
+            speed: avg_speed as u64,
+            eta: 0,
+            percent: match runtime.require_bytes {
+                0 => 1.0,
+                require_bytes => process_bytes as f64 / require_bytes as f64,
+            },
+            elapsed: start.elapsed(),
+            parallel: httpd_parallel.limit(),
+        });
+    }
+
     println!("{}", runtime);
     println!("{:?}", start.elapsed());
+    println!("Report: {}", report_path);
 
+    if cancelled {
+        return Err(HttpdError::Cancelled);
+    }
     Ok(())
 }
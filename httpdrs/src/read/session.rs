@@ -0,0 +1,186 @@
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+
+use httpdrs_core::httpd::{HttpdError, PathPolicy, RetryPolicy, SignerConfig, TokenVerifier};
+use httpdrs_core::read::reader::MetaRow;
+
+use crate::config::{Config, TransferConfig};
+use crate::read::queue::{Job, JobQueue, QueueClosed};
+use crate::read::report::{FileResult, FileWaiters};
+use crate::read::runtime;
+use crate::read::state::{ProgressCallback, RuntimeContext, RuntimeSnapshot};
+
+/// SessionConfig 一次下载的参数
+/// 清单在 {use_loc}/meta, 文件下载到 {use_loc}/data, 分片和断点在 {use_loc}/temp
+pub struct SessionConfig {
+    pub max_bandwidth: u64, // MB/s
+    pub max_parallel: usize,
+    pub min_parallel: usize, // 分片并发在 [min_parallel, max_parallel] 之间调整
+    pub use_loc: String,
+    pub signer: SignerConfig,
+    pub verifier: TokenVerifier,
+    pub preallocate: bool,
+    pub path_policy: PathPolicy,
+    pub retry: RetryPolicy,
+    pub download: TransferConfig, // 分片大小, 文件并发, 合并缓冲和 HTTP 客户端
+    pub progress: Option<ProgressCallback>, // 每秒回调一次, 结束时再回调一次
+}
+
+impl SessionConfig {
+    pub fn from_config(
+        config: &Config,
+        progress: Option<ProgressCallback>,
+    ) -> Result<SessionConfig, String> {
+        let (signer, verifier) = config.signer_config()?;
+        Ok(SessionConfig {
+            max_bandwidth: config.max_bandwidth,
+            max_parallel: config.max_parallel,
+            min_parallel: config.min_parallel,
+            use_loc: config.use_loc.clone(),
+            signer,
+            verifier,
+            preallocate: config.preallocate,
+            path_policy: config.path_policy,
+            retry: config.retry.clone(),
+            download: config.download.clone(),
+            progress,
+        })
+    }
+
+    pub(crate) fn runtime_context(&self) -> RuntimeContext {
+        RuntimeContext::new(
+            format!("{}/meta", self.use_loc),
+            format!("{}/data", self.use_loc),
+            format!("{}/temp", self.use_loc),
+        )
+    }
+}
+
+type SessionHandle = thread::JoinHandle<Result<(), HttpdError>>;
+
+/// Session 一次下载, 拥有自己的统计, 任务队列和取消信号
+/// 同一进程中可以同时或先后运行多个 Session, 互不影响
+pub struct Session {
+    runtime_context: Arc<RuntimeContext>,
+    queue: JobQueue,
+    waiters: FileWaiters,
+    cancel: CancellationToken,
+    handle: Mutex<Option<SessionHandle>>,
+    result: OnceLock<Result<(), HttpdError>>,
+    done: watch::Receiver<Option<Result<(), HttpdError>>>, // 下载线程结束时写入结果
+}
+
+impl Session {
+    /// start 在后台线程开始下载, 通过 push 提交任务, close 后等待处理完成
+    pub fn start(config: SessionConfig) -> Session {
+        let runtime_context = Arc::new(config.runtime_context());
+        let (queue, rx_job) = JobQueue::new();
+        let waiters = FileWaiters::default();
+        let cancel = CancellationToken::new();
+        let (tx_done, rx_done) = watch::channel(None);
+
+        let handle = {
+            let runtime_context = Arc::clone(&runtime_context);
+            let waiters = waiters.clone();
+            let cancel = cancel.clone();
+            thread::spawn(move || {
+                let result = runtime::run(config, runtime_context, rx_job, waiters, cancel);
+                tx_done.send_replace(Some(result.clone()));
+                result
+            })
+        };
+
+        Session {
+            runtime_context,
+            queue,
+            waiters,
+            cancel,
+            handle: Mutex::new(Some(handle)),
+            result: OnceLock::new(),
+            done: rx_done,
+        }
+    }
+
+    pub fn push(&self, job: Job) -> Result<(), QueueClosed> {
+        self.queue.push(job)
+    }
+
+    pub fn push_meta(&self, meta_name: String) -> Result<(), QueueClosed> {
+        self.queue.push_meta(meta_name)
+    }
+
+    pub fn push_row(&self, meta_row: MetaRow) -> Result<(), QueueClosed> {
+        self.queue.push_row(meta_row)
+    }
+
+    /// push_row_wait 提交单个文件并等待它的处理结果
+    /// 下载取消或结束时还没有处理的文件返回 Cancelled
+    pub fn push_row_wait(
+        &self,
+        meta_row: MetaRow,
+    ) -> Result<impl Future<Output = Result<FileResult, HttpdError>> + use<>, QueueClosed> {
+        let rx_result = self.waiters.register(&meta_row.sign).ok_or(QueueClosed)?;
+        self.queue.push_row(meta_row)?;
+        Ok(async move { rx_result.await.map_err(|_| HttpdError::Cancelled) })
+    }
+
+    /// close 结束提交
+    pub fn close(&self) {
+        self.queue.close();
+    }
+
+    /// cancel 停止下载, 已经收到的文件结果仍然写入报告, wait 返回 Cancelled
+    pub fn cancel(&self) {
+        self.queue.close();
+        self.cancel.cancel();
+    }
+
+    pub fn stats(&self) -> RuntimeSnapshot {
+        self.runtime_context.snapshot()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        match self.handle.lock().unwrap().as_ref() {
+            Some(handle) => handle.is_finished(),
+            None => true,
+        }
+    }
+
+    /// wait 等待下载结束, 可以重复调用, 返回同一个结果
+    /// 下载线程 panic 时在调用方继续 panic
+    pub fn wait(&self) -> Result<(), HttpdError> {
+        let mut guard = self.handle.lock().unwrap();
+        if let Some(handle) = guard.take() {
+            let result = match handle.join() {
+                Ok(result) => result,
+                Err(panic) => std::panic::resume_unwind(panic),
+            };
+            let _ = self.result.set(result);
+        }
+        self.result.get().cloned().unwrap_or(Ok(()))
+    }
+
+    /// wait_async 不阻塞线程等待下载结束, 不依赖 tokio 运行时
+    pub async fn wait_async(&self) -> Result<(), HttpdError> {
+        let mut done = self.done.clone();
+        let result = match done.wait_for(|result| result.is_some()).await {
+            Ok(result) => result.clone(),
+            Err(_) => None,
+        };
+        match result {
+            Some(result) => result,
+            // 下载线程 panic 没有写入结果, 由 wait 继续 panic
+            None => self.wait(),
+        }
+    }
+}
+
+impl Drop for Session {
+    /// 没有 wait 的 Session 释放时结束提交, 已经提交的任务在后台继续下载
+    fn drop(&mut self) {
+        self.queue.close();
+    }
+}
@@ -1,23 +1,11 @@
-use std::collections::HashMap;
 use std::fmt::Display;
+use std::sync::Arc;
 use std::sync::atomic::AtomicU64;
-use std::sync::{Arc, LazyLock, Mutex, OnceLock};
+use std::time::Duration;
 
-use arc_swap::ArcSwap;
 use indicatif::HumanBytes;
 use tokio::sync::RwLock;
 
-pub(crate) static RUNTIME: OnceLock<RuntimeContext> = OnceLock::new();
-
-pub(crate) fn init_runtime(meta_path: String, data_path: String, temp_path: String) {
-    RUNTIME.get_or_init(|| RuntimeContext {
-        meta_path: RwLock::new(meta_path),
-        data_path: RwLock::new(data_path),
-        temp_path: RwLock::new(temp_path),
-        ..Default::default()
-    });
-}
-
 #[allow(dead_code)]
 #[derive(Debug, Default)]
 pub struct RuntimeContext {
@@ -40,9 +28,22 @@ pub struct RuntimeContext {
     // 下载失败
     pub uncompleted_count: AtomicU64, // 未完成下载的文件数量
     pub uncompleted_bytes: AtomicU64, // 未完成下载的文件大小
+
+    // 路径不安全被拒绝的文件, 同时计入下载失败
+    pub rejected_count: AtomicU64,
 }
 
 impl RuntimeContext {
+    /// new 每次下载独立的路径和计数
+    pub fn new(meta_path: String, data_path: String, temp_path: String) -> Self {
+        RuntimeContext {
+            meta_path: RwLock::new(meta_path),
+            data_path: RwLock::new(data_path),
+            temp_path: RwLock::new(temp_path),
+            ..Default::default()
+        }
+    }
+
     pub fn init(&mut self, meta_path: String, data_path: String, temp_path: String) {
         self.meta_path.get_mut().push_str(&meta_path);
         self.data_path.get_mut().push_str(&data_path);
@@ -67,12 +68,31 @@ impl RuntimeContext {
         self.completed_bytes
             .fetch_add(bytes, std::sync::atomic::Ordering::Relaxed);
     }
+    /// sub_completed 撤回已经统计的完成, 用于校验失败重新下载
+    pub fn sub_completed(&self, count: u64, bytes: u64) {
+        self.completed_count
+            .fetch_sub(count, std::sync::atomic::Ordering::Relaxed);
+        self.completed_bytes
+            .fetch_sub(bytes, std::sync::atomic::Ordering::Relaxed);
+    }
+    pub fn sub_download(&self, count: u64, bytes: u64) {
+        self.download_count
+            .fetch_sub(count, std::sync::atomic::Ordering::Relaxed);
+        self.download_bytes
+            .fetch_sub(bytes, std::sync::atomic::Ordering::Relaxed);
+    }
     pub fn add_uncompleted(&self, count: u64, bytes: u64) {
         self.uncompleted_count
             .fetch_add(count, std::sync::atomic::Ordering::Relaxed);
         self.uncompleted_bytes
             .fetch_add(bytes, std::sync::atomic::Ordering::Relaxed);
     }
+    /// add_rejected 路径不安全的文件记为失败
+    pub fn add_rejected(&self, count: u64, bytes: u64) {
+        self.add_uncompleted(count, bytes);
+        self.rejected_count
+            .fetch_add(count, std::sync::atomic::Ordering::Relaxed);
+    }
 }
 
 impl RuntimeContext {
@@ -105,6 +125,10 @@ impl RuntimeContext {
             uncompleted_bytes: self
                 .uncompleted_bytes
                 .load(std::sync::atomic::Ordering::Relaxed),
+
+            rejected_count: self
+                .rejected_count
+                .load(std::sync::atomic::Ordering::Relaxed),
         }
     }
 }
@@ -126,6 +150,8 @@ pub struct RuntimeSnapshot {
     // 下载失败
     pub uncompleted_count: u64, // 未完成下载的文件数量
     pub uncompleted_bytes: u64, // 未完成下载的文件大小
+
+    pub rejected_count: u64, // 路径不安全被拒绝的文件数量
 }
 
 impl Display for RuntimeSnapshot {
@@ -143,12 +169,24 @@ impl Display for RuntimeSnapshot {
             uncompleted_bytes_human,
             self.download_count,
             download_bytes_human
-        )
+        )?;
+        if self.rejected_count > 0 {
+            write!(f, ", Reject: {}", self.rejected_count)?;
+        }
+        Ok(())
     }
 }
-/// META 管理所有meta文件新
-pub(crate) static META: LazyLock<Arc<Mutex<HashMap<String, u64>>>> =
-    LazyLock::new(|| Arc::new(Mutex::new(HashMap::new())));
 
-pub static DATA: LazyLock<ArcSwap<String>> =
-    LazyLock::new(|| ArcSwap::new(Arc::new("".to_string())));
+/// Progress 定时回调的下载进度
+#[derive(Debug, Clone)]
+pub struct Progress {
+    pub stats: RuntimeSnapshot,
+    pub speed: u64,   // 最近一秒的下载速度, bytes/s
+    pub eta: u64,     // 预计剩余时间, 秒
+    pub percent: f64, // 已经处理的字节比例, 断点续传+成功+失败 / 总量
+    pub elapsed: Duration,
+    pub parallel: usize, // 当前的分片并发, 上传时为 0
+}
+
+/// ProgressCallback 在阻塞线程中调用, 可以执行耗时的操作
+pub type ProgressCallback = Arc<dyn Fn(&Progress) + Send + Sync>;
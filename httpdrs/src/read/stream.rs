@@ -1,29 +1,73 @@
-use std::path::PathBuf;
+use std::future::Future;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
+use futures::StreamExt;
 use indicatif::HumanBytes;
-use reqwest::Client;
-use reqwest::header::RANGE;
+use reqwest::header::{CONTENT_RANGE, RANGE};
+use reqwest::{Client, Response, StatusCode};
+use tokio::io::{AsyncWriteExt, BufWriter};
 use tokio::time::Instant;
 use tokio::{fs, time};
-use tokio_util::bytes::Bytes;
 
-use httpdrs_core::httpd::{HttpdMetaReader, SignatureClient};
-use httpdrs_core::read::presign;
+use httpdrs_core::httpd::{
+    Bandwidth, HttpdError, HttpdMetaReader, PathPolicy, Presigned, RetryPolicy,
+};
+use httpdrs_core::read::presign::PresignCache;
+
+use crate::config::TransferConfig;
+use crate::read::chunk::ChunkSizer;
+use crate::read::parallel::Parallel;
+use crate::read::partial::PartialFile;
+use crate::read::report::{FileResult, ReportSender};
+use crate::read::state::RuntimeContext;
 
 pub struct Args {
     pub data_path: String,
     pub temp_path: String,
+    pub preallocate: bool,        // 预分配目标文件, 分片按偏移写入, 不需要合并
+    pub path_policy: PathPolicy,  // 签名或清单中的路径不安全时拒绝或去掉
+    pub report: ReportSender,     // 每个文件的处理结果
+    pub retry: RetryPolicy,       // 分片和文件的重试策略
+    pub download: TransferConfig, // 分片大小和同时下载的文件数量
+    pub chunks: ChunkSizer,       // 按分片的吞吐量, 延迟和失败率选择每个文件的分片大小
+    pub parallel: Arc<Parallel>,  // 同时下载的分片数量, 按吞吐量和拥塞调整
+    pub runtime: Arc<RuntimeContext>, // 本次下载的统计
 }
 
 impl Args {
-    pub fn new(data_path: String, temp_path: String) -> Arc<Self> {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        data_path: String,
+        temp_path: String,
+        preallocate: bool,
+        path_policy: PathPolicy,
+        report: ReportSender,
+        retry: RetryPolicy,
+        download: TransferConfig,
+        parallel: Arc<Parallel>,
+        runtime: Arc<RuntimeContext>,
+    ) -> Arc<Self> {
         let args = Args {
             data_path,
             temp_path,
+            preallocate,
+            path_policy,
+            report,
+            retry,
+            chunks: ChunkSizer::new(&download),
+            download,
+            parallel,
+            runtime,
         };
         Arc::new(args)
     }
+
+    /// report 记录文件的处理结果, 报告已经结束时丢弃
+    pub fn report(&self, result: FileResult) {
+        let _ = self.report.send(result);
+    }
 }
 
 pub struct Range {
@@ -31,26 +75,33 @@ pub struct Range {
     pub start_pos: u64,
     pub end_pos: u64,
     pub total_parts: u64,
+    pub chunk_size: u64, // 文件的分片大小, 分片文件名的一部分
     pub sign: String,
     pub args: Arc<Args>,
+    pub partial: Option<Arc<PartialFile>>,
 }
 
 impl Range {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         idx_part: u64,
         start_pos: u64,
         end_pos: u64,
         total_parts: u64,
+        chunk_size: u64,
         sign: String,
         args: Arc<Args>,
+        partial: Option<Arc<PartialFile>>,
     ) -> Self {
         Range {
             idx_part,
             start_pos,
             end_pos,
             total_parts,
+            chunk_size,
             sign,
             args,
+            partial,
         }
     }
 
@@ -69,6 +120,7 @@ impl Range {
                 reader.local_part_path(
                     self.args.data_path.as_str(),
                     self.idx_part,
+                    self.chunk_size,
                     self.args.temp_path.as_str(),
                 ),
                 self.total_parts,
@@ -80,64 +132,63 @@ impl Range {
     }
 }
 
-/// stream_download_range 请求网络获取数据块
-/// 返回值是下载的(数据块大小, 下载状态)，None -> retry
+/// 写入目标文件的缓冲大小, 每个进行中的分片占用一份
+const STREAM_BUFFER_SIZE: usize = 256 * 1024;
+
+/// stream_download_range 请求网络获取数据块, 边读取边写入目标文件
+/// 每次请求的吞吐量, 延迟和失败记录到 args.chunks, 用于选择之后文件的分片大小
+/// 失败同时记录到 args.parallel, 拥塞时减少并发
+/// 返回值是下载的(数据块大小, 下载状态), Err -> retry
 /// 下载状态 0: skip, 1: down
 pub async fn stream_download_range(
+    bandwidth: Arc<Bandwidth>,
     client_down: Arc<Client>,
-    client_sign: Arc<SignatureClient>,
+    presign_cache: Arc<PresignCache>,
     reader_ref: Arc<HttpdMetaReader>,
     range: Range,
-) -> Option<(usize, usize)> {
+) -> Result<(usize, usize), HttpdError> {
     let start = Instant::now();
 
     let (range_path, _) = range.path(reader_ref);
 
-    let presign_url = presign::read(range.sign.clone(), client_sign).await?;
-
-    let mut retry_count = 0;
-    let max_retries = 20;
-    let resp_bytes = loop {
-        let resp_range =
-            stream_request_range(Arc::clone(&client_down), &presign_url, &range.header()).await;
-        match resp_range {
-            Some(resp_part) => break Some(resp_part),
-            None => {
-                retry_count += 1;
-                if retry_count <= max_retries {
-                    time::sleep(time::Duration::from_secs(retry_count as u64)).await;
-                    break None;
-                }
-                tracing::error!(
-                    "download_retry, retry: {}, presign: {}",
-                    retry_count,
-                    presign_url
-                );
+    let chunks = &range.args.chunks;
+    let context = format!(
+        "pos: ({}){}-{}",
+        range.idx_part, range.start_pos, range.end_pos
+    );
+    let (resp_len, retry_count) = stream_request_retry(
+        client_down,
+        &presign_cache,
+        &range.sign,
+        &range.header(),
+        &range.args.retry,
+        &context,
+        |resp, latency| {
+            let (bandwidth, range, range_path) = (Arc::clone(&bandwidth), &range, &range_path);
+            async move {
+                let write_start = Instant::now();
+                let resp_len = stream_write_range(bandwidth, resp, range, range_path).await?;
+                chunks.observe(resp_len as u64, latency + write_start.elapsed(), latency);
+                Ok(resp_len)
             }
-        }
-    }?;
+        },
+        |err| {
+            chunks.failure();
+            range.args.parallel.failure(err);
+        },
+    )
+    .await?;
 
-    if let Some(parent) = std::path::Path::new(&range_path).parent() {
-        match fs::create_dir_all(parent).await {
-            Ok(_) => {}
-            Err(err) => {
-                tracing::error!("download_err, create dir err: {}", err);
-                return None;
-            }
-        }
-    }
-    let resp_len = resp_bytes.len();
-    match fs::write(range_path.clone(), &resp_bytes).await {
-        Ok(_) => {}
-        Err(err) => {
-            tracing::error!("download_err, save err: {}", err);
-            return None;
-        }
+    // 预分配模式: 数据写入后再记录分片完成
+    if let Some(partial) = &range.partial
+        && let Err(err) = partial.mark_done(range.idx_part).await
+    {
+        tracing::error!("download_err, save bitmap err: {}", err);
+        return Err(err.into());
     }
 
     let end_duration = start.elapsed();
-    let use_sec = end_duration.as_millis();
-    let download_speed = resp_len / use_sec as usize * 1000;
+    let download_speed = resp_len as u128 * 1000 / (end_duration.as_millis() + 1);
     let download_speed_str = HumanBytes(download_speed as u64);
 
     tracing::info!(
@@ -150,33 +201,360 @@ pub async fn stream_download_range(
         range.start_pos,
         range.end_pos
     );
-    Some((resp_len, 1))
+    Ok((resp_len, 1))
+}
+
+/// 链接过期或被拒绝 (403) 时重新签名的次数上限, 不计入重试次数
+const MAX_REFRESHES: u32 = 3;
+
+/// stream_request_retry 发送分片请求并用 handle 处理响应, 文件下载和 ObjectReader 共用
+/// 403 时重新签名后立即重试, 其他错误按 retry 重试, 每次失败调用 on_failure
+/// handle 的第二个参数是收到响应头的时间, context 用于日志
+/// 返回值是 (handle 的结果, 重试次数)
+#[allow(clippy::too_many_arguments)]
+pub async fn stream_request_retry<T, F, Fut>(
+    client: Arc<Client>,
+    presign_cache: &PresignCache,
+    sign: &str,
+    range: &str,
+    retry: &RetryPolicy,
+    context: &str,
+    handle: F,
+    on_failure: impl Fn(&HttpdError),
+) -> Result<(T, u32), HttpdError>
+where
+    F: Fn(Response, Duration) -> Fut,
+    Fut: Future<Output = Result<T, HttpdError>>,
+{
+    let mut presigned = presign_cache.get(sign).await?;
+
+    let mut retry_count = 0;
+    let mut refresh_count = 0;
+    loop {
+        let request_start = Instant::now();
+        let result = match stream_request_range(Arc::clone(&client), &presigned, range).await {
+            Ok(resp) => handle(resp, request_start.elapsed()).await,
+            Err(err) if err.is_status(StatusCode::FORBIDDEN) && refresh_count < MAX_REFRESHES => {
+                // 链接过期或被拒绝, 重新签名后立即重试
+                refresh_count += 1;
+                presign_cache.invalidate(sign, &presigned.endpoint);
+                presigned = presign_cache.get(sign).await?;
+                continue;
+            }
+            Err(err) => Err(err),
+        };
+        let err = match result {
+            Ok(value) => return Ok((value, retry_count)),
+            Err(err) => err,
+        };
+
+        on_failure(&err);
+        retry_count += 1;
+        if retry_count >= retry.range_attempts || !retry.is_retryable(&err) {
+            tracing::error!(
+                "download_retry, attempt: {}, {}, {}",
+                retry_count,
+                context,
+                err
+            );
+            return Err(err);
+        }
+        let delay = retry.backoff(retry_count);
+        tracing::warn!(
+            "download_retry, attempt: {}/{}, delay: {:?}, {}, {}",
+            retry_count,
+            retry.range_attempts,
+            delay,
+            context,
+            err
+        );
+        time::sleep(delay).await;
+    }
 }
 
-pub async fn stream_request_range(client: Arc<Client>, url: &str, range: &str) -> Option<Bytes> {
-    let rs_send = client.get(url).header(RANGE, range).send().await;
+/// stream_request_range 发送分片请求
+/// Err 是存储返回的错误状态或网络错误
+pub async fn stream_request_range(
+    client: Arc<Client>,
+    presigned: &Presigned,
+    range: &str,
+) -> Result<Response, HttpdError> {
+    let mut request = client.get(&presigned.endpoint).header(RANGE, range);
+    for (name, value) in &presigned.headers {
+        request = request.header(name, value);
+    }
+    let rs_send = request.send().await;
     let resp = match rs_send {
         Ok(resp) => resp,
         Err(err) => {
             tracing::error!("stream_request, reqwest err: {}", err);
-            return None;
+            return Err(err.into());
         }
     };
 
     if !resp.status().is_success() {
         tracing::error!("stream_request, reqwest status: {}", resp.status());
-        return None;
+        return Err(HttpdError::status(resp.status(), ""));
     }
 
-    // TODO: 这种情况的需要增加重试
-    let rs_bytes = resp.bytes().await;
-    let bytes = match rs_bytes {
-        Ok(bytes) => bytes,
-        Err(err) => {
-            tracing::error!("stream_request, reqwest read bytes err: {}", err);
-            return None;
+    Ok(resp)
+}
+
+/// check_range 检查响应是请求的范围: 状态必须是 206, 有 Content-Range 时起止位置必须一致
+/// 存储忽略 Range 返回 200 和整个对象时, 按偏移写入会覆盖 .partial 中相邻的分片
+pub fn check_range(resp: &Response, start_pos: u64, end_pos: u64) -> Result<(), HttpdError> {
+    if resp.status() != StatusCode::PARTIAL_CONTENT {
+        tracing::error!(
+            "stream_request, range not supported, status: {}",
+            resp.status()
+        );
+        return Err(HttpdError::status(resp.status(), "range not supported"));
+    }
+    let expected = format!("bytes {}-{}/", start_pos, end_pos - 1);
+    match resp.headers().get(CONTENT_RANGE) {
+        None => Ok(()),
+        Some(value)
+            if value
+                .to_str()
+                .is_ok_and(|value| value.starts_with(&expected)) =>
+        {
+            Ok(())
+        }
+        Some(value) => {
+            tracing::error!(
+                "stream_request, content-range: {:?}, expected: {}",
+                value,
+                expected
+            );
+            Err(HttpdError::status(
+                resp.status(),
+                format!("content-range: {:?}, expected: {}", value, expected),
+            ))
+        }
+    }
+}
+
+/// RangeWriter 分片的写入位置: 单独的分片文件, 或者预分配的 .partial
+enum RangeWriter {
+    File(BufWriter<fs::File>),
+    Partial {
+        partial: Arc<PartialFile>,
+        offset: u64,     // 缓冲区数据在 .partial 中的位置
+        buffer: Vec<u8>, // 攒够 STREAM_BUFFER_SIZE 再写入, 减少阻塞线程的调用
+    },
+}
+
+impl RangeWriter {
+    async fn write_all(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        match self {
+            RangeWriter::File(writer) => writer.write_all(bytes).await,
+            RangeWriter::Partial { buffer, .. } => {
+                buffer.extend_from_slice(bytes);
+                match buffer.len() >= STREAM_BUFFER_SIZE {
+                    true => self.flush().await,
+                    false => Ok(()),
+                }
+            }
+        }
+    }
+
+    async fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            RangeWriter::File(writer) => writer.flush().await,
+            RangeWriter::Partial {
+                partial,
+                offset,
+                buffer,
+            } => {
+                if buffer.is_empty() {
+                    return Ok(());
+                }
+                let data = std::mem::replace(buffer, Vec::with_capacity(STREAM_BUFFER_SIZE));
+                let data_len = data.len() as u64;
+                partial.write_at(*offset, data).await?;
+                *offset += data_len;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// stream_write_range 按块读取响应并写入文件, 每块写入前申请带宽
+/// 内存占用只和缓冲大小有关, 和分片大小、并发数量无关
+async fn stream_write_range(
+    bandwidth: Arc<Bandwidth>,
+    resp: Response,
+    range: &Range,
+    range_path: &Path,
+) -> Result<usize, HttpdError> {
+    // 写入前检查, 不是请求的范围时不打开分片文件, 也不写入 .partial
+    check_range(&resp, range.start_pos, range.end_pos)?;
+
+    let mut writer = match &range.partial {
+        Some(partial) => RangeWriter::Partial {
+            partial: Arc::clone(partial),
+            offset: range.start_pos,
+            buffer: Vec::with_capacity(STREAM_BUFFER_SIZE),
+        },
+        None => {
+            if let Some(parent) = range_path.parent()
+                && let Err(err) = fs::create_dir_all(parent).await
+            {
+                tracing::error!("download_err, create dir err: {}", err);
+                return Err(err.into());
+            }
+            match fs::File::create(range_path).await {
+                Ok(range_file) => {
+                    RangeWriter::File(BufWriter::with_capacity(STREAM_BUFFER_SIZE, range_file))
+                }
+                Err(err) => {
+                    tracing::error!("download_err, open {:?} err: {}", range_path, err);
+                    return Err(err.into());
+                }
+            }
         }
     };
 
-    Some(bytes)
+    let name = format!("{}", range.idx_part);
+    let mut resp_len = 0;
+    let mut resp_stream = resp.bytes_stream();
+    while let Some(rs_bytes) = resp_stream.next().await {
+        let bytes = match rs_bytes {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                tracing::error!("stream_request, reqwest read bytes err: {}", err);
+                return Err(err.into());
+            }
+        };
+        // 超出分片大小时立即停止, 不能写到下一个分片的位置
+        if (resp_len + bytes.len()) as u64 > range.size() {
+            tracing::error!(
+                "download_err, range size: {}, resp_len: {}",
+                range.size(),
+                resp_len + bytes.len()
+            );
+            return Err(HttpdError::Network(format!(
+                "range size: {}, resp_len: {}",
+                range.size(),
+                resp_len + bytes.len()
+            )));
+        }
+        let _ = bandwidth.permit(bytes.len() as u64, name.clone()).await; // 获取可以使用带宽后才可以写入
+        range.args.parallel.record(bytes.len() as u64);
+        if let Err(err) = writer.write_all(&bytes).await {
+            tracing::error!("download_err, save err: {}", err);
+            return Err(err.into());
+        }
+        resp_len += bytes.len();
+    }
+    if let Err(err) = writer.flush().await {
+        tracing::error!("download_err, save err: {}", err);
+        return Err(err.into());
+    }
+
+    if resp_len as u64 != range.size() {
+        tracing::error!(
+            "download_err, range size: {}, resp_len: {}",
+            range.size(),
+            resp_len
+        );
+        return Err(HttpdError::Network(format!(
+            "range size: {}, resp_len: {}",
+            range.size(),
+            resp_len
+        )));
+    }
+    Ok(resp_len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    /// serve 在本地端口返回一次固定的响应, 返回请求地址
+    async fn serve(response: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let n = stream.read(&mut buf).await.unwrap();
+                if n == 0 {
+                    return;
+                }
+                request.extend_from_slice(&buf[..n]);
+            }
+            stream.write_all(response.as_bytes()).await.unwrap();
+        });
+        format!("http://{}/object", addr)
+    }
+
+    #[tokio::test]
+    async fn test_range_ignored() {
+        let temp_dir = std::env::temp_dir().join(format!("httpdrs-stream-{}", std::process::id()));
+        let local_path = temp_dir.join("data/a.bin");
+        let partial = PartialFile::open(local_path, temp_dir.join("temp/a.bitmap"), 10, 4)
+            .await
+            .unwrap();
+        partial.write_at(0, b"0123".to_vec()).await.unwrap();
+        partial.mark_done(0).await.unwrap();
+
+        let (report, _) = tokio::sync::mpsc::unbounded_channel();
+        let args = Args::new(
+            String::new(),
+            String::new(),
+            true,
+            PathPolicy::default(),
+            report,
+            RetryPolicy::default(),
+            TransferConfig::download(),
+            Parallel::new(1, 1),
+            Arc::new(RuntimeContext::default()),
+        );
+        let range = Range::new(1, 4, 8, 3, 4, String::new(), args, Some(partial.clone()));
+        let client = Client::new();
+        let bandwidth = Bandwidth::new(1024 * 1024);
+
+        // 存储忽略 Range 返回 200 和整个对象
+        let url = serve("HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nabcdefghij").await;
+        let resp = client.get(url).header(RANGE, range.header()).send().await;
+        let err = stream_write_range(Arc::clone(&bandwidth), resp.unwrap(), &range, Path::new(""))
+            .await
+            .unwrap_err();
+        assert!(err.is_status(StatusCode::OK), "{}", err);
+
+        // Content-Range 不是请求的范围
+        let url = serve(
+            "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes 0-9/10\r\nContent-Length: 10\r\n\r\nabcdefghij",
+        )
+        .await;
+        let resp = client.get(url).header(RANGE, range.header()).send().await;
+        let err = stream_write_range(Arc::clone(&bandwidth), resp.unwrap(), &range, Path::new(""))
+            .await
+            .unwrap_err();
+        assert!(err.is_status(StatusCode::PARTIAL_CONTENT), "{}", err);
+
+        // 没有 Content-Range 时, 超出分片大小的数据不写入
+        let url =
+            serve("HTTP/1.1 206 Partial Content\r\nContent-Length: 10\r\n\r\nabcdefghij").await;
+        let resp = client.get(url).header(RANGE, range.header()).send().await;
+        let err = stream_write_range(Arc::clone(&bandwidth), resp.unwrap(), &range, Path::new(""))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, HttpdError::Network(_)), "{}", err);
+
+        // 已经完成的分片没有被覆盖
+        partial.flush().await.unwrap();
+        let data = fs::read(&partial.partial_path).await.unwrap();
+        assert_eq!(&data[..4], b"0123");
+        assert!(partial.is_done(0).await);
+        assert!(!partial.is_done(1).await);
+
+        fs::remove_dir_all(&temp_dir).await.unwrap();
+    }
 }
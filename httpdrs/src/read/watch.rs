@@ -1,15 +1,35 @@
+use std::sync::Arc;
+
 use indicatif::{HumanBytes, ProgressBar};
 use tokio::time::Instant;
 use tokio_util::sync::CancellationToken;
 
 use httpdrs_core::pbar;
 
-use crate::read::state::RUNTIME;
+use crate::read::parallel::Parallel;
+use crate::read::state::{Progress, ProgressCallback, RuntimeContext};
+
+/// format_parallel 进度条信息加上当前的分片并发, 上传时没有
+pub(crate) fn format_parallel(message: String, parallel: Option<usize>) -> String {
+    match parallel {
+        Some(parallel) => format!("{} | parallel: {}", message, parallel),
+        None => message,
+    }
+}
 
-pub(crate) async fn init(pb: ProgressBar, token_bandwidth: CancellationToken) {
+pub(crate) async fn init(
+    pb: ProgressBar,
+    runtime_context: Arc<RuntimeContext>,
+    parallel: Option<Arc<Parallel>>,
+    progress: Option<ProgressCallback>,
+    token_bandwidth: CancellationToken,
+) {
     let start = Instant::now();
 
-    pb.set_message(pbar::format(0, 0, 0.0, 0, 0));
+    pb.set_message(format_parallel(
+        pbar::format(0, 0, 0.0, 0, 0),
+        parallel.as_ref().map(|parallel| parallel.limit()),
+    ));
 
     let mut last_count: u64 = 0;
     let mut last_bytes: u64 = 0;
@@ -21,6 +41,7 @@ pub(crate) async fn init(pb: ProgressBar, token_bandwidth: CancellationToken) {
                 let _start_count = last_count;
                 let start_bytes = last_bytes;
 
+                let stats = runtime_context.snapshot();
                 let (
                     require_bytes,
                     require_count,
@@ -31,7 +52,7 @@ pub(crate) async fn init(pb: ProgressBar, token_bandwidth: CancellationToken) {
                     download_bytes,
                     download_count
                 ) = {
-                    let runtime = RUNTIME.get().unwrap().snapshot();
+                    let runtime = &stats;
                     (
                         runtime.require_bytes,
                         runtime.require_count,
@@ -54,7 +75,7 @@ pub(crate) async fn init(pb: ProgressBar, token_bandwidth: CancellationToken) {
                 last_bytes = completed_bytes;
 
                 let use_ms = start.elapsed().as_millis();
-                let period_bytes = last_bytes - start_bytes;
+                let period_bytes = last_bytes.saturating_sub(start_bytes); // 重新下载时已完成的字节会撤回
                 let period_speed = match use_ms {
                     0 => 0,
                     _ => period_bytes,
@@ -88,17 +109,39 @@ pub(crate) async fn init(pb: ProgressBar, token_bandwidth: CancellationToken) {
                     _ => remaining_bytes as u128 / speed_avg,
                 };
 
-                tracing::info!("download_watch, last_speed: {}/s", HumanBytes(last_speed));
+                let current_parallel = parallel.as_ref().map(|parallel| parallel.limit());
+                tracing::info!(
+                    "download_watch, last_speed: {}/s, parallel: {}",
+                    HumanBytes(last_speed),
+                    current_parallel.map_or("-".to_string(), |parallel| parallel.to_string())
+                );
                 pb.set_length(require_count);
                 pb.set_position(last_count);
 
-                pb.set_message(pbar::format(
-                    require_bytes,
-                    period_speed,
-                    download_percent,
-                    process_bytes,
-                    remaining_time
+                pb.set_message(format_parallel(
+                    pbar::format(
+                        require_bytes,
+                        period_speed,
+                        download_percent,
+                        process_bytes,
+                        remaining_time
+                    ),
+                    current_parallel,
                 ));
+
+                if let Some(progress) = &progress {
+                    let current = Progress {
+                        stats,
+                        speed: period_speed,
+                        eta: remaining_time as u64,
+                        percent: download_percent,
+                        elapsed: start.elapsed(),
+                        parallel: current_parallel.unwrap_or(0),
+                    };
+                    // 回调可能等待 GIL, 不占用工作线程; 等待回调结束后再开始下一次
+                    let progress = Arc::clone(progress);
+                    let _ = tokio::task::spawn_blocking(move || progress(&current)).await;
+                }
             }
             _ = token_bandwidth.cancelled() => {
                 break;
@@ -0,0 +1,27 @@
+/// shutdown 等待退出信号
+pub(crate) async fn shutdown() {
+    #[cfg(unix)]
+    {
+        let mut sigint =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::interrupt()).expect("");
+        let mut sigterm =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()).expect("");
+
+        tokio::select! {
+            _ = sigint.recv() => {
+                println!("\n收到中断信号 (Ctrl+C)，正在退出...");
+            },
+            _ = sigterm.recv() => {
+                println!("\n收到终止信号，正在退出...");
+            }
+        }
+    }
+
+    #[cfg(windows)]
+    {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to install Ctrl+C handler");
+        println!("\n收到 Ctrl+C 信号，正在退出...");
+    }
+}
@@ -1 +1,6 @@
-
+pub mod multipart;
+pub mod runtime;
+pub mod state;
+pub mod stream;
+pub mod upload;
+pub mod uploader;
@@ -0,0 +1,306 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tokio::sync::{Semaphore, mpsc};
+
+use httpdrs_core::httpd::writer::MultipartAction;
+use httpdrs_core::httpd::{Bandwidth, HttpdError, Signer};
+use httpdrs_core::io;
+use httpdrs_core::request::FSWriter;
+use httpdrs_core::write::presign;
+
+use crate::write::state::Args;
+use crate::write::stream;
+
+/// 分片上传的断点记录, 保存在 temp 目录下
+/// 每确认一个分片就写一次, 中断后从已确认的分片继续
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct UploadState {
+    upload_id: String,
+    require_size: u64,
+    chunk_size: u64,
+    parts: BTreeMap<u64, String>, // part_number -> etag
+}
+
+impl UploadState {
+    async fn load(state_path: &Path) -> Option<Self> {
+        let state_data = fs::read(state_path).await.ok()?;
+        match serde_json::from_slice(&state_data) {
+            Ok(state) => Some(state),
+            Err(err) => {
+                tracing::warn!("upload_state, parse {:?} err: {}", state_path, err);
+                None
+            }
+        }
+    }
+
+    async fn save(&self, state_path: &Path) -> std::io::Result<()> {
+        io::write_replace(state_path, &serde_json::to_vec(self)?).await
+    }
+}
+
+/// 单个文件的分片数量上限 (S3 最多 10000 个分片)
+const MAX_PARTS: u64 = 10_000;
+
+/// 分片大小按 1MiB 对齐
+const CHUNK_ALIGN: u64 = 1024 * 1024;
+
+/// chunk_size 文件的分片大小, 分片数量超过 MAX_PARTS 时使用更大的分片
+pub fn chunk_size(require_size: u64, chunk_size: u64) -> u64 {
+    let min_chunk = require_size.div_ceil(MAX_PARTS).div_ceil(CHUNK_ALIGN) * CHUNK_ALIGN;
+    chunk_size.max(min_chunk)
+}
+
+/// upload_multipart 分片上传大文件: initiate -> part * N -> complete
+/// 分片字节数计入 args.runtime, 文件数量由调用方统计
+/// 上传记录已经失效 (404, 例如被清理或中止) 时删除记录, 重新创建一次分片上传
+#[allow(clippy::too_many_arguments)]
+pub async fn upload_multipart(
+    bandwidth: Arc<Bandwidth>,
+    jobs: Arc<Semaphore>,
+    client_up: Arc<Client>,
+    client_sign: Arc<dyn Signer>,
+    args: Arc<Args>,
+    request_writer: Arc<FSWriter>,
+    local_path: PathBuf,
+    state_path: PathBuf,
+) -> Result<(), HttpdError> {
+    let mut counted = BTreeSet::new(); // 已经计入统计的分片, 重新上传时不再统计
+    let mut reinitiated = false;
+    loop {
+        let uploaded = upload_parts(
+            Arc::clone(&bandwidth),
+            Arc::clone(&jobs),
+            Arc::clone(&client_up),
+            Arc::clone(&client_sign),
+            Arc::clone(&args),
+            Arc::clone(&request_writer),
+            &local_path,
+            &state_path,
+            &mut counted,
+        )
+        .await;
+        match uploaded {
+            Err(err) if err.is_status(StatusCode::NOT_FOUND) && !reinitiated => {
+                tracing::warn!(
+                    "upload_multipart, upload not found, initiate again, local_path: {:?}, {}",
+                    local_path,
+                    err
+                );
+                fs::remove_file(&state_path).await.unwrap_or(());
+                reinitiated = true;
+            }
+            uploaded => return uploaded,
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn upload_parts(
+    bandwidth: Arc<Bandwidth>,
+    jobs: Arc<Semaphore>,
+    client_up: Arc<Client>,
+    client_sign: Arc<dyn Signer>,
+    args: Arc<Args>,
+    request_writer: Arc<FSWriter>,
+    local_path: &Path,
+    state_path: &Path,
+    counted: &mut BTreeSet<u64>,
+) -> Result<(), HttpdError> {
+    let sign = &request_writer.request_sign;
+    let require_size = request_writer.require_size;
+    let chunk_size = request_writer.chunk_size;
+    let total_parts = request_writer.total_parts();
+
+    let mut upload_state = match UploadState::load(state_path).await {
+        Some(state)
+            if !state.upload_id.is_empty()
+                && state.require_size == require_size
+                && state.chunk_size == chunk_size =>
+        {
+            tracing::info!(
+                "upload_multipart, resume: {}, parts: {}/{}",
+                state.upload_id,
+                state.parts.len(),
+                total_parts
+            );
+            state
+        }
+        _ => {
+            let initiate_url = presign::multipart(
+                sign.clone(),
+                Arc::clone(&client_sign),
+                MultipartAction::Initiate,
+                "".to_string(),
+                None,
+            )
+            .await?;
+            let upload_id =
+                stream::stream_initiate_multipart(Arc::clone(&client_up), &initiate_url).await?;
+
+            let state = UploadState {
+                upload_id,
+                require_size,
+                chunk_size,
+                parts: BTreeMap::new(),
+            };
+            if let Err(err) = state.save(state_path).await {
+                tracing::error!("upload_state, save {:?} err: {}", state_path, err);
+                return Err(err.into());
+            }
+            state
+        }
+    };
+
+    let part_size = |part_number: u64| {
+        let part_start = (part_number - 1) * chunk_size;
+        let part_end = (part_number * chunk_size).min(require_size);
+        (part_start, part_end - part_start)
+    };
+
+    // 已经确认的分片
+    for part_number in upload_state.parts.keys() {
+        if counted.insert(*part_number) {
+            let (_, size) = part_size(*part_number);
+            args.runtime.add_download(0, size);
+        }
+    }
+
+    let (tx_part, mut rx_part) = mpsc::channel::<(u64, Result<String, HttpdError>)>(100);
+    for part_number in 1..=total_parts {
+        if upload_state.parts.contains_key(&part_number) {
+            continue;
+        }
+        let (part_start, size) = part_size(part_number);
+
+        let bandwidth_ = Arc::clone(&bandwidth);
+        let jobs_ = Arc::clone(&jobs);
+        let client_up_ = Arc::clone(&client_up);
+        let client_sign_ = Arc::clone(&client_sign);
+        let tx_part_ = tx_part.clone();
+        let sign_ = sign.clone();
+        let upload_id = upload_state.upload_id.clone();
+        let local_path_ = local_path.to_path_buf();
+        let args_ = Arc::clone(&args);
+
+        tokio::spawn(async move {
+            // 上传并发控制, 获取许可后再签名, 避免同时签名所有分片和链接在等待时过期
+            let Ok(_permit) = jobs_.acquire().await else {
+                let _ = tx_part_
+                    .send((part_number, Err(HttpdError::Cancelled)))
+                    .await;
+                return;
+            };
+
+            let uploaded = stream::stream_upload_retry(
+                client_up_,
+                bandwidth_,
+                &args_.retry,
+                || {
+                    presign::multipart(
+                        sign_.clone(),
+                        Arc::clone(&client_sign_),
+                        MultipartAction::Part,
+                        upload_id.clone(),
+                        Some(part_number),
+                    )
+                },
+                &local_path_,
+                part_start,
+                size,
+            )
+            .await;
+            if let Ok((_, retry_count)) = &uploaded {
+                tracing::info!(
+                    "upload_part, part: {}/{}, retry: {}",
+                    part_number,
+                    total_parts,
+                    retry_count
+                );
+            }
+            // 分片上传完成时需要每个分片的 ETag
+            let etag = match uploaded {
+                Ok((etag, _)) if etag.is_empty() => {
+                    tracing::error!("upload_part, ETag not found, part: {}", part_number);
+                    Err(HttpdError::status(StatusCode::OK, "ETag not found"))
+                }
+                uploaded => uploaded.map(|(etag, _)| etag),
+            };
+            let _ = tx_part_.send((part_number, etag)).await;
+        });
+    }
+    drop(tx_part);
+
+    let mut part_err = None; // 第一个失败分片的错误, 404 优先, 用于判断是否重新创建
+    while let Some((part_number, etag)) = rx_part.recv().await {
+        let etag = match etag {
+            Ok(etag) => etag,
+            Err(err) => {
+                if part_err.is_none() || err.is_status(StatusCode::NOT_FOUND) {
+                    part_err = Some(err);
+                }
+                continue;
+            }
+        };
+        if counted.insert(part_number) {
+            let (_, size) = part_size(part_number);
+            args.runtime.add_completed(0, size);
+        }
+
+        upload_state.parts.insert(part_number, etag);
+        if let Err(err) = upload_state.save(state_path).await {
+            tracing::warn!("upload_state, save {:?} err: {}", state_path, err);
+        }
+    }
+
+    if upload_state.parts.len() as u64 != total_parts {
+        tracing::error!(
+            "upload_multipart, incomplete: {}/{}, local_path: {:?}",
+            upload_state.parts.len(),
+            total_parts,
+            local_path
+        );
+        return Err(part_err.unwrap_or(HttpdError::Cancelled));
+    }
+
+    let complete_url = presign::multipart(
+        sign.clone(),
+        Arc::clone(&client_sign),
+        MultipartAction::Complete,
+        upload_state.upload_id.clone(),
+        None,
+    )
+    .await?;
+
+    let parts: Vec<(u64, String)> = upload_state
+        .parts
+        .iter()
+        .map(|(part_number, etag)| (*part_number, etag.clone()))
+        .collect();
+    stream::stream_complete_multipart(client_up, &complete_url, &parts).await?;
+
+    fs::remove_file(state_path).await.unwrap_or(());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIB: u64 = 1024 * 1024;
+
+    #[test]
+    fn test_chunk_size() {
+        // 分片数量不超过上限时使用配置的分片大小
+        assert_eq!(chunk_size(100 * MIB, 5 * MIB), 5 * MIB);
+        assert_eq!(chunk_size(10_000 * 5 * MIB, 5 * MIB), 5 * MIB);
+        // 超过上限时增大分片, 按 1MiB 对齐
+        let size = 10_000 * 5 * MIB + 1;
+        assert_eq!(chunk_size(size, 5 * MIB), 6 * MIB);
+        assert!(size.div_ceil(chunk_size(size, 5 * MIB)) <= MAX_PARTS);
+    }
+}
@@ -0,0 +1,130 @@
+use std::sync::Arc;
+use std::thread;
+
+use tokio::runtime;
+use tokio::sync::Semaphore;
+use tokio_util::sync::CancellationToken;
+
+use httpdrs_core::httpd::{HttpdError, PathPolicy, RetryPolicy, SignerConfig, TokenVerifier};
+
+use crate::config::TransferConfig;
+use crate::core::{httpd, pbar};
+use crate::read::state::{RuntimeContext, RuntimeSnapshot};
+use crate::write::state::Args;
+use crate::write::uploader;
+use crate::{bandwidth, read, signal};
+
+/// start_multi_thread 上传 {use_loc}/upload 下清单中的文件, 返回本次上传的统计
+#[allow(clippy::too_many_arguments)]
+pub fn start_multi_thread(
+    max_bandwidth: u64,
+    max_parallel: usize,
+    use_loc: String,
+    signer: SignerConfig,
+    verifier: TokenVerifier,
+    path_policy: PathPolicy,
+    retry: RetryPolicy,
+    upload: TransferConfig,
+) -> Result<RuntimeSnapshot, HttpdError> {
+    let start = tokio::time::Instant::now();
+
+    let rt = runtime::Builder::new_multi_thread()
+        .worker_threads(thread::available_parallelism().map_or(4, |n| n.get()))
+        .enable_all()
+        .build()?;
+
+    let rt_token = CancellationToken::new();
+
+    let meta_path = format!("{}/upload", use_loc);
+    let data_path = format!("{}/data", use_loc);
+    let temp_path = format!("{}/temp", use_loc);
+    let runtime = Arc::new(RuntimeContext::new(
+        meta_path,
+        data_path.clone(),
+        temp_path.clone(),
+    ));
+    let args = Args::new(
+        data_path,
+        temp_path,
+        path_policy,
+        retry.clone(),
+        upload.chunk_size,
+        Arc::clone(&runtime),
+    );
+
+    let client_up = Arc::new(upload.client.build()?);
+
+    let verified = verifier.is_verified();
+    let client_sign = signer.build_writer(verifier, retry);
+    tracing::info!(
+        "Runtime signer: {}, verify token: {}",
+        client_sign.name(),
+        verified
+    );
+
+    let httpd_bandwidth = httpd::Bandwidth::new(1024 * 1024 * (max_bandwidth + 1)); // 网络带宽控制
+    let httpd_jobs = Arc::new(Semaphore::new(max_parallel)); // 上传器并发控制
+
+    tracing::info!("Runtime initialized: baai-flagdataset-rs, upload");
+
+    let pb = pbar::create();
+
+    rt.spawn(bandwidth::reset_period(
+        Arc::clone(&httpd_bandwidth),
+        rt_token.clone(),
+    ));
+    rt.spawn(read::watch::init(
+        pb.clone(),
+        Arc::clone(&runtime),
+        None,
+        None,
+        rt_token.clone(),
+    ));
+
+    let spawn_up = rt.spawn(uploader::up(
+        Arc::clone(&httpd_bandwidth),
+        Arc::clone(&httpd_jobs),
+        Arc::clone(&client_up),
+        Arc::clone(&client_sign),
+        args,
+        upload.max_files,
+        rt_token.clone(),
+    ));
+
+    // 等待所以任务处理完成
+    let cancelled = rt.block_on(async move {
+        tokio::select! {
+            _ = spawn_up => {
+                tracing::info!("所有任务处理完成");
+                false
+            }
+            _ = signal::shutdown() => {
+                rt_token.cancel();
+                true
+            }
+        }
+    });
+    rt.shutdown_background();
+
+    let runtime = runtime.snapshot();
+
+    pb.set_length(runtime.require_count);
+    pb.set_position(runtime.completed_count + runtime.uncompleted_count);
+    let avg_speed = 1000 * runtime.completed_bytes as u128 / (start.elapsed().as_millis() + 1);
+    let process_bytes = runtime.completed_bytes + runtime.uncompleted_bytes;
+    pb.set_message(pbar::format(
+        runtime.require_bytes,
+        avg_speed as u64,
+        1.0,
+        process_bytes,
+        0,
+    ));
+
+    println!("{}", runtime);
+    println!("{:?}", start.elapsed());
+
+    if cancelled {
+        return Err(HttpdError::Cancelled);
+    }
+    Ok(runtime)
+}
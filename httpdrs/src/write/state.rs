@@ -0,0 +1,36 @@
+use std::sync::Arc;
+
+use httpdrs_core::httpd::{PathPolicy, RetryPolicy};
+
+use crate::read::state::RuntimeContext;
+
+/// Args 一次上传的参数和统计, 每次上传独立
+/// 上传和下载使用同样的统计结构, 但各自独立计数
+pub struct Args {
+    pub data_path: String,
+    pub temp_path: String,
+    pub path_policy: PathPolicy,      // 签名中的路径不安全时拒绝或去掉
+    pub retry: RetryPolicy,           // 分片和文件的重试策略
+    pub chunk_size: u64,              // 分片上传的分片大小, 文件太大时自动增大
+    pub runtime: Arc<RuntimeContext>, // 本次上传的统计
+}
+
+impl Args {
+    pub fn new(
+        data_path: String,
+        temp_path: String,
+        path_policy: PathPolicy,
+        retry: RetryPolicy,
+        chunk_size: u64,
+        runtime: Arc<RuntimeContext>,
+    ) -> Arc<Self> {
+        Arc::new(Args {
+            data_path,
+            temp_path,
+            path_policy,
+            retry,
+            chunk_size,
+            runtime,
+        })
+    }
+}
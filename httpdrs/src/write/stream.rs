@@ -0,0 +1,225 @@
+use std::future::Future;
+use std::io::SeekFrom;
+use std::path::Path;
+use std::sync::Arc;
+
+use futures::StreamExt;
+use reqwest::header::{CONTENT_LENGTH, ETAG};
+use reqwest::{Body, Client, StatusCode};
+use tokio::fs;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::time;
+use tokio_util::io::ReaderStream;
+
+use httpdrs_core::httpd::{Bandwidth, HttpdError, RetryPolicy};
+
+/// 每次从文件读取并申请带宽的大小
+const UPLOAD_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// 链接过期或被拒绝 (403) 时重新签名的次数上限, 不计入重试次数
+const MAX_REFRESHES: u32 = 3;
+
+/// stream_upload_retry 上传本地文件 [start_pos, start_pos + size), 失败时按 retry 重试
+/// presign 在第一次请求前和 403 时调用, 获取新的上传链接
+/// 返回值是 (ETag, 重试次数)
+pub async fn stream_upload_retry<F, Fut>(
+    client_up: Arc<Client>,
+    bandwidth: Arc<Bandwidth>,
+    retry: &RetryPolicy,
+    mut presign: F,
+    local_path: &Path,
+    start_pos: u64,
+    size: u64,
+) -> Result<(String, u32), HttpdError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<String, HttpdError>>,
+{
+    let mut presign_url = presign().await?;
+    let mut retry_count = 0;
+    let mut refresh_count = 0;
+    loop {
+        let err = match stream_upload_range(
+            Arc::clone(&client_up),
+            Arc::clone(&bandwidth),
+            &presign_url,
+            local_path,
+            start_pos,
+            size,
+        )
+        .await
+        {
+            Ok(etag) => return Ok((etag, retry_count)),
+            Err(err) if err.is_status(StatusCode::FORBIDDEN) && refresh_count < MAX_REFRESHES => {
+                // 链接过期或被拒绝, 重新签名后立即重试
+                refresh_count += 1;
+                presign_url = presign().await?;
+                continue;
+            }
+            Err(err) => err,
+        };
+
+        retry_count += 1;
+        if retry_count >= retry.range_attempts || !retry.is_retryable(&err) {
+            tracing::error!(
+                "upload_retry, attempt: {}, pos: {}-{}, local_path: {:?}, {}",
+                retry_count,
+                start_pos,
+                start_pos + size,
+                local_path,
+                err
+            );
+            return Err(err);
+        }
+        let delay = retry.backoff(retry_count);
+        tracing::warn!(
+            "upload_retry, attempt: {}/{}, delay: {:?}, pos: {}-{}, {}",
+            retry_count,
+            retry.range_attempts,
+            delay,
+            start_pos,
+            start_pos + size,
+            err
+        );
+        time::sleep(delay).await;
+    }
+}
+
+/// stream_upload_range 将本地文件 [start_pos, start_pos + size) PUT 到预签名地址
+/// 读取文件的同时按块申请带宽, 不会把整个分片读入内存
+/// 返回值是响应的 ETag, 分片上传完成时需要
+pub async fn stream_upload_range(
+    client_up: Arc<Client>,
+    bandwidth: Arc<Bandwidth>,
+    presign_url: &str,
+    local_path: &Path,
+    start_pos: u64,
+    size: u64,
+) -> Result<String, HttpdError> {
+    let mut file = match fs::File::open(local_path).await {
+        Ok(file) => file,
+        Err(err) => {
+            tracing::error!("upload_err, open {:?} err: {}", local_path, err);
+            return Err(err.into());
+        }
+    };
+    if let Err(err) = file.seek(SeekFrom::Start(start_pos)).await {
+        tracing::error!("upload_err, seek {:?} err: {}", local_path, err);
+        return Err(err.into());
+    }
+
+    let name = local_path.to_string_lossy().to_string();
+    let body_stream =
+        ReaderStream::with_capacity(file.take(size), UPLOAD_BUFFER_SIZE).then(move |chunk| {
+            let bandwidth = Arc::clone(&bandwidth);
+            let name = name.clone();
+            async move {
+                if let Ok(bytes) = &chunk {
+                    let _ = bandwidth.permit(bytes.len() as u64, name).await;
+                }
+                chunk
+            }
+        });
+
+    let rs_send = client_up
+        .put(presign_url)
+        .header(CONTENT_LENGTH, size)
+        .body(Body::wrap_stream(body_stream))
+        .send()
+        .await;
+    let resp = match rs_send {
+        Ok(resp) => resp,
+        Err(err) => {
+            tracing::error!("stream_upload, reqwest err: {}", err);
+            return Err(err.into());
+        }
+    };
+
+    if !resp.status().is_success() {
+        tracing::error!("stream_upload, reqwest status: {}", resp.status());
+        return Err(HttpdError::status(resp.status(), ""));
+    }
+
+    let etag = resp
+        .headers()
+        .get(ETAG)
+        .and_then(|etag| etag.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    Ok(etag)
+}
+
+/// stream_initiate_multipart 创建分片上传, 返回 UploadId
+pub async fn stream_initiate_multipart(
+    client_up: Arc<Client>,
+    presign_url: &str,
+) -> Result<String, HttpdError> {
+    let body = stream_post(client_up, presign_url, String::new()).await?;
+    match xml_value(&body, "UploadId") {
+        Some(upload_id) if !upload_id.is_empty() => Ok(upload_id.to_string()),
+        _ => {
+            tracing::error!("stream_initiate, UploadId not found: {}", body);
+            Err(HttpdError::status(StatusCode::OK, "UploadId not found"))
+        }
+    }
+}
+
+/// stream_complete_multipart 提交所有分片的 (part_number, etag) 合并文件
+pub async fn stream_complete_multipart(
+    client_up: Arc<Client>,
+    presign_url: &str,
+    parts: &[(u64, String)],
+) -> Result<(), HttpdError> {
+    let mut body = String::from("<CompleteMultipartUpload>");
+    for (part_number, etag) in parts {
+        body.push_str(&format!(
+            "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+            part_number, etag
+        ));
+    }
+    body.push_str("</CompleteMultipartUpload>");
+
+    let resp_body = stream_post(client_up, presign_url, body).await?;
+    // complete 即使返回 200, 也可能在响应体中带有错误
+    if resp_body.contains("<Error>") {
+        tracing::error!("stream_complete, err: {}", resp_body);
+        return Err(HttpdError::status(StatusCode::OK, resp_body));
+    }
+    Ok(())
+}
+
+async fn stream_post(
+    client_up: Arc<Client>,
+    presign_url: &str,
+    body: String,
+) -> Result<String, HttpdError> {
+    let resp = match client_up.post(presign_url).body(body).send().await {
+        Ok(resp) => resp,
+        Err(err) => {
+            tracing::error!("stream_post, reqwest err: {}", err);
+            return Err(err.into());
+        }
+    };
+
+    let status = resp.status();
+    let text = match resp.text().await {
+        Ok(text) => text,
+        Err(err) => {
+            tracing::error!("stream_post, reqwest read body err: {}", err);
+            return Err(err.into());
+        }
+    };
+    if !status.is_success() {
+        tracing::error!("stream_post, reqwest status: {}, body: {}", status, text);
+        return Err(HttpdError::status(status, text));
+    }
+    Ok(text)
+}
+
+fn xml_value<'a>(body: &'a str, tag: &str) -> Option<&'a str> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let start = body.find(&open)? + open.len();
+    let end = body[start..].find(&close)? + start;
+    Some(body[start..end].trim())
+}
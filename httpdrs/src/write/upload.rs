@@ -0,0 +1,138 @@
+use std::sync::Arc;
+
+use reqwest::Client;
+use tokio::sync::Semaphore;
+use tokio::time::Instant;
+
+use httpdrs_core::httpd;
+use httpdrs_core::httpd::{Bandwidth, HttpdError, Signer};
+use httpdrs_core::request;
+use httpdrs_core::write::presign;
+
+use crate::write::state::Args;
+use crate::write::{multipart, stream};
+
+pub async fn upload_file(
+    bandwidth: Arc<Bandwidth>,
+    jobs: Arc<Semaphore>,
+    client_up: Arc<Client>,
+    client_sign: Arc<dyn Signer>,
+    args: Arc<Args>,
+    request_writer: Arc<request::FSWriter>,
+) -> Result<(String, tokio::time::Duration), HttpdError> {
+    let start = Instant::now();
+    let sign = request_writer.request_sign.clone();
+    let require_size = request_writer.require_size;
+
+    let runtime = &args.runtime;
+
+    let writer_ref = match client_sign.parse_write(&sign) {
+        Ok(writer_ref) => writer_ref,
+        Err(err) => {
+            tracing::error!("upload_err, writer_parse err: {}", err);
+            runtime.add_uncompleted(1, require_size);
+            return Err(err);
+        }
+    };
+    let writer_ref = match writer_ref.safe_path(args.path_policy) {
+        Ok(writer_ref) => writer_ref,
+        Err(err) => {
+            tracing::error!("upload_err, {}", err);
+            runtime.add_rejected(1, require_size);
+            return Err(err.into());
+        }
+    };
+    let local_path = writer_ref.local_absolute_path_str(args.data_path.as_str());
+
+    // 本地文件必须存在并且大小和清单一致
+    let local_size = httpd::check_file_meta(local_path.clone()).await;
+    if local_size != Some(require_size) {
+        tracing::warn!(
+            "upload_check, require_size: {}, local_size: {:?}, local_path: {:?}",
+            require_size,
+            local_size,
+            local_path
+        );
+        runtime.add_uncompleted(1, require_size);
+        return Err(HttpdError::Verify(format!(
+            "require_size: {}, local_size: {:?}",
+            require_size, local_size
+        )));
+    }
+
+    // 大文件使用分片上传
+    if request_writer.total_parts() > 1 {
+        let state_path =
+            writer_ref.local_upload_path(args.data_path.as_str(), args.temp_path.as_str());
+        let uploaded = multipart::upload_multipart(
+            bandwidth,
+            jobs,
+            client_up,
+            client_sign,
+            Arc::clone(&args),
+            request_writer,
+            local_path.clone(),
+            state_path,
+        )
+        .await;
+
+        // 分片的字节数已经统计, 这里只统计文件数量
+        if let Err(err) = uploaded {
+            runtime.add_uncompleted(1, 0);
+            return Err(err);
+        }
+        runtime.add_completed(1, 0);
+        tracing::info!(
+            "upload_file, use: {:?}, multipart, local_path: {:?}",
+            start.elapsed(),
+            local_path
+        );
+        return Ok((
+            writer_ref
+                .local_relative_path()
+                .to_string_lossy()
+                .to_string(),
+            start.elapsed(),
+        ));
+    }
+
+    // 上传并发控制, 获取许可后再签名, 避免链接在等待时过期
+    let Ok(_permit) = jobs.acquire().await else {
+        runtime.add_uncompleted(1, require_size);
+        return Err(HttpdError::Cancelled);
+    };
+
+    let uploaded = stream::stream_upload_retry(
+        client_up,
+        bandwidth,
+        &args.retry,
+        || presign::write(sign.clone(), Arc::clone(&client_sign)),
+        &local_path,
+        0,
+        require_size,
+    )
+    .await;
+    let retry_count = match uploaded {
+        Ok((_, retry_count)) => retry_count,
+        Err(err) => {
+            runtime.add_uncompleted(1, require_size);
+            return Err(err);
+        }
+    };
+
+    runtime.add_completed(1, require_size);
+    tracing::info!(
+        "upload_file, use: {:?}, retry: {}, local_path: {:?}",
+        start.elapsed(),
+        retry_count,
+        local_path
+    );
+
+    Ok((
+        writer_ref
+            .local_relative_path()
+            .to_string_lossy()
+            .to_string(),
+        start.elapsed(),
+    ))
+}
@@ -0,0 +1,105 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use csv::Reader;
+use reqwest::Client;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+
+use httpdrs_core::httpd::{Bandwidth, Signer};
+use httpdrs_core::request;
+
+use crate::write::multipart;
+use crate::write::state::Args;
+use crate::write::upload::upload_file;
+
+// 上传流程
+pub(crate) async fn up(
+    bandwidth: Arc<Bandwidth>,
+    jobs: Arc<Semaphore>,
+    client_up: Arc<Client>,
+    client_sign: Arc<dyn Signer>,
+    args: Arc<Args>,
+    max_files: usize,
+    cancel: CancellationToken,
+) {
+    let meta_path = args.runtime.meta_path.read().await.clone();
+
+    // 上传清单: meta_path 下所有的 *.bin, 格式和下载清单一致
+    let mut meta_list: Vec<PathBuf> = match std::fs::read_dir(meta_path.as_str()) {
+        Ok(paths) => paths
+            .filter_map(|path| path.ok().map(|path| path.path()))
+            .filter(|path| path.extension().is_some_and(|extn| extn == "bin"))
+            .collect(),
+        Err(err) => {
+            tracing::error!("upload_meta, read dir: {} {}", meta_path, err);
+            return;
+        }
+    };
+    meta_list.sort();
+
+    // 文件上传并发控制, 同时作为读取清单的背压
+    let semaphore = Arc::new(Semaphore::new(max_files));
+    let mut upload_tasks = JoinSet::new();
+
+    for csv_meta_path in meta_list {
+        let mut csv_reader = match Reader::from_path(&csv_meta_path) {
+            Ok(csv_reader) => csv_reader,
+            Err(err) => {
+                tracing::error!("read csv: {:?} {}", csv_meta_path, err);
+                continue;
+            }
+        };
+
+        for raw_result in csv_reader.records() {
+            if cancel.is_cancelled() {
+                break;
+            }
+
+            let raw_line = match raw_result {
+                Ok(raw_line) => raw_line,
+                Err(err) => {
+                    tracing::error!("read csv: {:?} {}", csv_meta_path, err);
+                    continue;
+                }
+            };
+            let sign = raw_line.get(0).unwrap_or_default().to_string();
+            let size = raw_line
+                .get(1)
+                .and_then(|size| size.parse::<u64>().ok())
+                .unwrap_or_default();
+            args.runtime.add_require(1, size);
+
+            let bandwidth_ = Arc::clone(&bandwidth);
+            let jobs_ = Arc::clone(&jobs);
+            let client_up_ = Arc::clone(&client_up);
+            let client_sign_ = Arc::clone(&client_sign);
+            let args_ = Arc::clone(&args);
+
+            let request_writer =
+                request::FSWriter::new(sign, size, multipart::chunk_size(size, args.chunk_size));
+
+            let permit = Arc::clone(&semaphore).acquire_owned().await.unwrap();
+            upload_tasks.spawn(async move {
+                let _permit = permit; // 最大并发上传文件数量
+                let request_sign = request_writer.request_sign.clone();
+                if let Err(err) = upload_file(
+                    bandwidth_,
+                    jobs_,
+                    client_up_,
+                    client_sign_,
+                    args_,
+                    request_writer,
+                )
+                .await
+                {
+                    tracing::error!("upload_file, {}, sign: {}", err.kind(), request_sign);
+                }
+            });
+        }
+    }
+
+    while upload_tasks.join_next().await.is_some() {}
+    tracing::info!("upload_meta: tasks completed");
+}
@@ -1 +1,6 @@
 from . import read
+from . import write
+from . import aio
+from .aio import download
+from ._ihttpd import (HttpdError, PresignError, TokenError, PathError, NetworkError, HttpStatusError, IoError,
+                      VerifyError, CancelledError)
@@ -1,8 +1,192 @@
-def multi_read(use_loc: str, presign_api: str, network: str, max_bandwidth: int, max_parallel: int):
+from typing import Callable
+
+
+def multi_read(use_loc: str, presign_api: str | None = None, network: str | None = None,
+               max_bandwidth: int | None = None, max_parallel: int | None = None, preallocate: bool | None = None,
+               signer: str | None = None, access_key: str | None = None, secret_key: str | None = None,
+               region: str | None = None, endpoint: str | None = None, virtual_host: bool | None = None,
+               jwt_secret: str | None = None, jwks_path: str | None = None,
+               path_policy: str | None = None, range_attempts: int | None = None,
+               file_attempts: int | None = None, retry_status: str | None = None,
+               progress: Callable[[Progress], None] | None = None):
     pass
 
 
-def push_read(name): ...
+def push_read(name: str): ...
+
+
+def push_row(sign: str, size: int, extn: str = "", path: str | None = None, md5: str | None = None,
+             sha256: str | None = None, crc32c: str | None = None): ...
+
+
+def close_read(): ...
+
+
+def wait_read() -> Stats | None: ...
+
+
+def stats_read() -> Stats | None: ...
+
+
+class Stats:
+    require_count: int
+    require_bytes: int
+    download_count: int
+    download_bytes: int
+    completed_count: int
+    completed_bytes: int
+    uncompleted_count: int
+    uncompleted_bytes: int
+    rejected_count: int
+    summary: str
+
+    def to_dict(self) -> dict[str, int]: ...
+
+
+class Progress:
+    stats: Stats
+    speed: int
+    eta: int
+    percent: float
+    elapsed: float
+    parallel: int
+
+
+class FileResult:
+    path: str
+    size: int
+    status: str
+    attempts: int
+    error: str | None
+    message: str | None
+    duration_ms: int
+
+
+class Session:
+    def __init__(self, use_loc: str, presign_api: str | None = None, network: str | None = None,
+                 max_bandwidth: int | None = None, max_parallel: int | None = None,
+                 preallocate: bool | None = None, signer: str | None = None, access_key: str | None = None,
+                 secret_key: str | None = None, region: str | None = None, endpoint: str | None = None,
+                 virtual_host: bool | None = None, jwt_secret: str | None = None, jwks_path: str | None = None,
+                 path_policy: str | None = None, range_attempts: int | None = None,
+                 file_attempts: int | None = None, retry_status: str | None = None,
+                 progress: Callable[[Progress], None] | None = None): ...
+
+    def start(self): ...
+
+    def push(self, name: str): ...
+
+    def push_row(self, sign: str, size: int, extn: str = "", path: str | None = None, md5: str | None = None,
+                 sha256: str | None = None, crc32c: str | None = None): ...
+
+    def close(self): ...
+
+    def wait(self) -> Stats: ...
+
+    async def wait_async(self) -> Stats: ...
+
+    async def download_row(self, sign: str, size: int, extn: str = "", path: str | None = None,
+                           md5: str | None = None, sha256: str | None = None,
+                           crc32c: str | None = None) -> FileResult: ...
+
+    def cancel(self): ...
+
+    def stats(self) -> Stats: ...
+
+
+class ManifestEntry:
+    path: str
+    sign: str
+    size: int
+    md5: str | None
+    sha256: str | None
+    crc32c: str | None
+
+
+class ObjectReader:
+    block_size: int
+
+    def __init__(self, presign_api: str | None = None, network: str | None = None, max_parallel: int = 16,
+                 block_size: int | None = None, cache_path: str | None = None, max_bandwidth: int | None = None,
+                 path_policy: str | None = None, signer: str | None = None, access_key: str | None = None,
+                 secret_key: str | None = None, region: str | None = None, endpoint: str | None = None,
+                 virtual_host: bool | None = None, jwt_secret: str | None = None, jwks_path: str | None = None,
+                 range_attempts: int | None = None, file_attempts: int | None = None,
+                 retry_status: str | None = None): ...
+
+    def size(self, sign: str) -> int: ...
+
+    def read_bytes(self, sign: str, size: int | None = None, md5: str | None = None, sha256: str | None = None,
+                   crc32c: str | None = None) -> bytes: ...
+
+    def read_range(self, sign: str, offset: int, length: int, size: int | None = None) -> bytes: ...
+
+    def get(self, sign: str, local_path: str, size: int | None = None, md5: str | None = None,
+            sha256: str | None = None, crc32c: str | None = None) -> int: ...
+
+    def manifest(self, meta_path: str) -> list[ManifestEntry]: ...
+
+    def open(self, sign: str, size: int | None = None) -> RemoteFile: ...
+
+
+class RemoteFile:
+    size: int
+    closed: bool
+
+    def read(self, size: int = -1) -> bytes: ...
+
+    def seek(self, offset: int, whence: int = 0) -> int: ...
+
+    def tell(self) -> int: ...
+
+    def close(self): ...
+
+    def readable(self) -> bool: ...
+
+    def seekable(self) -> bool: ...
+
+    def writable(self) -> bool: ...
+
+    def __enter__(self) -> RemoteFile: ...
+
+    def __exit__(self, exc_type, exc_value, traceback) -> bool: ...
+
+
+def multi_write(use_loc: str, presign_api: str | None = None, network: str | None = None,
+                max_bandwidth: int | None = None, max_parallel: int | None = None,
+                signer: str | None = None, access_key: str | None = None, secret_key: str | None = None,
+                region: str | None = None, endpoint: str | None = None, virtual_host: bool | None = None,
+                jwt_secret: str | None = None, jwks_path: str | None = None,
+                path_policy: str | None = None, range_attempts: int | None = None,
+                file_attempts: int | None = None, retry_status: str | None = None):
+    pass
+
+
+def wait_write(): ...
+
+
+class HttpdError(Exception): ...
+
+
+class PresignError(HttpdError): ...
+
+
+class TokenError(HttpdError): ...
+
+
+class PathError(HttpdError): ...
+
+
+class NetworkError(HttpdError): ...
+
+
+class HttpStatusError(HttpdError): ...
+
+
+class IoError(HttpdError): ...
+
+
+class VerifyError(HttpdError): ...
 
 
-def wait_read(): ...
+class CancelledError(HttpdError): ...
@@ -0,0 +1,19 @@
+from collections.abc import Iterable
+
+from ._ihttpd import Session, Stats, FileResult
+
+
+__all__ = ["download", "Session", "Stats", "FileResult"]
+
+
+async def download(use_loc: str, presign_api: str | None = None, network: str | None = None,
+                   max_bandwidth: int | None = None, max_parallel: int | None = None,
+                   metas: Iterable[str] = (), **kwargs) -> Stats:
+    """下载 {use_loc}/meta 下的清单, 在当前事件循环中等待完成; 任务被取消时停止下载
+    为 None 的参数使用 IHTTPD_* 环境变量, {use_loc}/ihttpd.toml 或 ~/.config/ihttpd/config.toml"""
+    session = Session(use_loc, presign_api, network, max_bandwidth, max_parallel, **kwargs)
+    session.start()
+    for name in metas:
+        session.push(name)
+    session.close()
+    return await session.wait_async()
@@ -1,4 +1,5 @@
 import argparse
+import sys
 
 
 def with_cmdargs():
@@ -9,20 +10,36 @@ def with_cmdargs():
     subparsers = parser.add_subparsers(dest='command')
 
     init_parser = subparsers.add_parser('init', help='init', parents=[root_parser])
-    init_parser.add_argument('--network', type=str, default="private", help='network')
-    init_parser.add_argument('--bandwidth', type=int, default="100", help='bandwidth')
-    init_parser.add_argument('--parallel', type=int, default="200", help='parallel')
+    # 未指定的参数使用 IHTTPD_* 环境变量, ./ihttpd.toml 或 ~/.config/ihttpd/config.toml
+    init_parser.add_argument('--presign-api', type=str, default=None, help='presign api, signer http')
+    init_parser.add_argument('--network', type=str, default=None, help='network, default private')
+    init_parser.add_argument('--bandwidth', type=int, default=None, help='bandwidth, default 100')
+    init_parser.add_argument('--parallel', type=int, default=None, help='parallel, default 200')
+    init_parser.add_argument('--preallocate', action='store_true', default=None, help='preallocate, write ranges into data directly')
+    init_parser.add_argument('--signer', type=str, default=None, choices=["http", "s3", "passthrough"], help='signer, default http')
+    init_parser.add_argument('--s3-access-key', type=str, default=None, help='s3 access key, signer s3')
+    init_parser.add_argument('--s3-secret-key', type=str, default=None, help='s3 secret key, signer s3')
+    init_parser.add_argument('--s3-region', type=str, default=None, help='s3 region, signer s3, default us-east-1')
+    init_parser.add_argument('--s3-endpoint', type=str, default=None, help='s3 endpoint, signer s3')
+    init_parser.add_argument('--s3-virtual-host', action='store_true', default=None, help='s3 virtual-hosted style, signer s3')
+    init_parser.add_argument('--jwt-secret', type=str, default=None, help='verify sign with hmac secret')
+    init_parser.add_argument('--jwks-path', type=str, default=None, help='verify sign with jwks file')
+    init_parser.add_argument('--path-policy', type=str, default=None, choices=["reject", "sanitize"], help='unsafe local path policy, default reject')
+    init_parser.add_argument('--range-attempts', type=int, default=None, help='max attempts per range and presign request')
+    init_parser.add_argument('--file-attempts', type=int, default=None, help='max attempts per file, failed ranges are requeued')
+    init_parser.add_argument('--retry-status', type=str, default=None, help='retryable http status, e.g. 5xx,408,429')
     init_parser.set_defaults(func=init_with_cmdargs)
 
     cmd_args = parser.parse_args()
     if hasattr(cmd_args, 'func'):
         try:
             cmd_args.func(cmd_args)
-        except Exception: # noqa
-            pass
         except KeyboardInterrupt:
             print()
-            pass
+            sys.exit(130)
+        except Exception as e: # noqa
+            print(f"ihttpd: {e}", file=sys.stderr)
+            sys.exit(1)
     else:
         parser.print_help()
 
@@ -30,33 +47,35 @@ def with_cmdargs():
 def init_with_cmdargs(cmd_args):
     import pathlib
 
-    try:
-        from ..helper import figlet
-        from .. import read as httpdrs
-
-
-        figlet.print_figlet()
-
-        use_path = pathlib.Path("").absolute().__str__()
-        presign = "http://internal-data.baai.ac.cn/api/v1/storage/sign/download/presign"
-        network = cmd_args.network
-        bandwidth = cmd_args.bandwidth
-        parallel = cmd_args.parallel
-
-        print(f"ihttpd: use_path, {use_path}")
-        print(f"ihttpd: presign, {presign}")
-        print(f"ihttpd: network, {network}")
-        print(f"ihttpd: bandwidth, {bandwidth}")
-        print(f"ihttpd: parallel, {parallel}")
-
-        httpdrs.multi_download(use_path, presign, network,bandwidth,  parallel)
-
-        httpdrs.push("---start---")
-        for meta_bin in (pathlib.Path("").absolute() / "meta").glob("*.bin"):
-            httpdrs.push(meta_bin.name)
-        httpdrs.push("---end---")
-
-        httpdrs.wait()
-
-    except Exception as e:
-        print(e)
+    from ..helper import figlet
+    from .. import read as httpdrs
+
+
+    figlet.print_figlet()
+
+    use_path = pathlib.Path("").absolute().__str__()
+    print(f"ihttpd: use_path, {use_path}")
+    for name in ["presign_api", "network", "bandwidth", "parallel", "preallocate", "signer"]:
+        if getattr(cmd_args, name) is not None:
+            print(f"ihttpd: {name}, {getattr(cmd_args, name)}")
+
+    httpdrs.multi_download(use_path, cmd_args.presign_api, cmd_args.network, cmd_args.bandwidth, cmd_args.parallel,
+                           preallocate=cmd_args.preallocate,
+                           signer=cmd_args.signer,
+                           access_key=cmd_args.s3_access_key,
+                           secret_key=cmd_args.s3_secret_key,
+                           region=cmd_args.s3_region,
+                           endpoint=cmd_args.s3_endpoint,
+                           virtual_host=cmd_args.s3_virtual_host,
+                           jwt_secret=cmd_args.jwt_secret,
+                           jwks_path=cmd_args.jwks_path,
+                           path_policy=cmd_args.path_policy,
+                           range_attempts=cmd_args.range_attempts,
+                           file_attempts=cmd_args.file_attempts,
+                           retry_status=cmd_args.retry_status)
+
+    for meta_bin in (pathlib.Path("").absolute() / "meta").glob("*.bin"):
+        httpdrs.push(meta_bin.name)
+    httpdrs.close()
+
+    httpdrs.wait()
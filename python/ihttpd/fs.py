@@ -0,0 +1,128 @@
+"""fsspec 文件系统: 把清单当作目录, 通过 ihttpd 的分块读取访问文件
+
+    fs = IhttpdFileSystem(presign_api, "public", use_loc="/data/AIM-500")
+    fs.ls("")
+    pd.read_parquet("ihttpd://dir/part-0.parquet", storage_options={...})
+
+安装后注册为 ihttpd:// 协议, 只支持读取
+"""
+import os
+import posixpath
+from collections.abc import Iterable
+
+from fsspec import AbstractFileSystem
+from fsspec.callbacks import DEFAULT_CALLBACK
+from fsspec.spec import AbstractBufferedFile
+
+from ._ihttpd import ObjectReader, ManifestEntry
+
+
+__all__ = ["IhttpdFileSystem", "IhttpdFile"]
+
+
+class IhttpdFileSystem(AbstractFileSystem):
+    protocol = "ihttpd"
+    root_marker = ""
+
+    def __init__(self, presign_api: str | None = None, network: str | None = None, manifests: Iterable[str] = (),
+                 use_loc: str | None = None, **kwargs):
+        """manifests 是清单文件路径, use_loc 不为空时加载 {use_loc}/meta 下的所有清单
+        其他参数传给 ObjectReader: max_parallel, max_bandwidth, block_size, cache_path, signer ...
+        """
+        super().__init__(presign_api, network, manifests=manifests, use_loc=use_loc, **kwargs)
+        self._reader = ObjectReader(presign_api, network, **kwargs)
+
+        paths = list(manifests)
+        if use_loc is not None:
+            meta_path = os.path.join(use_loc, "meta")
+            paths.extend(os.path.join(meta_path, name) for name in sorted(os.listdir(meta_path)))
+
+        # 文件和目录的索引, 同一路径后加载的清单覆盖前面的
+        self._files: dict[str, ManifestEntry] = {}
+        self._dirs: dict[str, dict[str, dict]] = {"": {}}
+        for path in paths:
+            for entry in self._reader.manifest(path):
+                self._files[entry.path] = entry
+        for path, entry in self._files.items():
+            self._add_entry(path, {"name": path, "size": entry.size, "type": "file", "md5": entry.md5})
+
+    def _add_entry(self, path: str, info: dict):
+        parent = posixpath.dirname(path)
+        self._dirs.setdefault(parent, {})[path] = info
+        while parent and parent not in self._dirs.get(posixpath.dirname(parent), {}):
+            grand = posixpath.dirname(parent)
+            self._dirs.setdefault(grand, {})[parent] = {"name": parent, "size": 0, "type": "directory"}
+            parent = grand
+
+    def _entry(self, path: str) -> ManifestEntry:
+        path = self._strip_protocol(path)
+        entry = self._files.get(path)
+        if entry is None:
+            raise FileNotFoundError(path)
+        return entry
+
+    def ls(self, path, detail=True, **kwargs):
+        path = self._strip_protocol(path)
+        if path in self._files:
+            infos = [self.info(path)]
+        elif path in self._dirs:
+            infos = sorted(self._dirs[path].values(), key=lambda info: info["name"])
+        else:
+            raise FileNotFoundError(path)
+        return infos if detail else [info["name"] for info in infos]
+
+    def info(self, path, **kwargs):
+        path = self._strip_protocol(path)
+        entry = self._files.get(path)
+        if entry is not None:
+            return {"name": path, "size": entry.size, "type": "file", "md5": entry.md5}
+        if path in self._dirs:
+            return {"name": path, "size": 0, "type": "directory"}
+        raise FileNotFoundError(path)
+
+    def _open(self, path, mode="rb", block_size=None, autocommit=True, cache_options=None, **kwargs):
+        if mode != "rb":
+            raise NotImplementedError("ihttpd filesystem is read-only")
+        entry = self._entry(path)
+        return IhttpdFile(self, self._strip_protocol(path), mode, block_size=block_size or self._reader.block_size,
+                          cache_options=cache_options, size=entry.size, **kwargs)
+
+    def cat_file(self, path, start=None, end=None, **kwargs):
+        entry = self._entry(path)
+        if start is None and end is None:
+            # 整个文件时按清单中的摘要校验
+            return self._reader.read_bytes(entry.sign, entry.size, md5=entry.md5, sha256=entry.sha256,
+                                           crc32c=entry.crc32c)
+        start = 0 if start is None else start
+        end = entry.size if end is None else end
+        if start < 0:
+            start = max(entry.size + start, 0)
+        if end < 0:
+            end = entry.size + end
+        if end <= start:
+            return b""
+        return self._reader.read_range(entry.sign, start, end - start, entry.size)
+
+    def get_file(self, rpath, lpath, callback=DEFAULT_CALLBACK, outfile=None, **kwargs):
+        if self.isdir(rpath):
+            os.makedirs(lpath, exist_ok=True)
+            return
+        entry = self._entry(rpath)
+        callback.set_size(entry.size)
+        if outfile is not None:
+            outfile.write(self.cat_file(rpath))
+        else:
+            self._reader.get(entry.sign, lpath, entry.size, md5=entry.md5, sha256=entry.sha256,
+                             crc32c=entry.crc32c)
+        callback.relative_update(entry.size)
+
+    def _rm(self, path):
+        raise NotImplementedError("ihttpd filesystem is read-only")
+
+
+class IhttpdFile(AbstractBufferedFile):
+    """IhttpdFile 按需请求分块, 一次读取跨多个分块时并发请求"""
+
+    def _fetch_range(self, start, end):
+        entry = self.fs._entry(self.path)
+        return self.fs._reader.read_range(entry.sign, start, end - start, entry.size)
@@ -1,7 +1,11 @@
-from ._ihttpd import multi_read, push_read, wait_read
+import functools
 
+from ._ihttpd import (multi_read, push_read, push_row, close_read, wait_read, stats_read, Session, Stats, Progress,
+                      ObjectReader, RemoteFile, ManifestEntry)
 
-__all__ = ["multi_read", "push_read", "wait_read"]
+
+__all__ = ["multi_read", "push_read", "push_row", "close_read", "wait_read", "stats_read", "Session", "Stats",
+           "Progress", "ObjectReader", "RemoteFile", "ManifestEntry"]
 
 
 def multi_download(*args, **kwargs):
@@ -9,8 +13,46 @@ def multi_download(*args, **kwargs):
 
 
 def push(name: str):
+    # 兼容旧的标记: ---start--- 忽略, ---end--- 结束提交
+    if name == "---start---":
+        return
+    if name == "---end---":
+        close_read()
+        return
     push_read(name)
 
 
-def wait():
-    wait_read()
+def push_file(sign: str, size: int, **kwargs):
+    push_row(sign, size, **kwargs)
+
+
+def close():
+    close_read()
+
+
+def wait() -> Stats | None:
+    return wait_read()
+
+
+def stats() -> Stats | None:
+    return stats_read()
+
+
+def reader(presign_api: str | None = None, network: str | None = None, **kwargs) -> ObjectReader:
+    # 同一个 reader 可以在多个线程中复用, 共享签名缓存和连接
+    return ObjectReader(presign_api, network, **kwargs)
+
+
+@functools.lru_cache(maxsize=8)
+def _cached_reader(presign_api: str | None, network: str | None, **kwargs) -> ObjectReader:
+    # read_bytes/open 按参数复用 reader, 不用每次调用都重新创建连接和签名缓存
+    return ObjectReader(presign_api, network, **kwargs)
+
+
+def read_bytes(sign: str, presign_api: str | None = None, network: str | None = None, size: int | None = None,
+               md5: str | None = None, sha256: str | None = None, crc32c: str | None = None, **kwargs) -> bytes:
+    return _cached_reader(presign_api, network, **kwargs).read_bytes(sign, size, md5=md5, sha256=sha256, crc32c=crc32c)
+
+
+def open(sign: str, presign_api: str | None = None, network: str | None = None, size: int | None = None, **kwargs) -> RemoteFile:
+    return _cached_reader(presign_api, network, **kwargs).open(sign, size)
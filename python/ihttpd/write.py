@@ -0,0 +1,12 @@
+from ._ihttpd import multi_write, wait_write
+
+
+__all__ = ["multi_write", "wait_write"]
+
+
+def multi_upload(*args, **kwargs):
+    multi_write(*args, **kwargs)
+
+
+def wait():
+    wait_write()
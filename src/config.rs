@@ -0,0 +1,9 @@
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use httpdrs::config::{Config, ConfigLayer};
+
+/// load_config 合并参数, IHTTPD_* 环境变量和配置文件, 参数为 None 的项使用下一层
+pub(crate) fn load_config(args: ConfigLayer) -> PyResult<Config> {
+    Config::load(args, None).map_err(PyValueError::new_err)
+}
@@ -0,0 +1,46 @@
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+
+use httpdrs::core::httpd;
+
+// HttpdError 是所有异常的基类, 按 kind 区分子类
+pyo3::create_exception!(_ihttpd, HttpdError, PyException);
+pyo3::create_exception!(_ihttpd, PresignError, HttpdError);
+pyo3::create_exception!(_ihttpd, TokenError, HttpdError);
+pyo3::create_exception!(_ihttpd, PathError, HttpdError);
+pyo3::create_exception!(_ihttpd, NetworkError, HttpdError);
+pyo3::create_exception!(_ihttpd, HttpStatusError, HttpdError);
+pyo3::create_exception!(_ihttpd, IoError, HttpdError);
+pyo3::create_exception!(_ihttpd, VerifyError, HttpdError);
+pyo3::create_exception!(_ihttpd, CancelledError, HttpdError);
+
+/// to_pyerr 转换为对应的 Python 异常
+pub(crate) fn to_pyerr(err: httpd::HttpdError) -> PyErr {
+    let message = err.to_string();
+    match err {
+        httpd::HttpdError::Presign(_) | httpd::HttpdError::PresignUnavailable(_) => {
+            PresignError::new_err(message)
+        }
+        httpd::HttpdError::Token(_) => TokenError::new_err(message),
+        httpd::HttpdError::Path(_) => PathError::new_err(message),
+        httpd::HttpdError::Network(_) => NetworkError::new_err(message),
+        httpd::HttpdError::Status { .. } => HttpStatusError::new_err(message),
+        httpd::HttpdError::Io(_) => IoError::new_err(message),
+        httpd::HttpdError::Verify(_) => VerifyError::new_err(message),
+        httpd::HttpdError::Cancelled => CancelledError::new_err(message),
+    }
+}
+
+pub(crate) fn register(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    let py = m.py();
+    m.add("HttpdError", py.get_type::<HttpdError>())?;
+    m.add("PresignError", py.get_type::<PresignError>())?;
+    m.add("TokenError", py.get_type::<TokenError>())?;
+    m.add("PathError", py.get_type::<PathError>())?;
+    m.add("NetworkError", py.get_type::<NetworkError>())?;
+    m.add("HttpStatusError", py.get_type::<HttpStatusError>())?;
+    m.add("IoError", py.get_type::<IoError>())?;
+    m.add("VerifyError", py.get_type::<VerifyError>())?;
+    m.add("CancelledError", py.get_type::<CancelledError>())?;
+    Ok(())
+}
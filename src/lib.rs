@@ -1,5 +1,10 @@
+mod config;
+mod error;
+mod object;
 mod read;
 mod state;
+mod stats;
+mod write;
 
 use pyo3::prelude::*;
 
@@ -9,6 +14,15 @@ use pyo3::prelude::*;
 fn ihttpd(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(read::multi_read, m)?)?;
     m.add_function(wrap_pyfunction!(read::push_read, m)?)?;
+    m.add_function(wrap_pyfunction!(read::push_row, m)?)?;
+    m.add_function(wrap_pyfunction!(read::close_read, m)?)?;
     m.add_function(wrap_pyfunction!(read::wait_read, m)?)?;
+    m.add_function(wrap_pyfunction!(read::stats_read, m)?)?;
+    m.add_class::<read::DownloadSession>()?;
+    m.add_function(wrap_pyfunction!(write::multi_write, m)?)?;
+    m.add_function(wrap_pyfunction!(write::wait_write, m)?)?;
+    object::register(m)?;
+    stats::register(m)?;
+    error::register(m)?;
     Ok(())
 }
@@ -0,0 +1,299 @@
+use std::io::{Seek, SeekFrom};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use pyo3::exceptions::{PyOSError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use crate::config;
+use crate::error;
+use httpdrs::config::ConfigLayer;
+use httpdrs::core::verify::FileDigest;
+use httpdrs::read::object::{self, ObjectConfig};
+
+/// ManifestEntry 清单中的一个文件, path 和下载到 data 目录时的相对路径相同
+#[pyclass(name = "ManifestEntry", module = "_ihttpd", frozen, get_all)]
+#[derive(Debug, Clone)]
+pub struct ManifestEntry {
+    path: String,
+    sign: String,
+    size: u64,
+    md5: Option<String>,
+    sha256: Option<String>,
+    crc32c: Option<String>,
+}
+
+impl From<object::ManifestEntry> for ManifestEntry {
+    fn from(entry: object::ManifestEntry) -> Self {
+        ManifestEntry {
+            path: entry.path,
+            sign: entry.sign,
+            size: entry.size,
+            md5: entry.digest.md5,
+            sha256: entry.digest.sha256,
+            crc32c: entry.digest.crc32c,
+        }
+    }
+}
+
+#[pymethods]
+impl ManifestEntry {
+    fn __repr__(&self) -> String {
+        format!("ManifestEntry(path={:?}, size={})", self.path, self.size)
+    }
+}
+
+/// ObjectReader 直接读取对象到内存, 不写入 data 目录
+/// 签名参数和 multi_read 相同, cache_path 不为空时按块缓存到本地
+#[pyclass(name = "ObjectReader", module = "_ihttpd", frozen)]
+pub struct ObjectReader {
+    reader: Arc<object::ObjectReader>,
+}
+
+#[pymethods]
+impl ObjectReader {
+    #[new]
+    #[pyo3(signature = (presign_api=None, network=None, max_parallel=16, block_size=None, cache_path=None, max_bandwidth=None, path_policy=None, signer=None, access_key=None, secret_key=None, region=None, endpoint=None, virtual_host=None, jwt_secret=None, jwks_path=None, range_attempts=None, file_attempts=None, retry_status=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        presign_api: Option<String>,
+        network: Option<String>,
+        max_parallel: u64,
+        block_size: Option<u64>,
+        cache_path: Option<String>,
+        max_bandwidth: Option<u64>,
+        path_policy: Option<String>,
+        signer: Option<String>,
+        access_key: Option<String>,
+        secret_key: Option<String>,
+        region: Option<String>,
+        endpoint: Option<String>,
+        virtual_host: Option<bool>,
+        jwt_secret: Option<String>,
+        jwks_path: Option<String>,
+        range_attempts: Option<u32>,
+        file_attempts: Option<u32>,
+        retry_status: Option<String>,
+    ) -> PyResult<Self> {
+        // max_parallel 和 max_bandwidth 只使用参数, 和下载的默认值不同
+        let config = config::load_config(ConfigLayer {
+            presign_api,
+            network,
+            signer,
+            s3_access_key: access_key,
+            s3_secret_key: secret_key,
+            s3_region: region,
+            s3_endpoint: endpoint,
+            s3_virtual_host: virtual_host,
+            jwt_secret,
+            jwks_path,
+            path_policy,
+            range_attempts,
+            file_attempts,
+            retry_status,
+            ..Default::default()
+        })?;
+        let (signer, verifier) = config.signer_config().map_err(PyValueError::new_err)?;
+
+        let reader = object::ObjectReader::new(ObjectConfig {
+            signer,
+            verifier,
+            retry: config.retry,
+            max_parallel: max_parallel as usize,
+            block_size: block_size.unwrap_or(config.download.chunk_size),
+            cache_path,
+            max_bandwidth,
+            path_policy: config.path_policy,
+            client: config.download.client,
+        })
+        .map_err(error::to_pyerr)?;
+        Ok(ObjectReader { reader })
+    }
+
+    /// size 对象的大小
+    fn size(&self, py: Python<'_>, sign: String) -> PyResult<u64> {
+        py.detach(|| self.reader.size(&sign))
+            .map_err(error::to_pyerr)
+    }
+
+    /// read_bytes 读取整个对象, 提供摘要时校验, 读取时释放 GIL
+    #[pyo3(signature = (sign, size=None, md5=None, sha256=None, crc32c=None))]
+    fn read_bytes<'py>(
+        &self,
+        py: Python<'py>,
+        sign: String,
+        size: Option<u64>,
+        md5: Option<String>,
+        sha256: Option<String>,
+        crc32c: Option<String>,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let digest = FileDigest {
+            md5,
+            sha256,
+            crc32c,
+        };
+        let data = py
+            .detach(|| self.reader.read_bytes(&sign, size, &digest))
+            .map_err(error::to_pyerr)?;
+        Ok(PyBytes::new(py, &data))
+    }
+
+    /// read_range 读取 [offset, offset + length), 超出对象大小的部分忽略
+    #[pyo3(signature = (sign, offset, length, size=None))]
+    fn read_range<'py>(
+        &self,
+        py: Python<'py>,
+        sign: String,
+        offset: u64,
+        length: u64,
+        size: Option<u64>,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let data = py
+            .detach(|| self.reader.read_range(&sign, size, offset, length))
+            .map_err(error::to_pyerr)?;
+        Ok(PyBytes::new(py, &data))
+    }
+
+    /// get 保存到本地文件, 提供摘要时校验, 返回文件大小
+    #[pyo3(signature = (sign, local_path, size=None, md5=None, sha256=None, crc32c=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn get(
+        &self,
+        py: Python<'_>,
+        sign: String,
+        local_path: PathBuf,
+        size: Option<u64>,
+        md5: Option<String>,
+        sha256: Option<String>,
+        crc32c: Option<String>,
+    ) -> PyResult<u64> {
+        let digest = FileDigest {
+            md5,
+            sha256,
+            crc32c,
+        };
+        py.detach(|| self.reader.read_to_file(&sign, size, &local_path, &digest))
+            .map_err(error::to_pyerr)
+    }
+
+    /// manifest 读取清单中的文件, 签名无效或路径不安全的行跳过
+    fn manifest(&self, py: Python<'_>, meta_path: String) -> PyResult<Vec<ManifestEntry>> {
+        let entries = py
+            .detach(|| self.reader.manifest(&meta_path))
+            .map_err(error::to_pyerr)?;
+        Ok(entries.into_iter().map(ManifestEntry::from).collect())
+    }
+
+    #[getter]
+    fn block_size(&self) -> u64 {
+        self.reader.block_size()
+    }
+
+    /// open 打开可以 seek 的对象, 读取时才请求覆盖的分块
+    #[pyo3(signature = (sign, size=None))]
+    fn open(&self, py: Python<'_>, sign: String, size: Option<u64>) -> PyResult<RemoteFile> {
+        let file = py
+            .detach(|| self.reader.open(&sign, size))
+            .map_err(error::to_pyerr)?;
+        Ok(RemoteFile { file: Some(file) })
+    }
+}
+
+/// RemoteFile 只读的二进制文件对象, 支持 read/seek/tell 和 with
+#[pyclass(name = "RemoteFile", module = "_ihttpd")]
+pub struct RemoteFile {
+    file: Option<object::RemoteFile>,
+}
+
+impl RemoteFile {
+    fn opened(&mut self) -> PyResult<&mut object::RemoteFile> {
+        self.file
+            .as_mut()
+            .ok_or_else(|| PyValueError::new_err("I/O operation on closed file"))
+    }
+}
+
+#[pymethods]
+impl RemoteFile {
+    /// read 从当前位置读取最多 size 字节, size 为负数时读取到末尾
+    #[pyo3(signature = (size=-1))]
+    fn read<'py>(&mut self, py: Python<'py>, size: i64) -> PyResult<Bound<'py, PyBytes>> {
+        let file = self.opened()?;
+        let len = u64::try_from(size).unwrap_or(u64::MAX);
+        let data = py.detach(|| file.read_next(len)).map_err(error::to_pyerr)?;
+        Ok(PyBytes::new(py, &data))
+    }
+
+    /// seek whence: 0 文件开头, 1 当前位置, 2 文件末尾
+    #[pyo3(signature = (offset, whence=0))]
+    fn seek(&mut self, offset: i64, whence: i32) -> PyResult<u64> {
+        let pos = match whence {
+            0 => SeekFrom::Start(u64::try_from(offset).map_err(|_| {
+                PyValueError::new_err(format!("negative seek position {}", offset))
+            })?),
+            1 => SeekFrom::Current(offset),
+            2 => SeekFrom::End(offset),
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "invalid whence ({})",
+                    whence
+                )))
+            }
+        };
+        self.opened()?
+            .seek(pos)
+            .map_err(|err| PyOSError::new_err(err.to_string()))
+    }
+
+    fn tell(&mut self) -> PyResult<u64> {
+        Ok(self.opened()?.position())
+    }
+
+    #[getter]
+    fn size(&mut self) -> PyResult<u64> {
+        Ok(self.opened()?.size())
+    }
+
+    #[getter]
+    fn closed(&self) -> bool {
+        self.file.is_none()
+    }
+
+    fn close(&mut self) {
+        self.file = None;
+    }
+
+    fn readable(&self) -> bool {
+        true
+    }
+
+    fn seekable(&self) -> bool {
+        true
+    }
+
+    fn writable(&self) -> bool {
+        false
+    }
+
+    fn __enter__(slf: Py<Self>) -> Py<Self> {
+        slf
+    }
+
+    fn __exit__(
+        &mut self,
+        _exc_type: Py<PyAny>,
+        _exc_value: Py<PyAny>,
+        _traceback: Py<PyAny>,
+    ) -> bool {
+        self.close();
+        false
+    }
+}
+
+pub(crate) fn register(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<ManifestEntry>()?;
+    m.add_class::<ObjectReader>()?;
+    m.add_class::<RemoteFile>()?;
+    Ok(())
+}
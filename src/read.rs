@@ -1,56 +1,385 @@
-use std::sync::Arc;
-use std::thread;
+use std::sync::{Arc, Mutex, OnceLock};
 
+use futures::future::{select, Either};
+use futures::pin_mut;
+use pyo3::coroutine::CancelHandle;
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
 use pyo3::prelude::*;
 
+use crate::config;
+use crate::error;
 use crate::state;
+use crate::stats::{self, FileReport, Stats};
+use httpdrs::config::ConfigLayer;
+use httpdrs::core::read::reader::MetaRow;
+use httpdrs::core::verify::FileDigest;
 use httpdrs::prelude::*;
-use httpdrs::read::state::DATA;
+use httpdrs::read::queue::Job;
+use httpdrs::read::session::{Session, SessionConfig};
 
-#[pyfunction]
-pub fn multi_read(
+/// session_config 解析 multi_read 和 Session 的参数
+#[allow(clippy::too_many_arguments)]
+fn session_config(
     use_loc: String,
-    presign_api: String,
-    network: String,
-    max_bandwidth: u64,
-    max_parallel: u64,
-) -> PyResult<()> {
-    let handle = thread::spawn(move || {
-        logger::try_logger_init(format!("{}/logs", use_loc).as_str());
+    presign_api: Option<String>,
+    network: Option<String>,
+    max_bandwidth: Option<u64>,
+    max_parallel: Option<u64>,
+    preallocate: Option<bool>,
+    signer: Option<String>,
+    access_key: Option<String>,
+    secret_key: Option<String>,
+    region: Option<String>,
+    endpoint: Option<String>,
+    virtual_host: Option<bool>,
+    jwt_secret: Option<String>,
+    jwks_path: Option<String>,
+    path_policy: Option<String>,
+    range_attempts: Option<u32>,
+    file_attempts: Option<u32>,
+    retry_status: Option<String>,
+    progress: Option<Py<PyAny>>,
+) -> PyResult<SessionConfig> {
+    let config = config::load_config(ConfigLayer {
+        use_loc: Some(use_loc),
+        presign_api,
+        network,
+        max_bandwidth,
+        max_parallel,
+        preallocate,
+        signer,
+        s3_access_key: access_key,
+        s3_secret_key: secret_key,
+        s3_region: region,
+        s3_endpoint: endpoint,
+        s3_virtual_host: virtual_host,
+        jwt_secret,
+        jwks_path,
+        path_policy,
+        range_attempts,
+        file_attempts,
+        retry_status,
+        ..Default::default()
+    })?;
+    SessionConfig::from_config(&config, progress.map(stats::progress_callback))
+        .map_err(PyValueError::new_err)
+}
 
-        runtime::start_multi_thread(
-            max_bandwidth,
-            max_parallel as usize,
+fn start_session(config: SessionConfig) -> Session {
+    logger::try_logger_init(format!("{}/logs", config.use_loc).as_str());
+    Session::start(config)
+}
+
+fn meta_row(
+    sign: String,
+    size: u64,
+    extn: String,
+    path: Option<String>,
+    md5: Option<String>,
+    sha256: Option<String>,
+    crc32c: Option<String>,
+) -> MetaRow {
+    MetaRow {
+        sign,
+        path,
+        size,
+        extn,
+        digest: FileDigest {
+            md5,
+            sha256,
+            crc32c,
+        },
+    }
+}
+
+fn push_job(session: &Session, job: Job) -> PyResult<()> {
+    session
+        .push(job)
+        .map_err(|err| PyRuntimeError::new_err(err.to_string()))
+}
+
+/// Session 一次下载, 同一进程中可以同时或先后运行多个
+/// start 后通过 push/push_row 提交任务, close 结束提交, wait 等待完成
+#[pyclass(name = "Session", module = "_ihttpd")]
+pub struct DownloadSession {
+    config: Mutex<Option<SessionConfig>>,
+    session: OnceLock<Arc<Session>>,
+}
+
+impl DownloadSession {
+    fn started(&self) -> PyResult<&Arc<Session>> {
+        self.session
+            .get()
+            .ok_or_else(|| PyRuntimeError::new_err("session is not started"))
+    }
+}
+
+#[pymethods]
+impl DownloadSession {
+    #[new]
+    #[pyo3(signature = (use_loc, presign_api=None, network=None, max_bandwidth=None, max_parallel=None, preallocate=None, signer=None, access_key=None, secret_key=None, region=None, endpoint=None, virtual_host=None, jwt_secret=None, jwks_path=None, path_policy=None, range_attempts=None, file_attempts=None, retry_status=None, progress=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        use_loc: String,
+        presign_api: Option<String>,
+        network: Option<String>,
+        max_bandwidth: Option<u64>,
+        max_parallel: Option<u64>,
+        preallocate: Option<bool>,
+        signer: Option<String>,
+        access_key: Option<String>,
+        secret_key: Option<String>,
+        region: Option<String>,
+        endpoint: Option<String>,
+        virtual_host: Option<bool>,
+        jwt_secret: Option<String>,
+        jwks_path: Option<String>,
+        path_policy: Option<String>,
+        range_attempts: Option<u32>,
+        file_attempts: Option<u32>,
+        retry_status: Option<String>,
+        progress: Option<Py<PyAny>>,
+    ) -> PyResult<Self> {
+        let config = session_config(
             use_loc,
             presign_api,
             network,
-        )
-        .expect("start multi thread runtime err");
-    });
+            max_bandwidth,
+            max_parallel,
+            preallocate,
+            signer,
+            access_key,
+            secret_key,
+            region,
+            endpoint,
+            virtual_host,
+            jwt_secret,
+            jwks_path,
+            path_policy,
+            range_attempts,
+            file_attempts,
+            retry_status,
+            progress,
+        )?;
+        Ok(DownloadSession {
+            config: Mutex::new(Some(config)),
+            session: OnceLock::new(),
+        })
+    }
 
-    let manager = state::manager();
-    let mut guard = manager.lock().unwrap();
-    *guard = Some(handle);
+    fn start(&self) -> PyResult<()> {
+        let Some(config) = self.config.lock().unwrap().take() else {
+            return Err(PyRuntimeError::new_err("session is already started"));
+        };
+        let _ = self.session.set(Arc::new(start_session(config)));
+        Ok(())
+    }
 
-    Ok(())
+    /// push 提交 {use_loc}/meta 下的清单文件
+    fn push(&self, name: String) -> PyResult<()> {
+        push_job(self.started()?, Job::Meta(name))
+    }
+
+    #[pyo3(signature = (sign, size, extn="".to_string(), path=None, md5=None, sha256=None, crc32c=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn push_row(
+        &self,
+        sign: String,
+        size: u64,
+        extn: String,
+        path: Option<String>,
+        md5: Option<String>,
+        sha256: Option<String>,
+        crc32c: Option<String>,
+    ) -> PyResult<()> {
+        let job = Job::Row(meta_row(sign, size, extn, path, md5, sha256, crc32c));
+        push_job(self.started()?, job)
+    }
+
+    fn close(&self) -> PyResult<()> {
+        self.started()?.close();
+        Ok(())
+    }
+
+    /// wait 等待下载结束并返回统计, 等待时释放 GIL
+    fn wait(&self, py: Python<'_>) -> PyResult<Stats> {
+        let session = self.started()?;
+        py.detach(|| session.wait()).map_err(error::to_pyerr)?;
+        Ok(Stats::from(session.stats()))
+    }
+
+    /// wait_async 在 asyncio 中等待下载结束, 等待被取消时停止下载
+    /// 取消后等待已经收到的结果写入报告, 再抛出 CancelledError
+    async fn wait_async(&self, #[pyo3(cancel_handle)] mut cancel: CancelHandle) -> PyResult<Stats> {
+        let session = Arc::clone(self.started()?);
+        let finished = session.wait_async();
+        let cancelled = cancel.cancelled();
+        pin_mut!(finished, cancelled);
+        let cancelled = match select(finished, cancelled).await {
+            Either::Left(_) => None,
+            Either::Right((err, finished)) => {
+                session.cancel();
+                let _ = finished.await;
+                Some(err)
+            }
+        };
+        // 下载线程唤醒事件循环后才退出, 释放 GIL 等待线程结束, 避免解释器退出时线程仍在调用 Python
+        let result = Python::attach(|py| py.detach(|| session.wait()));
+        if let Some(err) = cancelled {
+            return Err(Python::attach(|py| PyErr::from_value(err.into_bound(py))));
+        }
+        result.map_err(error::to_pyerr)?;
+        Ok(Stats::from(session.stats()))
+    }
+
+    /// download_row 提交单个文件并在 asyncio 中等待它的结果
+    /// 等待被取消时不影响这个文件和其他文件的下载
+    #[pyo3(signature = (sign, size, extn="".to_string(), path=None, md5=None, sha256=None, crc32c=None))]
+    #[allow(clippy::too_many_arguments)]
+    async fn download_row(
+        &self,
+        sign: String,
+        size: u64,
+        extn: String,
+        path: Option<String>,
+        md5: Option<String>,
+        sha256: Option<String>,
+        crc32c: Option<String>,
+    ) -> PyResult<FileReport> {
+        let meta_row = meta_row(sign, size, extn, path, md5, sha256, crc32c);
+        let file_result = self
+            .started()?
+            .push_row_wait(meta_row)
+            .map_err(|err| PyRuntimeError::new_err(err.to_string()))?;
+        let file_result = file_result.await.map_err(error::to_pyerr)?;
+        Ok(FileReport::from(file_result))
+    }
+
+    fn cancel(&self) -> PyResult<()> {
+        self.started()?.cancel();
+        Ok(())
+    }
+
+    /// stats 当前的统计, 不会阻塞
+    fn stats(&self) -> PyResult<Stats> {
+        Ok(Stats::from(self.started()?.stats()))
+    }
 }
 
 #[pyfunction]
-pub fn wait_read() -> PyResult<()> {
-    let manager = state::manager();
-    let mut guard = manager.lock().unwrap();
-    if let Some(handle) = guard.take() {
-        handle.join().map_err(|e| {
-            PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("Thread panicked: {:?}", e))
-        })?;
+#[pyo3(signature = (use_loc, presign_api=None, network=None, max_bandwidth=None, max_parallel=None, preallocate=None, signer=None, access_key=None, secret_key=None, region=None, endpoint=None, virtual_host=None, jwt_secret=None, jwks_path=None, path_policy=None, range_attempts=None, file_attempts=None, retry_status=None, progress=None))]
+#[allow(clippy::too_many_arguments)]
+pub fn multi_read(
+    use_loc: String,
+    presign_api: Option<String>,
+    network: Option<String>,
+    max_bandwidth: Option<u64>,
+    max_parallel: Option<u64>,
+    preallocate: Option<bool>,
+    signer: Option<String>,
+    access_key: Option<String>,
+    secret_key: Option<String>,
+    region: Option<String>,
+    endpoint: Option<String>,
+    virtual_host: Option<bool>,
+    jwt_secret: Option<String>,
+    jwks_path: Option<String>,
+    path_policy: Option<String>,
+    range_attempts: Option<u32>,
+    file_attempts: Option<u32>,
+    retry_status: Option<String>,
+    progress: Option<Py<PyAny>>,
+) -> PyResult<()> {
+    let config = session_config(
+        use_loc,
+        presign_api,
+        network,
+        max_bandwidth,
+        max_parallel,
+        preallocate,
+        signer,
+        access_key,
+        secret_key,
+        region,
+        endpoint,
+        virtual_host,
+        jwt_secret,
+        jwks_path,
+        path_policy,
+        range_attempts,
+        file_attempts,
+        retry_status,
+        progress,
+    )?;
+
+    // 上一次的 Session 结束提交, 之后提交的任务进入新的 Session
+    let session = Arc::new(start_session(config));
+    if let Some(previous) = state::session().lock().unwrap().replace(session) {
+        previous.close();
     }
+
     Ok(())
 }
 
+/// current 最近一次 multi_read 创建的 Session
+fn current() -> PyResult<Arc<Session>> {
+    state::session()
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or_else(|| PyRuntimeError::new_err("multi_read is not started"))
+}
+
+/// wait_read 等待下载结束并返回统计
+/// 结束后 stats_read 仍然返回这次的统计, 直到下一次 multi_read
+#[pyfunction]
+pub fn wait_read(py: Python<'_>) -> PyResult<Option<Stats>> {
+    let Some(session) = state::session().lock().unwrap().clone() else {
+        return Ok(None);
+    };
+    py.detach(|| session.wait()).map_err(error::to_pyerr)?;
+    Ok(Some(Stats::from(session.stats())))
+}
+
+/// stats_read 最近一次 multi_read 的统计, 不会阻塞, 没有开始下载时返回 None
+#[pyfunction]
+pub fn stats_read() -> Option<Stats> {
+    state::session()
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|session| Stats::from(session.stats()))
+}
+
+/// push_read 提交 {use_loc}/meta 下的清单文件, 同一个清单只下载一次
 #[pyfunction]
 pub fn push_read(name: String) -> PyResult<()> {
-    let current_data = DATA.load().clone();
-    let new_string = format!("{}\n{}", current_data, name);
-    DATA.store(Arc::new(new_string.trim().to_string()));
+    let session = current()?;
+    push_job(&session, Job::Meta(name))
+}
+
+/// push_row 提交单个文件, 参数和清单中的一行相同, path 不为空时 sign 是下载链接
+#[pyfunction]
+#[pyo3(signature = (sign, size, extn="".to_string(), path=None, md5=None, sha256=None, crc32c=None))]
+pub fn push_row(
+    sign: String,
+    size: u64,
+    extn: String,
+    path: Option<String>,
+    md5: Option<String>,
+    sha256: Option<String>,
+    crc32c: Option<String>,
+) -> PyResult<()> {
+    let session = current()?;
+    let job = Job::Row(meta_row(sign, size, extn, path, md5, sha256, crc32c));
+    push_job(&session, job)
+}
+
+/// close_read 结束提交, 已经提交的任务完成后 wait_read 返回
+#[pyfunction]
+pub fn close_read() -> PyResult<()> {
+    if let Some(session) = state::session().lock().unwrap().as_ref() {
+        session.close();
+    }
     Ok(())
 }
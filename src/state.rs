@@ -1,8 +1,21 @@
-use std::sync::{Mutex, OnceLock};
+use std::sync::{Arc, Mutex, OnceLock};
 use std::thread;
 
-static THREAD: OnceLock<Mutex<Option<thread::JoinHandle<()>>>> = OnceLock::new();
+use httpdrs::core::httpd::HttpdError;
+use httpdrs::read::session::Session;
 
-pub(crate) fn manager() -> &'static Mutex<Option<thread::JoinHandle<()>>> {
-    THREAD.get_or_init(|| Mutex::new(None))
+/// 后台运行的线程, 结束时返回运行结果
+type RuntimeHandle = thread::JoinHandle<Result<(), HttpdError>>;
+
+/// multi_read 创建的 Session, push_read/push_row/close_read/wait_read 使用
+static SESSION: OnceLock<Mutex<Option<Arc<Session>>>> = OnceLock::new();
+
+static WRITE_THREAD: OnceLock<Mutex<Option<RuntimeHandle>>> = OnceLock::new();
+
+pub(crate) fn session() -> &'static Mutex<Option<Arc<Session>>> {
+    SESSION.get_or_init(|| Mutex::new(None))
+}
+
+pub(crate) fn write_manager() -> &'static Mutex<Option<RuntimeHandle>> {
+    WRITE_THREAD.get_or_init(|| Mutex::new(None))
 }
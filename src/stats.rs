@@ -0,0 +1,158 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use httpdrs::read::report::FileResult;
+use httpdrs::read::state::{Progress, ProgressCallback, RuntimeSnapshot};
+
+/// Stats 下载统计, 和 RuntimeSnapshot 相同
+/// download_* 是本地已经存在跳过的文件, uncompleted_* 是失败的文件
+#[pyclass(name = "Stats", module = "_ihttpd", frozen, get_all)]
+#[derive(Debug, Clone)]
+pub struct Stats {
+    require_count: u64,
+    require_bytes: u64,
+    download_count: u64,
+    download_bytes: u64,
+    completed_count: u64,
+    completed_bytes: u64,
+    uncompleted_count: u64,
+    uncompleted_bytes: u64,
+    rejected_count: u64,
+    summary: String,
+}
+
+impl From<RuntimeSnapshot> for Stats {
+    fn from(stats: RuntimeSnapshot) -> Self {
+        Stats {
+            require_count: stats.require_count,
+            require_bytes: stats.require_bytes,
+            download_count: stats.download_count,
+            download_bytes: stats.download_bytes,
+            completed_count: stats.completed_count,
+            completed_bytes: stats.completed_bytes,
+            uncompleted_count: stats.uncompleted_count,
+            uncompleted_bytes: stats.uncompleted_bytes,
+            rejected_count: stats.rejected_count,
+            summary: stats.to_string(),
+        }
+    }
+}
+
+#[pymethods]
+impl Stats {
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new(py);
+        dict.set_item("require_count", self.require_count)?;
+        dict.set_item("require_bytes", self.require_bytes)?;
+        dict.set_item("download_count", self.download_count)?;
+        dict.set_item("download_bytes", self.download_bytes)?;
+        dict.set_item("completed_count", self.completed_count)?;
+        dict.set_item("completed_bytes", self.completed_bytes)?;
+        dict.set_item("uncompleted_count", self.uncompleted_count)?;
+        dict.set_item("uncompleted_bytes", self.uncompleted_bytes)?;
+        dict.set_item("rejected_count", self.rejected_count)?;
+        Ok(dict)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Stats({})", self.summary)
+    }
+}
+
+/// ProgressInfo 传给进度回调的参数
+#[pyclass(name = "Progress", module = "_ihttpd", frozen, get_all)]
+#[derive(Debug, Clone)]
+pub struct ProgressInfo {
+    stats: Stats,
+    speed: u64,      // bytes/s
+    eta: u64,        // 秒
+    percent: f64,    // 0.0 ~ 1.0
+    elapsed: f64,    // 秒
+    parallel: usize, // 当前的分片并发
+}
+
+impl From<&Progress> for ProgressInfo {
+    fn from(progress: &Progress) -> Self {
+        ProgressInfo {
+            stats: Stats::from(progress.stats.clone()),
+            speed: progress.speed,
+            eta: progress.eta,
+            percent: progress.percent,
+            elapsed: progress.elapsed.as_secs_f64(),
+            parallel: progress.parallel,
+        }
+    }
+}
+
+#[pymethods]
+impl ProgressInfo {
+    fn __repr__(&self) -> String {
+        format!(
+            "Progress(percent={:.3}, speed={}, eta={}, parallel={}, {})",
+            self.percent, self.speed, self.eta, self.parallel, self.stats.summary
+        )
+    }
+}
+
+/// FileReport 单个文件的处理结果, 和 report.jsonl 中的一行相同
+#[pyclass(name = "FileResult", module = "_ihttpd", frozen, get_all)]
+#[derive(Debug, Clone)]
+pub struct FileReport {
+    path: String,
+    size: u64,
+    status: &'static str, // completed, skipped, failed, rejected
+    attempts: u32,
+    error: Option<&'static str>,
+    message: Option<String>,
+    duration_ms: u64,
+}
+
+impl From<FileResult> for FileReport {
+    fn from(result: FileResult) -> Self {
+        FileReport {
+            path: result.path,
+            size: result.size,
+            status: result.status.as_str(),
+            attempts: result.attempts,
+            error: result.error,
+            message: result.message,
+            duration_ms: result.duration_ms,
+        }
+    }
+}
+
+#[pymethods]
+impl FileReport {
+    fn __repr__(&self) -> String {
+        format!(
+            "FileResult(path={:?}, status={}, attempts={})",
+            self.path, self.status, self.attempts
+        )
+    }
+}
+
+/// progress_callback 在下载线程中获取 GIL 后调用 Python 回调
+/// 回调抛出的异常不影响下载, 只打印第一次
+pub(crate) fn progress_callback(callback: Py<PyAny>) -> ProgressCallback {
+    let printed = AtomicBool::new(false);
+    Arc::new(move |progress: &Progress| {
+        Python::attach(|py| {
+            if let Err(err) = callback.call1(py, (ProgressInfo::from(progress),)) {
+                tracing::warn!("download_progress, callback err: {}", err);
+                if !printed.swap(true, Ordering::Relaxed) {
+                    err.print(py);
+                }
+            }
+        })
+    })
+}
+
+pub(crate) fn register(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Stats>()?;
+    m.add_class::<ProgressInfo>()?;
+    m.add_class::<FileReport>()?;
+    Ok(())
+}
@@ -0,0 +1,96 @@
+use std::thread;
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use crate::config;
+use crate::error;
+use crate::state;
+use httpdrs::config::ConfigLayer;
+use httpdrs::prelude::*;
+use httpdrs::write::runtime as write_runtime;
+
+#[pyfunction]
+#[pyo3(signature = (use_loc, presign_api=None, network=None, max_bandwidth=None, max_parallel=None, signer=None, access_key=None, secret_key=None, region=None, endpoint=None, virtual_host=None, jwt_secret=None, jwks_path=None, path_policy=None, range_attempts=None, file_attempts=None, retry_status=None))]
+#[allow(clippy::too_many_arguments)]
+pub fn multi_write(
+    use_loc: String,
+    presign_api: Option<String>,
+    network: Option<String>,
+    max_bandwidth: Option<u64>,
+    max_parallel: Option<u64>,
+    signer: Option<String>,
+    access_key: Option<String>,
+    secret_key: Option<String>,
+    region: Option<String>,
+    endpoint: Option<String>,
+    virtual_host: Option<bool>,
+    jwt_secret: Option<String>,
+    jwks_path: Option<String>,
+    path_policy: Option<String>,
+    range_attempts: Option<u32>,
+    file_attempts: Option<u32>,
+    retry_status: Option<String>,
+) -> PyResult<()> {
+    let config = config::load_config(ConfigLayer {
+        use_loc: Some(use_loc),
+        presign_api,
+        network,
+        max_bandwidth,
+        max_parallel,
+        signer,
+        s3_access_key: access_key,
+        s3_secret_key: secret_key,
+        s3_region: region,
+        s3_endpoint: endpoint,
+        s3_virtual_host: virtual_host,
+        jwt_secret,
+        jwks_path,
+        path_policy,
+        range_attempts,
+        file_attempts,
+        retry_status,
+        ..Default::default()
+    })?;
+    let (signer, verifier) = config.signer_config().map_err(PyValueError::new_err)?;
+
+    let handle = thread::spawn(move || {
+        logger::try_logger_init(format!("{}/logs", config.use_loc).as_str());
+
+        write_runtime::start_multi_thread(
+            config.max_bandwidth,
+            config.max_parallel,
+            config.use_loc,
+            signer,
+            verifier,
+            config.path_policy,
+            config.retry,
+            config.upload,
+        )
+        .map(|_| ())
+    });
+
+    let manager = state::write_manager();
+    let mut guard = manager.lock().unwrap();
+    *guard = Some(handle);
+
+    Ok(())
+}
+
+#[pyfunction]
+pub fn wait_write() -> PyResult<()> {
+    let manager = state::write_manager();
+    let mut guard = manager.lock().unwrap();
+    if let Some(handle) = guard.take() {
+        handle
+            .join()
+            .map_err(|e| {
+                PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!(
+                    "Thread panicked: {:?}",
+                    e
+                ))
+            })?
+            .map_err(error::to_pyerr)?;
+    }
+    Ok(())
+}